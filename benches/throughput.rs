@@ -1,6 +1,8 @@
 use criterion::{criterion_group, criterion_main, Criterion, Throughput};
-use rusty_reckoning::{run, run_async};
-use std::io;
+use rusty_reckoning::{read_csv_into_iter, run, run_async, BinaryWriter, Transaction};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::runtime::Runtime;
 
@@ -17,7 +19,39 @@ impl io::Write for NoopWriter {
     }
 }
 
+/// Converts the CSV input file to the binary format in a temporary directory.
+fn convert_to_binary(csv_path: impl AsRef<Path>) -> PathBuf {
+    let binary_path = std::env::temp_dir().join("rusty_reckoning_10K_clients.bin");
+    let transactions = read_csv_into_iter::<Transaction, _>(csv_path).unwrap();
+    let mut writer =
+        BinaryWriter::new(BufWriter::new(File::create(&binary_path).unwrap())).unwrap();
+    for transaction in transactions {
+        writer.write_transaction(&transaction.unwrap()).unwrap();
+    }
+    writer.flush().unwrap();
+    binary_path
+}
+
+/// Writes gzip and zstd compressed copies of the CSV input file to a temporary directory.
+fn compress(csv_path: impl AsRef<Path>) -> (PathBuf, PathBuf) {
+    let input = std::fs::read(csv_path).unwrap();
+    let gzip_path = std::env::temp_dir().join("rusty_reckoning_10K_clients.csv.gz");
+    let mut encoder = flate2::write::GzEncoder::new(
+        File::create(&gzip_path).unwrap(),
+        flate2::Compression::default(),
+    );
+    encoder.write_all(&input).unwrap();
+    encoder.finish().unwrap();
+
+    let zstd_path = std::env::temp_dir().join("rusty_reckoning_10K_clients.csv.zst");
+    zstd::stream::copy_encode(&input[..], File::create(&zstd_path).unwrap(), 0).unwrap();
+    (gzip_path, zstd_path)
+}
+
 fn process_transactions(c: &mut Criterion) {
+    let binary_path = convert_to_binary("data/10K_clients.csv");
+    let (gzip_path, zstd_path) = compress("data/10K_clients.csv");
+
     let mut group = c.benchmark_group("throughput");
 
     group.throughput(Throughput::Elements(1_000_000)); // 1M transactions in the input file
@@ -36,6 +70,30 @@ fn process_transactions(c: &mut Criterion) {
             .iter(|| async { run_async("data/10K_clients.csv", NoopWriter).await.unwrap() });
     });
 
+    group.bench_function("sync_process_10K_clients_1M_transactions_binary", |b| {
+        b.iter(|| {
+            run(&binary_path, NoopWriter).unwrap();
+        });
+    });
+
+    group.bench_function("sync_process_10K_clients_1M_transactions_gzip", |b| {
+        b.iter(|| {
+            run(&gzip_path, NoopWriter).unwrap();
+        });
+    });
+
+    group.bench_function("sync_process_10K_clients_1M_transactions_zstd", |b| {
+        b.iter(|| {
+            run(&zstd_path, NoopWriter).unwrap();
+        });
+    });
+
+    group.bench_function("async_process_10K_clients_1M_transactions_zstd", |b| {
+        let rt = Runtime::new().unwrap();
+        b.to_async(rt)
+            .iter(|| async { run_async(&zstd_path, NoopWriter).await.unwrap() });
+    });
+
     group.finish();
 }
 
@@ -2,7 +2,10 @@ use std::env;
 use std::error::Error;
 use std::process;
 
-use rusty_reckoning::run_async;
+use rusty_reckoning::{run_async_with_config, CsvDialect, ParseMode, RunConfig, SnapshotConfig};
+
+const USAGE: &str =
+    "Usage: cargo run --example async_main -- [--format csv|ndjson|binary] [--compression none|gzip|zstd] [--lenient] [--max-errors N] [--rejects rejects.csv] [--outcomes outcomes.csv] [--deltas deltas.ndjson] [--precision N] [--rounding to-zero|half-even|half-up] [--strict-precision] [--delimiter C] [--quote C] [--header-alias name=field] [--columns type,client,tx,amount] [--ignore-type-case] [--snapshot-dir DIR] [--snapshot-every N] [--snapshot-on-marker] [--snapshot-mode full|changed] transactions.csv";
 
 #[tokio::main]
 async fn main() {
@@ -13,10 +16,104 @@ async fn main() {
 }
 
 async fn run_app() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        return Err("Usage: cargo run -- transactions.csv".into());
+    let mut config = RunConfig::default();
+    let mut input_path = None;
+    let mut lenient = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let format = args.next().ok_or(USAGE)?;
+                config.format = Some(format.parse()?);
+            }
+            "--compression" => {
+                let compression = args.next().ok_or(USAGE)?;
+                config.compression = Some(compression.parse()?);
+            }
+            "--lenient" => lenient = true,
+            "--max-errors" => {
+                let max_errors = args.next().ok_or(USAGE)?;
+                config.parse_mode = ParseMode::Lenient {
+                    max_errors: Some(max_errors.parse()?),
+                };
+            }
+            "--rejects" => {
+                let rejects_path = args.next().ok_or(USAGE)?;
+                config.rejects_path = Some(rejects_path.into());
+                lenient = true;
+            }
+            "--outcomes" => {
+                let outcomes_path = args.next().ok_or(USAGE)?;
+                config.outcomes_path = Some(outcomes_path.into());
+            }
+            "--deltas" => {
+                let deltas_path = args.next().ok_or(USAGE)?;
+                config.deltas_path = Some(deltas_path.into());
+            }
+            "--precision" => {
+                let scale = args.next().ok_or(USAGE)?;
+                config.engine.precision.scale = scale.parse()?;
+            }
+            "--rounding" => {
+                let rounding = args.next().ok_or(USAGE)?;
+                config.engine.precision.rounding = rounding.parse()?;
+            }
+            "--strict-precision" => config.engine.precision.strict = true,
+            "--delimiter" => {
+                let delimiter = args.next().ok_or(USAGE)?;
+                config.csv.delimiter = CsvDialect::parse_char(&delimiter)?;
+            }
+            "--quote" => {
+                let quote = args.next().ok_or(USAGE)?;
+                config.csv.quote = CsvDialect::parse_char(&quote)?;
+            }
+            "--header-alias" => {
+                let alias = args.next().ok_or(USAGE)?;
+                let (name, field) = alias.split_once('=').ok_or(USAGE)?;
+                config.csv.header_aliases.insert(name, field)?;
+            }
+            "--columns" => {
+                let columns = args.next().ok_or(USAGE)?;
+                config.csv.columns = Some(columns.split(',').map(String::from).collect());
+            }
+            "--ignore-type-case" => config.csv.case_insensitive_types = true,
+            "--snapshot-dir" => {
+                let dir = args.next().ok_or(USAGE)?;
+                config
+                    .snapshots
+                    .get_or_insert_with(SnapshotConfig::default)
+                    .dir = dir.into();
+            }
+            "--snapshot-every" => {
+                let every = args.next().ok_or(USAGE)?;
+                config
+                    .snapshots
+                    .get_or_insert_with(SnapshotConfig::default)
+                    .every = Some(every.parse()?);
+            }
+            "--snapshot-on-marker" => {
+                config
+                    .snapshots
+                    .get_or_insert_with(SnapshotConfig::default)
+                    .on_marker = true
+            }
+            "--snapshot-mode" => {
+                let mode = args.next().ok_or(USAGE)?;
+                config
+                    .snapshots
+                    .get_or_insert_with(SnapshotConfig::default)
+                    .mode = mode.parse()?;
+            }
+            _ if input_path.is_none() => input_path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let input_path = input_path.ok_or(USAGE)?;
+    if lenient && config.parse_mode == ParseMode::Strict {
+        config.parse_mode = ParseMode::Lenient { max_errors: None };
     }
 
-    run_async(args[1].clone(), std::io::stdout()).await
+    run_async_with_config(input_path, std::io::stdout(), &config).await?;
+    Ok(())
 }
@@ -0,0 +1,75 @@
+//! This example converts transaction files between CSV and the compact binary format.
+//!
+//! Converting a large CSV file to binary once allows replaying it much faster,
+//! as the engine no longer spends time on text parsing.
+//!
+//! Example (CSV to binary and back):
+//! ```bash
+//! cargo run --release --example convert -- to-binary data/10K_clients.csv data/10K_clients.bin
+//! cargo run --release --example convert -- to-csv data/10K_clients.bin data/10K_clients_copy.csv
+//! ```
+//! Binary amounts have 4 decimal places unless a scale is given, e.g. `to-binary in.csv out.bin 8`.
+//! The binary file can then be processed directly, as the format is detected from the `.bin`
+//! extension:
+//! ```bash
+//! cargo run --release -- data/10K_clients.bin
+//! ```
+
+use csv::Writer;
+use rusty_reckoning::{read_csv_into_iter, BinaryReader, BinaryWriter, Transaction};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::{env, error::Error};
+
+const USAGE: &str =
+    "Usage: cargo run --example convert -- <to-binary|to-csv> <input> <output> [scale]";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    if !(4..=5).contains(&args.len()) {
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+    let (input, output) = (&args[2], &args[3]);
+    let scale = match args.get(4) {
+        Some(scale) => scale.parse()?,
+        None => 4,
+    };
+
+    let count = match args[1].as_str() {
+        "to-binary" => csv_to_binary(input, output, scale)?,
+        "to-csv" => binary_to_csv(input, output)?,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("Converted {} transactions: {} -> {}", count, input, output);
+    Ok(())
+}
+
+fn csv_to_binary(input: &str, output: &str, scale: u32) -> Result<usize, Box<dyn Error>> {
+    let transactions = read_csv_into_iter::<Transaction, _>(input)?;
+    let mut writer = BinaryWriter::with_scale(BufWriter::new(File::create(output)?), scale)?;
+
+    let mut count = 0;
+    for transaction in transactions {
+        writer.write_transaction(&transaction?)?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+fn binary_to_csv(input: &str, output: &str) -> Result<usize, Box<dyn Error>> {
+    let reader = BinaryReader::new(BufReader::new(File::open(input)?))?;
+    let mut writer = Writer::from_path(output)?;
+
+    let mut count = 0;
+    for transaction in reader {
+        writer.serialize(transaction?)?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
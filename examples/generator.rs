@@ -44,7 +44,7 @@
 use csv::Writer;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
-use rusty_reckoning::{Transaction, TransactionType};
+use rusty_reckoning::{ClientId, Transaction, TransactionType, TxId};
 use std::{env, error::Error};
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -58,7 +58,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     // Parse NUM_CLIENTS from the first argument
-    let num_clients: u16 = match args[1].parse() {
+    let num_clients: ClientId = match args[1].parse() {
         Ok(n) if n > 0 => n,
         _ => {
             eprintln!("Error: <num_clients> must be a positive integer.");
@@ -81,7 +81,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // We'll assign new global transaction IDs for deposit and withdrawal transactions.
     // (Dispute, resolve, and chargeback transactions reference deposit tx IDs mathematically.)
-    let mut global_tx_counter: u32 = 1;
+    let mut global_tx_counter: TxId = 1;
 
     let mut wtr = Writer::from_writer(std::io::stdout());
 
@@ -99,6 +99,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     client,
                     tx: tx_id,
                     amount: Some(BASE_DEPOSIT_AMOUNT * client_decimal),
+                    timestamp: None,
                 }
             } else if round < NUM_DEPOSITS + NUM_WITHDRAWALS {
                 // Withdrawal rounds: assign a new global transaction ID.
@@ -109,6 +110,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     client,
                     tx: tx_id,
                     amount: Some(BASE_WITHDRAWAL_AMOUNT * client_decimal),
+                    timestamp: None,
                 }
             } else if round < NUM_DEPOSITS + NUM_WITHDRAWALS + NUM_DISPUTES {
                 // Dispute rounds: reference the deposit corresponding to dispute index.
@@ -116,34 +118,39 @@ fn main() -> Result<(), Box<dyn Error>> {
                 // references the deposit produced in round i. That deposit’s global ID was:
                 // deposit_global_id = i * NUM_CLIENTS + client.
                 let dispute_index = round - (NUM_DEPOSITS + NUM_WITHDRAWALS);
-                let deposit_tx_id = (dispute_index as u32) * (num_clients as u32) + client as u32;
+                let deposit_tx_id =
+                    (dispute_index as TxId) * (num_clients as TxId) + client as TxId;
                 Transaction {
                     tx_type: TransactionType::Dispute,
                     client,
                     tx: deposit_tx_id,
                     amount: None,
+                    timestamp: None,
                 }
             } else if round < NUM_DEPOSITS + NUM_WITHDRAWALS + NUM_DISPUTES + NUM_RESOLVES {
                 // Resolve rounds: similar to disputes, reference deposit at index i.
                 let resolve_index = round - (NUM_DEPOSITS + NUM_WITHDRAWALS + NUM_DISPUTES);
-                let deposit_tx_id = (resolve_index as u32) * (num_clients as u32) + client as u32;
+                let deposit_tx_id =
+                    (resolve_index as TxId) * (num_clients as TxId) + client as TxId;
                 Transaction {
                     tx_type: TransactionType::Resolve,
                     client,
                     tx: deposit_tx_id,
                     amount: None,
+                    timestamp: None,
                 }
             } else {
                 // Final round: if client is even, issue a chargeback; if odd, an extra withdrawal.
                 if client % 2 == 0 {
                     // Chargeback references deposit with index NUM_RESOLVES.
                     let deposit_tx_id =
-                        (NUM_RESOLVES as u32) * (num_clients as u32) + client as u32;
+                        (NUM_RESOLVES as TxId) * (num_clients as TxId) + client as TxId;
                     Transaction {
                         tx_type: TransactionType::Chargeback,
                         client,
                         tx: deposit_tx_id,
                         amount: None,
+                        timestamp: None,
                     }
                 } else {
                     // Extra withdrawal: assign a new global transaction ID.
@@ -154,6 +161,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                         client,
                         tx: tx_id,
                         amount: Some(BASE_WITHDRAWAL_AMOUNT * client_decimal),
+                        timestamp: None,
                     }
                 }
             };
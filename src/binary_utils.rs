@@ -0,0 +1,597 @@
+//! Compact binary serialization for transactions.
+//!
+//! A binary file starts with an 8-byte header (`RRTX` magic, format version, the scale of
+//! the amounts and two reserved zero bytes), followed by fixed-width 16-byte records. All
+//! integers are little-endian:
+//!
+//! | Offset | Size | Field                                                    |
+//! |--------|------|----------------------------------------------------------|
+//! | 0      | 1    | Transaction type (0 = deposit, 1 = withdrawal, 2 = dispute, 3 = resolve, 4 = chargeback) |
+//! | 1      | 2    | Client ID (`u16`)                                        |
+//! | 3      | 4    | Transaction ID (`u32`)                                   |
+//! | 7      | 1    | Amount presence flag (0 = absent, 1 = present)           |
+//! | 8      | 8    | Amount as fixed-point `i64`, scaled by 10^scale          |
+//!
+//! The scale defaults to 4 decimal places, and can be anything from 0 to 28. Amounts with
+//! more decimal places than the scale are rejected when encoding rather than truncated.
+//! With the `wide-ids` feature, the client and transaction IDs are `u32` and `u64`,
+//! widening the records to 22 bytes, and the format version is 2. Files written with
+//! one width are rejected by readers of the other.
+//!
+//! Skipping text parsing entirely makes re-running large replays considerably faster.
+
+use rust_decimal::Decimal;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::dto::{ClientId, Transaction, TransactionType, TxId, MAX_SCALE};
+
+/// Start of the file header: magic bytes and format version.
+#[cfg(not(feature = "wide-ids"))]
+const MAGIC: [u8; 5] = *b"RRTX\x01";
+/// Start of the file header: magic bytes and format version.
+#[cfg(feature = "wide-ids")]
+const MAGIC: [u8; 5] = *b"RRTX\x02";
+/// Size of the file header: magic bytes, format version, amount scale, reserved bytes.
+pub const HEADER_SIZE: usize = 8;
+/// Number of decimal places stored in the fixed-point amount, unless configured otherwise.
+pub const DEFAULT_SCALE: u32 = 4;
+/// Offset of the client ID in a record.
+const CLIENT_OFFSET: usize = 1;
+/// Offset of the transaction ID in a record.
+const TX_OFFSET: usize = CLIENT_OFFSET + size_of::<ClientId>();
+/// Offset of the amount presence flag in a record.
+const AMOUNT_FLAG_OFFSET: usize = TX_OFFSET + size_of::<TxId>();
+/// Offset of the fixed-point amount in a record.
+const AMOUNT_OFFSET: usize = AMOUNT_FLAG_OFFSET + 1;
+/// Size of a single encoded transaction record in bytes.
+pub const RECORD_SIZE: usize = AMOUNT_OFFSET + size_of::<i64>();
+
+/// Error produced while reading or writing binary transaction files.
+/// Record numbers are 1-based and do not count the header.
+#[derive(Debug)]
+pub enum BinaryError {
+    Io(io::Error),
+    InvalidHeader,
+    InvalidRecord {
+        record: u64,
+        reason: &'static str,
+    },
+    TruncatedRecord {
+        record: u64,
+    },
+    AmountOutOfRange(Decimal),
+    /// The amount has more decimal places than the scale of the file.
+    ExcessPrecision {
+        amount: Decimal,
+        scale: u32,
+    },
+    /// The scale exceeds the 28 decimal places supported by `Decimal`.
+    InvalidScale(u32),
+}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryError::Io(err) => write!(f, "Binary I/O error: {}", err),
+            BinaryError::InvalidHeader => write!(f, "Binary format error: invalid file header"),
+            BinaryError::InvalidRecord { record, reason } => {
+                write!(f, "Binary format error: record {}: {}", record, reason)
+            }
+            BinaryError::TruncatedRecord { record } => {
+                write!(
+                    f,
+                    "Binary format error: record {}: unexpected end of file",
+                    record
+                )
+            }
+            BinaryError::AmountOutOfRange(amount) => write!(
+                f,
+                "Binary format error: amount {} does not fit a fixed-point i64",
+                amount
+            ),
+            BinaryError::ExcessPrecision { amount, scale } => write!(
+                f,
+                "Binary format error: amount {} has more than {} decimal places",
+                amount, scale
+            ),
+            BinaryError::InvalidScale(scale) => write!(
+                f,
+                "Binary format error: scale {} exceeds {} decimal places",
+                scale, MAX_SCALE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BinaryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BinaryError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BinaryError {
+    fn from(err: io::Error) -> Self {
+        BinaryError::Io(err)
+    }
+}
+
+/// Encodes a transaction into a fixed-width record, with amounts scaled by 10^`scale`.
+/// Amounts with more decimal places than `scale` are rejected.
+pub fn encode_record(
+    transaction: &Transaction,
+    scale: u32,
+) -> Result<[u8; RECORD_SIZE], BinaryError> {
+    let mut record = [0u8; RECORD_SIZE];
+    record[0] = match transaction.tx_type {
+        TransactionType::Deposit => 0,
+        TransactionType::Withdrawal => 1,
+        TransactionType::Dispute => 2,
+        TransactionType::Resolve => 3,
+        TransactionType::Chargeback => 4,
+    };
+    record[CLIENT_OFFSET..TX_OFFSET].copy_from_slice(&transaction.client.to_le_bytes());
+    record[TX_OFFSET..AMOUNT_FLAG_OFFSET].copy_from_slice(&transaction.tx.to_le_bytes());
+    if let Some(amount) = transaction.amount {
+        if amount.normalize().scale() > scale {
+            return Err(BinaryError::ExcessPrecision { amount, scale });
+        }
+        let mut scaled = amount;
+        scaled.rescale(scale);
+        let mantissa =
+            i64::try_from(scaled.mantissa()).map_err(|_| BinaryError::AmountOutOfRange(amount))?;
+        record[AMOUNT_FLAG_OFFSET] = 1;
+        record[AMOUNT_OFFSET..].copy_from_slice(&mantissa.to_le_bytes());
+    }
+    Ok(record)
+}
+
+/// Decodes a fixed-width record into a transaction, with amounts scaled by 10^`scale`.
+/// `record_no` is only used for error reporting.
+pub fn decode_record(
+    record: &[u8; RECORD_SIZE],
+    record_no: u64,
+    scale: u32,
+) -> Result<Transaction, BinaryError> {
+    let invalid = |reason| BinaryError::InvalidRecord {
+        record: record_no,
+        reason,
+    };
+    let tx_type = match record[0] {
+        0 => TransactionType::Deposit,
+        1 => TransactionType::Withdrawal,
+        2 => TransactionType::Dispute,
+        3 => TransactionType::Resolve,
+        4 => TransactionType::Chargeback,
+        _ => return Err(invalid("unknown transaction type")),
+    };
+    let client = ClientId::from_le_bytes(
+        record[CLIENT_OFFSET..TX_OFFSET]
+            .try_into()
+            .expect("slice of the client ID size"),
+    );
+    let tx = TxId::from_le_bytes(
+        record[TX_OFFSET..AMOUNT_FLAG_OFFSET]
+            .try_into()
+            .expect("slice of the transaction ID size"),
+    );
+    let mantissa = i64::from_le_bytes(
+        record[AMOUNT_OFFSET..]
+            .try_into()
+            .expect("slice of 8 bytes"),
+    );
+    let amount = match record[AMOUNT_FLAG_OFFSET] {
+        0 => None,
+        // Normalize so that e.g. 2.0000 reads back as 2, matching CSV input
+        1 => Some(Decimal::new(mantissa, scale).normalize()),
+        _ => return Err(invalid("invalid amount presence flag")),
+    };
+    Ok(Transaction {
+        tx_type,
+        client,
+        tx,
+        amount,
+        timestamp: None,
+    })
+}
+
+/// Builds the file header for amounts with the given scale.
+fn header(scale: u32) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    header[..MAGIC.len()].copy_from_slice(&MAGIC);
+    header[MAGIC.len()] = scale as u8;
+    header
+}
+
+/// Classifies an error reading the file header. Input too short for a header is not a
+/// binary transaction file, while other I/O errors are passed through.
+pub fn header_read_error(err: io::Error) -> BinaryError {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => BinaryError::InvalidHeader,
+        _ => BinaryError::Io(err),
+    }
+}
+
+/// Validates the binary file header, returning the scale of the amounts.
+pub fn check_header(header: &[u8; HEADER_SIZE]) -> Result<u32, BinaryError> {
+    let (magic, rest) = header.split_at(MAGIC.len());
+    if magic != MAGIC || rest[1..] != [0, 0] {
+        return Err(BinaryError::InvalidHeader);
+    }
+    match u32::from(rest[0]) {
+        scale if scale <= MAX_SCALE => Ok(scale),
+        _ => Err(BinaryError::InvalidHeader),
+    }
+}
+
+/// Streams transactions from a binary source.
+pub struct BinaryReader<R> {
+    reader: R,
+    record_no: u64,
+    scale: u32,
+}
+
+impl<R: Read> BinaryReader<R> {
+    /// Creates a reader, validating the file header.
+    pub fn new(mut reader: R) -> Result<Self, BinaryError> {
+        let mut header = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header).map_err(header_read_error)?;
+        let scale = check_header(&header)?;
+        Ok(Self {
+            reader,
+            record_no: 0,
+            scale,
+        })
+    }
+
+    /// Number of decimal places of the amounts in the file.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Reads the next raw record along with its 1-based record number,
+    /// returning `None` at a clean end of file.
+    pub fn read_record(&mut self) -> Result<Option<(u64, [u8; RECORD_SIZE])>, BinaryError> {
+        let mut record = [0u8; RECORD_SIZE];
+        let mut filled = 0;
+        while filled < RECORD_SIZE {
+            match self.reader.read(&mut record[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if filled == 0 {
+            return Ok(None);
+        }
+        self.record_no += 1;
+        if filled < RECORD_SIZE {
+            return Err(BinaryError::TruncatedRecord {
+                record: self.record_no,
+            });
+        }
+        Ok(Some((self.record_no, record)))
+    }
+
+    /// Reads the next transaction, returning `None` at a clean end of file.
+    pub fn read_transaction(&mut self) -> Result<Option<Transaction>, BinaryError> {
+        match self.read_record()? {
+            Some((record_no, record)) => decode_record(&record, record_no, self.scale).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: Read> Iterator for BinaryReader<R> {
+    type Item = Result<Transaction, BinaryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_transaction().transpose()
+    }
+}
+
+/// Writes transactions to a binary sink.
+pub struct BinaryWriter<W: Write> {
+    writer: W,
+    scale: u32,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    /// Creates a writer for amounts with up to 4 decimal places, emitting the file header.
+    pub fn new(writer: W) -> Result<Self, BinaryError> {
+        Self::with_scale(writer, DEFAULT_SCALE)
+    }
+
+    /// Creates a writer for amounts with up to `scale` decimal places, emitting the file
+    /// header.
+    pub fn with_scale(mut writer: W, scale: u32) -> Result<Self, BinaryError> {
+        if scale > MAX_SCALE {
+            return Err(BinaryError::InvalidScale(scale));
+        }
+        writer.write_all(&header(scale))?;
+        Ok(Self { writer, scale })
+    }
+
+    /// Encodes and writes a single transaction.
+    pub fn write_transaction(&mut self, transaction: &Transaction) -> Result<(), BinaryError> {
+        self.writer
+            .write_all(&encode_record(transaction, self.scale)?)?;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<(), BinaryError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn sample_transactions() -> Vec<Transaction> {
+        vec![
+            Transaction {
+                tx_type: TransactionType::Deposit,
+                client: 1,
+                tx: 1,
+                amount: Some(dec!(1.5)),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Withdrawal,
+                client: ClientId::MAX,
+                tx: TxId::MAX,
+                amount: Some(dec!(0.1234)),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 1,
+                tx: 1,
+                amount: None,
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Resolve,
+                client: 1,
+                tx: 1,
+                amount: None,
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Chargeback,
+                client: 1,
+                tx: 1,
+                amount: Some(dec!(-10)),
+                timestamp: None,
+            },
+        ]
+    }
+
+    fn encode_all(transactions: &[Transaction]) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(Vec::new()).unwrap();
+        for transaction in transactions {
+            writer.write_transaction(transaction).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<(), BinaryError> {
+        let transactions = sample_transactions();
+        let bytes = encode_all(&transactions);
+        assert_eq!(bytes.len(), HEADER_SIZE + transactions.len() * RECORD_SIZE);
+
+        let decoded: Vec<Transaction> =
+            BinaryReader::new(bytes.as_slice())?.collect::<Result<_, _>>()?;
+        assert_eq!(decoded, transactions);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(feature = "wide-ids"))]
+    fn test_record_layout() -> Result<(), BinaryError> {
+        let record = encode_record(
+            &Transaction {
+                tx_type: TransactionType::Withdrawal,
+                client: 0x0102,
+                tx: 0x03040506,
+                amount: Some(dec!(1.5)),
+                timestamp: None,
+            },
+            DEFAULT_SCALE,
+        )?;
+        assert_eq!(
+            record,
+            [1, 0x02, 0x01, 0x06, 0x05, 0x04, 0x03, 1, 0x98, 0x3A, 0, 0, 0, 0, 0, 0]
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "wide-ids")]
+    fn test_record_layout() -> Result<(), BinaryError> {
+        let record = encode_record(
+            &Transaction {
+                tx_type: TransactionType::Withdrawal,
+                client: 0x01020304,
+                tx: 0x05060708090A0B0C,
+                amount: Some(dec!(1.5)),
+                timestamp: None,
+            },
+            DEFAULT_SCALE,
+        )?;
+        assert_eq!(
+            record,
+            [
+                1, 0x04, 0x03, 0x02, 0x01, 0x0C, 0x0B, 0x0A, 0x09, 0x08, 0x07, 0x06, 0x05, 1, 0x98,
+                0x3A, 0, 0, 0, 0, 0, 0
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_amount_with_excess_decimal_places() {
+        let transaction = Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(dec!(0.123499999)),
+            timestamp: None,
+        };
+        let result = encode_record(&transaction, DEFAULT_SCALE);
+        assert!(matches!(
+            result,
+            Err(BinaryError::ExcessPrecision { scale: 4, .. })
+        ));
+        // Trailing zeros do not count
+        let transaction = Transaction {
+            amount: Some(dec!(0.12340000)),
+            ..transaction
+        };
+        let record = encode_record(&transaction, DEFAULT_SCALE).unwrap();
+        assert_eq!(
+            decode_record(&record, 1, DEFAULT_SCALE).unwrap().amount,
+            Some(dec!(0.1234))
+        );
+    }
+
+    #[test]
+    fn test_scale_in_header() -> Result<(), BinaryError> {
+        let transaction = Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(dec!(0.12345678)),
+            timestamp: None,
+        };
+        let mut writer = BinaryWriter::with_scale(Vec::new(), 8)?;
+        writer.write_transaction(&transaction)?;
+        let bytes = writer.into_inner();
+        assert_eq!(bytes[5], 8);
+
+        let mut reader = BinaryReader::new(bytes.as_slice())?;
+        assert_eq!(reader.scale(), 8);
+        assert_eq!(reader.read_transaction()?, Some(transaction));
+
+        let mut header = header(8);
+        header[5] = 29;
+        assert!(matches!(
+            check_header(&header),
+            Err(BinaryError::InvalidHeader)
+        ));
+        assert!(matches!(
+            BinaryWriter::with_scale(Vec::new(), 29),
+            Err(BinaryError::InvalidScale(29))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scale_0_round_trip() -> Result<(), BinaryError> {
+        let transaction = Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(dec!(5)),
+            timestamp: None,
+        };
+        let mut writer = BinaryWriter::with_scale(Vec::new(), 0)?;
+        writer.write_transaction(&transaction)?;
+        let bytes = writer.into_inner();
+        assert_eq!(bytes[5], 0);
+
+        let mut reader = BinaryReader::new(bytes.as_slice())?;
+        assert_eq!(reader.scale(), 0);
+        assert_eq!(reader.read_transaction()?, Some(transaction));
+
+        let fraction = Transaction {
+            amount: Some(dec!(5.5)),
+            ..sample_transactions()[0].clone()
+        };
+        assert!(matches!(
+            encode_record(&fraction, 0),
+            Err(BinaryError::ExcessPrecision { scale: 0, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_amount_out_of_range() {
+        let result = encode_record(
+            &Transaction {
+                tx_type: TransactionType::Deposit,
+                client: 1,
+                tx: 1,
+                amount: Some(Decimal::MAX),
+                timestamp: None,
+            },
+            DEFAULT_SCALE,
+        );
+        assert!(matches!(result, Err(BinaryError::AmountOutOfRange(_))));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert!(matches!(
+            BinaryReader::new(&b"type,client,tx,amount\n"[..]),
+            Err(BinaryError::InvalidHeader)
+        ));
+        assert!(matches!(
+            BinaryReader::new(&b"RR"[..]),
+            Err(BinaryError::InvalidHeader)
+        ));
+    }
+
+    #[test]
+    fn test_header_io_error() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"))
+            }
+        }
+        assert!(matches!(
+            BinaryReader::new(Failing),
+            Err(BinaryError::Io(err)) if err.kind() == io::ErrorKind::PermissionDenied
+        ));
+    }
+
+    #[test]
+    fn test_invalid_record() {
+        let mut bytes = encode_all(&sample_transactions());
+        // Corrupt the type byte of the second record
+        bytes[HEADER_SIZE + RECORD_SIZE] = 9;
+
+        let result: Result<Vec<_>, _> = BinaryReader::new(bytes.as_slice()).unwrap().collect();
+        assert!(matches!(
+            result,
+            Err(BinaryError::InvalidRecord { record: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let mut bytes = encode_all(&sample_transactions());
+        bytes.truncate(bytes.len() - 3);
+
+        let result: Result<Vec<_>, _> = BinaryReader::new(bytes.as_slice()).unwrap().collect();
+        assert!(matches!(
+            result,
+            Err(BinaryError::TruncatedRecord { record: 5 })
+        ));
+    }
+}
@@ -1,12 +1,86 @@
 //! CSV serialization and deserialization utilities.
 //!
-//! Provides generic functions for reading and writing CSV data.
+//! Provides generic functions for reading and writing CSV data, and the [`CsvDialect`]
+//! describing the layout of CSV transaction files.
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::collections::HashMap;
 use std::io::Write;
 use std::path::Path;
 
+/// Layout of a CSV transaction file.
+///
+/// Header names are trimmed and matched case-insensitively, and a leading UTF-8 BOM
+/// is always stripped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvDialect {
+    /// Field delimiter, `,` by default
+    pub delimiter: u8,
+    /// Quote character, `"` by default
+    pub quote: u8,
+    /// Maps column names used in the file to the transaction fields
+    /// (`type`, `client`, `tx` and `amount`), e.g. `transaction_id` to `tx`.
+    pub header_aliases: HeaderAliases,
+    /// Column order of files without a header row.
+    /// If `None`, the first row of the file is the header.
+    pub columns: Option<Vec<String>>,
+    /// Accept transaction type names in any case, e.g. `Deposit` or `DEPOSIT`.
+    pub case_insensitive_types: bool,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            header_aliases: HeaderAliases::default(),
+            columns: None,
+            case_insensitive_types: false,
+        }
+    }
+}
+
+impl CsvDialect {
+    /// Resolves a column name to the transaction field it holds.
+    pub fn resolve_column(&self, name: &str) -> String {
+        let name = name.trim().to_ascii_lowercase();
+        match self.header_aliases.0.get(&name) {
+            Some(field) => field.clone(),
+            None => name,
+        }
+    }
+
+    /// Parses a delimiter or quote character given as a single ASCII character,
+    /// or as `tab` / `\t`.
+    pub fn parse_char(s: &str) -> Result<u8, String> {
+        match s {
+            "tab" | "\\t" => Ok(b'\t'),
+            _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+            _ => Err(format!("expected a single ASCII character, got: {}", s)),
+        }
+    }
+}
+
+/// Column names of a CSV file mapped to the transaction fields they hold.
+/// Names are trimmed and lowercased when added, as header names are matched
+/// case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderAliases(HashMap<String, String>);
+
+impl HeaderAliases {
+    /// Adds an alias mapping a column name to a transaction field.
+    /// Returns an error if the name, in any case, already has an alias.
+    pub fn insert(&mut self, name: &str, field: &str) -> Result<(), String> {
+        let name = name.trim().to_ascii_lowercase();
+        if self.0.contains_key(&name) {
+            return Err(format!("duplicate header alias: {}", name));
+        }
+        self.0.insert(name, field.trim().to_ascii_lowercase());
+        Ok(())
+    }
+}
+
 /// Creates an iterator that reads CSV records from a file.
 /// Each record is deserialized into type T.
 pub fn read_csv_into_iter<T, P>(path: P) -> csv::Result<impl Iterator<Item = csv::Result<T>>>
@@ -38,9 +112,43 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{dto::AccountRow, dto::Transaction, TransactionType};
+    use crate::{dto::AccountRow, dto::Transaction, Precision, TransactionType};
     use rust_decimal_macros::dec;
 
+    #[test]
+    fn test_resolve_column() {
+        let mut dialect = CsvDialect::default();
+        dialect
+            .header_aliases
+            .insert("transaction_id", "tx")
+            .unwrap();
+        dialect.header_aliases.insert("Kind", "type").unwrap();
+        assert_eq!(dialect.resolve_column("amount"), "amount");
+        assert_eq!(dialect.resolve_column(" Client "), "client");
+        assert_eq!(dialect.resolve_column("Transaction_ID"), "tx");
+        assert_eq!(dialect.resolve_column("kind"), "type");
+    }
+
+    #[test]
+    fn test_duplicate_header_alias() {
+        let mut dialect = CsvDialect::default();
+        dialect.header_aliases.insert("Kind", "type").unwrap();
+        assert_eq!(
+            dialect.header_aliases.insert(" KIND ", "tx"),
+            Err("duplicate header alias: kind".to_string())
+        );
+        assert_eq!(dialect.resolve_column("kind"), "type");
+    }
+
+    #[test]
+    fn test_parse_char() {
+        assert_eq!(CsvDialect::parse_char(";"), Ok(b';'));
+        assert_eq!(CsvDialect::parse_char("tab"), Ok(b'\t'));
+        assert_eq!(CsvDialect::parse_char("\\t"), Ok(b'\t'));
+        assert!(CsvDialect::parse_char("ab").is_err());
+        assert!(CsvDialect::parse_char("").is_err());
+    }
+
     #[test]
     fn test_read_csv() -> csv::Result<()> {
         let transactions: Vec<Transaction> =
@@ -52,30 +160,35 @@ mod tests {
                 client: 1,
                 tx: 1,
                 amount: Some(dec!(1.0)),
+                timestamp: None,
             },
             Transaction {
                 tx_type: TransactionType::Deposit,
                 client: 2,
                 tx: 2,
                 amount: Some(dec!(2.0)),
+                timestamp: None,
             },
             Transaction {
                 tx_type: TransactionType::Deposit,
                 client: 1,
                 tx: 3,
                 amount: Some(dec!(2.0)),
+                timestamp: None,
             },
             Transaction {
                 tx_type: TransactionType::Withdrawal,
                 client: 1,
                 tx: 4,
                 amount: Some(dec!(1.5)),
+                timestamp: None,
             },
             Transaction {
                 tx_type: TransactionType::Withdrawal,
                 client: 2,
                 tx: 5,
                 amount: Some(dec!(3.0)),
+                timestamp: None,
             },
         ];
         assert_eq!(transactions, expected_transactions);
@@ -85,51 +198,82 @@ mod tests {
 
     #[test]
     fn test_write_csv() -> csv::Result<()> {
-        let accounts = vec![
-            AccountRow {
-                client: 1,
-                available: dec!(1.5),
-                held: dec!(0.0),
-                total: dec!(1.5),
-                locked: false,
-            },
-            AccountRow {
-                client: 2,
-                available: dec!(2.0),
-                held: dec!(3.1234),
-                total: dec!(5.1234),
-                locked: true,
-            },
-            AccountRow {
-                client: 3,
-                available: dec!(0.0),
-                held: dec!(0.0),
-                total: dec!(0.0),
-                locked: false,
-            },
-            // Test rounding behavior
-            AccountRow {
-                client: 4,
-                available: dec!(1.23456),
-                held: dec!(2.34567),
-                total: dec!(3.58009),
-                locked: false,
-            },
-        ];
-
-        let mut output = vec![];
-        write_csv(&mut output, accounts.into_iter())?;
+        let accounts = || {
+            vec![
+                AccountRow {
+                    client: 1,
+                    available: dec!(1.5),
+                    held: dec!(0.0),
+                    total: dec!(1.5),
+                    locked: false,
+                    flagged: None,
+                    receivable: None,
+                },
+                AccountRow {
+                    client: 2,
+                    available: dec!(2.0),
+                    held: dec!(3.1234),
+                    total: dec!(5.1234),
+                    locked: true,
+                    flagged: None,
+                    receivable: None,
+                },
+                AccountRow {
+                    client: 3,
+                    available: dec!(0.0),
+                    held: dec!(0.0),
+                    total: dec!(0.0),
+                    locked: false,
+                    flagged: None,
+                    receivable: None,
+                },
+                // Test rounding behavior
+                AccountRow {
+                    client: 4,
+                    available: dec!(1.23456),
+                    held: dec!(2.34567),
+                    total: dec!(3.58009),
+                    locked: false,
+                    flagged: None,
+                    receivable: None,
+                },
+            ]
+        };
 
-        let csv_string = String::from_utf8(output).unwrap();
-        let expected = "\
+        let cases = [
+            (
+                4,
+                "\
 client,available,held,total,locked
 1,1.5,0.0,1.5,false
 2,2.0,3.1234,5.1234,true
 3,0.0,0.0,0.0,false
 4,1.2345,2.3456,3.5800,false
-";
+",
+            ),
+            (
+                2,
+                "\
+client,available,held,total,locked
+1,1.5,0.0,1.5,false
+2,2.0,3.12,5.12,true
+3,0.0,0.0,0.0,false
+4,1.23,2.34,3.58,false
+",
+            ),
+        ];
+        for (scale, expected) in cases {
+            let precision = Precision {
+                scale,
+                ..Default::default()
+            };
+            let rows = accounts().into_iter().map(|row| row.rounded(&precision));
+            let mut output = vec![];
+            write_csv(&mut output, rows)?;
 
-        assert_eq!(csv_string, expected);
+            let csv_string = String::from_utf8(output).unwrap();
+            assert_eq!(csv_string, expected, "scale {}", scale);
+        }
         Ok(())
     }
 }
@@ -1,22 +1,44 @@
 //! Data Transfer Objects (DTOs) for the payment processing system.
 //!
 //! This module contains the structs and enums used for:
-//! - Parsing input transactions from CSV ([`Transaction`], [`TransactionType`])
+//! - Parsing input transactions from CSV or NDJSON ([`Transaction`], [`TransactionType`])
 //! - Serializing account state to CSV output ([`AccountRow`])
+//! - Reporting malformed input records in lenient mode ([`RejectRow`])
+//! - Journaling the outcome of every processed transaction ([`OutcomeRow`])
+//! - Reporting the would-be outcome of simulated transactions ([`SimulationRow`])
 //!
-//! It also includes serialization/deserialization helpers for handling decimal numbers
-//! with 4 decimal places precision.
+//! Client and transaction ids are [`ClientId`] and [`TxId`], whose width is chosen by
+//! the `wide-ids` cargo feature.
+//!
+//! Amounts are read with all their decimal places, and brought to the configured
+//! precision by the engine (see [`Precision`]). Account balances are rounded to the
+//! same precision on output.
 
 use crate::stores::Account;
+use crate::{Error, Precision, Simulation};
 use rust_decimal::Decimal;
-use rust_decimal::RoundingStrategy;
-use serde::de::Deserializer;
-use serde::ser::Serializer;
 use serde::Deserialize;
 use serde::Serialize;
 
+/// Client identifier: `u16` by default, `u32` with the `wide-ids` feature.
+#[cfg(not(feature = "wide-ids"))]
+pub type ClientId = u16;
+/// Client identifier: `u16` by default, `u32` with the `wide-ids` feature.
+#[cfg(feature = "wide-ids")]
+pub type ClientId = u32;
+
+/// Transaction identifier: `u32` by default, `u64` with the `wide-ids` feature.
+#[cfg(not(feature = "wide-ids"))]
+pub type TxId = u32;
+/// Transaction identifier: `u32` by default, `u64` with the `wide-ids` feature.
+#[cfg(feature = "wide-ids")]
+pub type TxId = u64;
+
+/// Maximum number of decimal places a [`Decimal`] can hold.
+pub(crate) const MAX_SCALE: u32 = 28;
+
 /// Transaction type supported by the engine.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -31,22 +53,47 @@ pub enum TransactionType {
 pub struct Transaction {
     #[serde(rename = "type")]
     pub tx_type: TransactionType,
-    pub client: u16,
-    pub tx: u32,
-    #[serde(deserialize_with = "deserialize_decimal_4dp")]
+    pub client: ClientId,
+    pub tx: TxId,
+    #[serde(default)]
     pub amount: Option<Decimal>,
+    /// Time of the transaction in seconds since the Unix epoch, from an optional
+    /// `timestamp` column. Used to age disputes by duration (see
+    /// [`DisputeAging`](crate::DisputeAging)). Binary input carries no timestamps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AccountRow {
-    pub client: u16,
-    #[serde(serialize_with = "serialize_decimal_4dp")]
+    pub client: ClientId,
     pub available: Decimal,
-    #[serde(serialize_with = "serialize_decimal_4dp")]
     pub held: Decimal,
-    #[serde(serialize_with = "serialize_decimal_4dp")]
     pub total: Decimal,
     pub locked: bool,
+    /// Whether a chargeback flagged the account for review, only written with a
+    /// [`ChargebackPolicy`](crate::ChargebackPolicy) other than `Lock`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flagged: Option<bool>,
+    /// Shortfall of disputes owed by the client, only written with
+    /// [`NegativeBalancePolicy::HoldAvailable`](crate::NegativeBalancePolicy::HoldAvailable)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receivable: Option<Decimal>,
+}
+
+impl AccountRow {
+    /// Rounds the amounts to the given precision, as written to the output.
+    pub fn rounded(self, precision: &Precision) -> Self {
+        AccountRow {
+            available: precision.round(self.available),
+            held: precision.round(self.held),
+            total: precision.round(self.total),
+            receivable: self
+                .receivable
+                .map(|receivable| precision.round(receivable)),
+            ..self
+        }
+    }
 }
 
 impl From<&Account> for AccountRow {
@@ -57,24 +104,103 @@ impl From<&Account> for AccountRow {
             held: account.held,
             total: account.total(),
             locked: account.locked,
+            flagged: None,
+            receivable: None,
+        }
+    }
+}
+
+/// Malformed input record, skipped in lenient mode and written to the rejects CSV.
+#[derive(Debug, Serialize)]
+pub struct RejectRow<'a> {
+    /// Line number of the record (record number for binary input)
+    pub line: u64,
+    pub raw: &'a str,
+    pub error: String,
+}
+
+/// Whether the engine applied a transaction.
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OutcomeStatus {
+    Accepted,
+    Rejected,
+    /// Resolve or chargeback applied by the engine to close a dispute that was too old
+    /// (see [`DisputeAging`](crate::DisputeAging))
+    Aged,
+}
+
+/// Outcome of a single transaction, written to the outcomes journal.
+#[derive(Debug, Serialize)]
+pub struct OutcomeRow {
+    /// Line number of the record (record number for binary input)
+    pub line: u64,
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+    pub client: ClientId,
+    pub tx: TxId,
+    pub status: OutcomeStatus,
+    /// Reason code of the rejection (see [`Error::code`]), empty if accepted
+    pub reason: Option<&'static str>,
+}
+
+impl OutcomeRow {
+    pub fn new(line: u64, transaction: &Transaction, result: &Result<(), Error>) -> Self {
+        let (status, reason) = match result {
+            Ok(()) => (OutcomeStatus::Accepted, None),
+            Err(err) => (OutcomeStatus::Rejected, Some(err.code())),
+        };
+        OutcomeRow {
+            line,
+            tx_type: transaction.tx_type,
+            client: transaction.client,
+            tx: transaction.tx,
+            status,
+            reason,
         }
     }
 }
 
-fn deserialize_decimal_4dp<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    Option::<Decimal>::deserialize(deserializer)
-        .map(|opt_dec| opt_dec.map(|dec| dec.round_dp_with_strategy(4, RoundingStrategy::ToZero)))
+/// Would-be outcome of a simulated transaction, written to the what-if report.
+#[derive(Debug, Serialize)]
+pub struct SimulationRow {
+    /// Line number of the record (record number for binary input)
+    pub line: u64,
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+    pub client: ClientId,
+    pub tx: TxId,
+    pub status: OutcomeStatus,
+    /// Reason code of the rejection (see [`Error::code`]), empty if accepted
+    pub reason: Option<&'static str>,
+    /// Balances of the account after the transaction, empty if rejected
+    pub available: Option<Decimal>,
+    pub held: Option<Decimal>,
+    pub total: Option<Decimal>,
+    pub locked: Option<bool>,
 }
 
-fn serialize_decimal_4dp<S>(decimal: &Decimal, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    let rounded = decimal.round_dp_with_strategy(4, RoundingStrategy::ToZero);
-    serializer.serialize_str(&rounded.to_string())
+impl SimulationRow {
+    pub fn new(line: u64, transaction: &Transaction, result: &Result<Simulation, Error>) -> Self {
+        let account = result.as_ref().ok().map(|simulation| &simulation.account);
+        let outcome = OutcomeRow::new(
+            line,
+            transaction,
+            &result.as_ref().map(|_| ()).map_err(|err| *err),
+        );
+        SimulationRow {
+            line,
+            tx_type: outcome.tx_type,
+            client: outcome.client,
+            tx: outcome.tx,
+            status: outcome.status,
+            reason: outcome.reason,
+            available: account.map(|account| account.available),
+            held: account.map(|account| account.held),
+            total: account.map(Account::total),
+            locked: account.map(|account| account.locked),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -97,6 +223,7 @@ mod tests {
                 client: 1,
                 tx: 1,
                 amount: Some(dec!(0.1234)),
+                timestamp: None,
             }
         );
     }
@@ -110,6 +237,7 @@ mod tests {
                 client: 2,
                 tx: 2,
                 amount: Some(dec!(1.5)),
+                timestamp: None,
             }
         );
     }
@@ -123,6 +251,7 @@ mod tests {
                 client: 1,
                 tx: 1,
                 amount: None,
+                timestamp: None,
             }
         );
     }
@@ -136,6 +265,7 @@ mod tests {
                 client: 1,
                 tx: 1,
                 amount: None,
+                timestamp: None,
             }
         );
     }
@@ -149,6 +279,7 @@ mod tests {
                 client: 1,
                 tx: 1,
                 amount: None,
+                timestamp: None,
             }
         );
     }
@@ -167,68 +298,144 @@ mod tests {
 
     #[test]
     fn test_client_id_overflow() {
-        let result = parse_csv_row("deposit,65536,1,1.0"); // u16::MAX + 1
+        let result = parse_csv_row(&format!("deposit,{},1,1.0", u64::from(ClientId::MAX) + 1));
         assert!(result.is_err());
     }
 
     #[test]
     fn test_transaction_id_overflow() {
-        let result = parse_csv_row("deposit,1,4294967296,1.0"); // u32::MAX + 1
+        let result = parse_csv_row(&format!("deposit,1,{},1.0", u128::from(TxId::MAX) + 1));
         assert!(result.is_err());
     }
 
     #[test]
     fn test_max_valid_ids() {
         assert_eq!(
-            parse_csv_row(&format!("deposit,{},{},1.0", u16::MAX, u32::MAX)).unwrap(),
+            parse_csv_row(&format!("deposit,{},{},1.0", ClientId::MAX, TxId::MAX)).unwrap(),
             Transaction {
                 tx_type: TransactionType::Deposit,
-                client: u16::MAX,
-                tx: u32::MAX,
+                client: ClientId::MAX,
+                tx: TxId::MAX,
                 amount: Some(dec!(1.0)),
+                timestamp: None,
             }
         );
     }
 
     #[test]
-    fn test_rounds_to_4_decimal_places() {
-        assert_eq!(
-            parse_csv_row("deposit,1,1,0.12345").unwrap(),
-            Transaction {
-                tx_type: TransactionType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(dec!(0.1234)), // Rounded down from 0.12345
-            }
-        );
+    #[cfg(not(feature = "wide-ids"))]
+    fn test_default_id_widths() {
+        // Existing files keep their limits unless the `wide-ids` feature is enabled
+        assert!(parse_csv_row("deposit,65536,1,1.0").is_err()); // u16::MAX + 1
+        assert!(parse_csv_row("deposit,1,4294967296,1.0").is_err()); // u32::MAX + 1
+    }
 
+    #[test]
+    #[cfg(feature = "wide-ids")]
+    fn test_wide_ids() {
         assert_eq!(
-            parse_csv_row("deposit,1,1,0.123499999").unwrap(),
+            parse_csv_row("deposit,65536,4294967296,1.0").unwrap(),
             Transaction {
                 tx_type: TransactionType::Deposit,
-                client: 1,
-                tx: 1,
-                amount: Some(dec!(0.1234)), // Rounded down from 0.123499999
+                client: 65536,
+                tx: 4294967296,
+                amount: Some(dec!(1.0)),
+                timestamp: None,
             }
         );
     }
 
+    #[test]
+    fn test_rounds_to_4_decimal_places() {
+        // Amounts are parsed as is, and rounded by the engine to its configured precision
+        let cases = [
+            ("0.12345", 4, dec!(0.1234)),     // Rounded down from 0.12345
+            ("0.123499999", 4, dec!(0.1234)), // Rounded down from 0.123499999
+            ("0.123499999", 8, dec!(0.12349999)),
+            ("0.12345678", 8, dec!(0.12345678)),
+        ];
+        for (amount, scale, expected) in cases {
+            let transaction = parse_csv_row(&format!("deposit,1,1,{}", amount)).unwrap();
+            assert_eq!(transaction.amount, Some(amount.parse().unwrap()));
+            let precision = Precision {
+                scale,
+                ..Default::default()
+            };
+            assert_eq!(
+                precision.apply(transaction.amount.unwrap()).unwrap(),
+                expected,
+                "{} at scale {}",
+                amount,
+                scale
+            );
+        }
+    }
+
     #[test]
     fn test_account_row_serialization() {
-        let row = AccountRow {
+        let row = || AccountRow {
             client: 1,
             available: dec!(1.23456),
             held: dec!(2.34567),
             total: dec!(3.58003),
             locked: false,
+            flagged: None,
+            receivable: None,
         };
 
+        let cases = [
+            (
+                4,
+                "client,available,held,total,locked\n1,1.2345,2.3456,3.5800,false\n",
+            ),
+            (
+                2,
+                "client,available,held,total,locked\n1,1.23,2.34,3.58,false\n",
+            ),
+        ];
+        for (scale, expected) in cases {
+            let precision = Precision {
+                scale,
+                ..Default::default()
+            };
+            let mut wtr = csv::Writer::from_writer(vec![]);
+            wtr.serialize(row().rounded(&precision)).unwrap();
+            let csv_output = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+            assert_eq!(csv_output, expected, "scale {}", scale);
+        }
+        let row = AccountRow {
+            flagged: Some(true),
+            receivable: Some(dec!(0.50001)),
+            ..row()
+        }
+        .rounded(&Precision::default());
         let mut wtr = csv::Writer::from_writer(vec![]);
         wtr.serialize(&row).unwrap();
         let csv_output = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
         assert_eq!(
             csv_output,
-            "client,available,held,total,locked\n1,1.2345,2.3456,3.5800,false\n"
+            "client,available,held,total,locked,flagged,receivable\n1,1.2345,2.3456,3.5800,false,true,0.5000\n"
+        );
+    }
+
+    #[test]
+    fn test_outcome_row_serialization() {
+        let transaction = parse_csv_row("withdrawal,2,5,3.0").unwrap();
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(OutcomeRow::new(6, &transaction, &Ok(())))
+            .unwrap();
+        wtr.serialize(OutcomeRow::new(
+            6,
+            &transaction,
+            &Err(Error::InsufficientFunds),
+        ))
+        .unwrap();
+        let csv_output = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            csv_output,
+            "line,type,client,tx,status,reason\n\
+             6,withdrawal,2,5,accepted,\n\
+             6,withdrawal,2,5,rejected,insufficient_funds\n"
         );
     }
 
@@ -241,7 +448,12 @@ mod tests {
                     id: 1,
                     available: dec!(100.5),
                     held: dec!(0.0),
+                    receivable: dec!(0.0),
                     locked: false,
+                    flagged: false,
+                    deposits: 0,
+                    chargebacks: 0,
+                    postings: Default::default(),
                 },
                 AccountRow {
                     client: 1,
@@ -249,6 +461,8 @@ mod tests {
                     held: dec!(0.0),
                     total: dec!(100.5),
                     locked: false,
+                    flagged: None,
+                    receivable: None,
                 },
             ),
             // Case with both available and held funds
@@ -257,7 +471,12 @@ mod tests {
                     id: 2,
                     available: dec!(50.25),
                     held: dec!(25.25),
+                    receivable: dec!(0.0),
                     locked: false,
+                    flagged: false,
+                    deposits: 0,
+                    chargebacks: 0,
+                    postings: Default::default(),
                 },
                 AccountRow {
                     client: 2,
@@ -265,6 +484,8 @@ mod tests {
                     held: dec!(25.25),
                     total: dec!(75.50),
                     locked: false,
+                    flagged: None,
+                    receivable: None,
                 },
             ),
             // Locked account case
@@ -273,7 +494,12 @@ mod tests {
                     id: 3,
                     available: dec!(-50.0),
                     held: dec!(0.0),
+                    receivable: dec!(0.0),
                     locked: true,
+                    flagged: false,
+                    deposits: 0,
+                    chargebacks: 0,
+                    postings: Default::default(),
                 },
                 AccountRow {
                     client: 3,
@@ -281,6 +507,8 @@ mod tests {
                     held: dec!(0.0),
                     total: dec!(-50.0),
                     locked: true,
+                    flagged: None,
+                    receivable: None,
                 },
             ),
             // Zero balance case
@@ -289,7 +517,12 @@ mod tests {
                     id: 4,
                     available: dec!(0.0),
                     held: dec!(0.0),
+                    receivable: dec!(0.0),
                     locked: false,
+                    flagged: false,
+                    deposits: 0,
+                    chargebacks: 0,
+                    postings: Default::default(),
                 },
                 AccountRow {
                     client: 4,
@@ -297,6 +530,8 @@ mod tests {
                     held: dec!(0.0),
                     total: dec!(0.0),
                     locked: false,
+                    flagged: None,
+                    receivable: None,
                 },
             ),
             // High precision case
@@ -305,7 +540,12 @@ mod tests {
                     id: 5,
                     available: dec!(100.1234),
                     held: dec!(50.5678),
+                    receivable: dec!(0.0),
                     locked: true,
+                    flagged: false,
+                    deposits: 0,
+                    chargebacks: 0,
+                    postings: Default::default(),
                 },
                 AccountRow {
                     client: 5,
@@ -313,6 +553,8 @@ mod tests {
                     held: dec!(50.5678),
                     total: dec!(150.6912),
                     locked: true,
+                    flagged: None,
+                    receivable: None,
                 },
             ),
         ];
@@ -324,6 +566,7 @@ mod tests {
             assert_eq!(row.held, expected_row.held);
             assert_eq!(row.total, expected_row.total);
             assert_eq!(row.locked, expected_row.locked);
+            assert_eq!(row.receivable, expected_row.receivable);
         }
     }
 }
@@ -0,0 +1,339 @@
+//! Aging of open disputes.
+//!
+//! Every dispute remembers when it was opened, counted in transactions processed by the
+//! engine, and as the latest transaction timestamp if transactions have timestamps.
+//! Under a [`DisputeAging`] policy, disputes are resolved or charged back automatically
+//! once the configured number of transactions was processed after them, or the
+//! configured duration elapsed since them, so that funds do not stay held indefinitely.
+//! [`Engine::open_disputes`] reports the disputes still open with their age, whether a
+//! policy is set or not.
+//!
+//! The clock of durations is the latest timestamp seen, so a transaction with an earlier
+//! timestamp than a previous one does not turn it back.
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use super::{AgingThreshold, Balances, DisputeAging, Engine};
+use crate::dto::{ClientId, Transaction, TransactionType, TxId};
+use crate::stores::{DisputeOpened, LedgerEntry};
+
+/// Transaction applied by the engine, with the ledger entry it posted and the balances
+/// of the client's account before and after it.
+pub(super) type Applied = (Transaction, LedgerEntry, Balances, Balances);
+
+/// A dispute that is still open, as reported by [`Engine::open_disputes`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OpenDispute {
+    pub client: ClientId,
+    pub tx: TxId,
+    /// Amount of the disputed deposit
+    pub amount: Decimal,
+    /// Number of transactions processed since the dispute
+    pub age_transactions: u64,
+    /// Seconds elapsed since the dispute, measured between transaction timestamps.
+    /// `None` if no transaction had a timestamp when it was opened.
+    pub age_seconds: Option<u64>,
+}
+
+impl Engine {
+    /// Returns an iterator over the disputes that are still open, oldest first.
+    pub fn open_disputes(&self) -> impl Iterator<Item = OpenDispute> + '_ {
+        self.transactions
+            .open_disputes()
+            .map(|(tx, deposit, opened)| OpenDispute {
+                client: deposit.client,
+                tx,
+                amount: deposit.amount,
+                age_transactions: self.processed - opened.processed,
+                age_seconds: self.age_seconds(opened),
+            })
+    }
+
+    /// Advances the clocks of dispute aging to a transaction about to be processed.
+    pub(super) fn tick(&mut self, transaction: &Transaction) {
+        self.processed += 1;
+        self.now = self.now.max(transaction.timestamp);
+    }
+
+    /// Returns when a dispute opened now was opened.
+    pub(super) fn dispute_opened(&self) -> DisputeOpened {
+        DisputeOpened {
+            processed: self.processed,
+            timestamp: self.now,
+        }
+    }
+
+    /// Returns the age of a dispute in transactions or seconds, as counted by the threshold,
+    /// or `None` if it cannot be measured in seconds for lack of timestamps.
+    fn age(&self, opened: DisputeOpened, after: AgingThreshold) -> Option<u64> {
+        match after {
+            AgingThreshold::Transactions(_) => Some(self.processed - opened.processed),
+            AgingThreshold::Seconds(_) => self.age_seconds(opened),
+        }
+    }
+
+    /// Returns the seconds elapsed since a dispute, or `None` without timestamps.
+    fn age_seconds(&self, opened: DisputeOpened) -> Option<u64> {
+        Some(self.now? - opened.timestamp?)
+    }
+
+    /// Returns the resolves and chargebacks applied under the [`DisputeAging`] policy while
+    /// processing the last transaction or batch, in the order they were applied.
+    pub fn aged(&self) -> &[Transaction] {
+        &self.aged
+    }
+
+    /// Closes the disputes that are too old under the [`DisputeAging`] policy, if any,
+    /// returning the transactions applied to close them.
+    ///
+    /// Aged disputes are closed even if the account was locked after they were opened.
+    pub(super) fn age_disputes(&mut self) -> Vec<Applied> {
+        let Some(DisputeAging { after, action }) = self.config.dispute_aging else {
+            return Vec::new();
+        };
+        // Disputes are ordered by when they were opened, so the ones without a timestamp
+        // come before all others
+        let expired: Vec<_> = self
+            .transactions
+            .open_disputes()
+            .skip_while(|(_, _, opened)| self.age(*opened, after).is_none())
+            .take_while(|(_, _, opened)| {
+                self.age(*opened, after)
+                    .is_some_and(|age| age >= after.value())
+            })
+            .map(|(tx, deposit, _)| Transaction {
+                tx_type: action.tx_type(),
+                client: deposit.client,
+                tx,
+                amount: None,
+                timestamp: None,
+            })
+            .collect();
+
+        let mut applied = Vec::with_capacity(expired.len());
+        for transaction in expired {
+            let (client, tx) = (transaction.client, transaction.tx);
+            let before = Balances::of(self.accounts.get(client));
+            let planned = match transaction.tx_type {
+                TransactionType::Resolve => self.plan_resolve(client, tx),
+                _ => self.plan_chargeback(client, tx),
+            };
+            let Ok(entry) = planned else {
+                continue;
+            };
+            self.record(&transaction);
+            if self.commit(transaction.tx_type, client, entry).is_err() {
+                continue;
+            }
+            let after = Balances::of(self.accounts.get(client));
+            self.aged.push(transaction.clone());
+            applied.push((transaction, entry, before, after));
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgingAction, Event, TransactionType};
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    fn transaction(tx_type: TransactionType, client: ClientId, tx: TxId) -> Transaction {
+        let amount = matches!(
+            tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        )
+        .then_some(dec!(10));
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+            timestamp: None,
+        }
+    }
+
+    fn aging(after: u64, action: AgingAction) -> Engine {
+        Engine::builder()
+            .dispute_aging(DisputeAging {
+                after: AgingThreshold::Transactions(after),
+                action,
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_open_disputes() {
+        let mut engine = Engine::new();
+        for tx in 1..=3 {
+            let _ = engine.process_transaction(transaction(TransactionType::Deposit, 1, tx));
+        }
+        let _ = engine.process_transaction(transaction(TransactionType::Dispute, 1, 2));
+        let _ = engine.process_transaction(transaction(TransactionType::Dispute, 1, 3));
+        let _ = engine.process_transaction(transaction(TransactionType::Dispute, 1, 1));
+        let _ = engine.process_transaction(transaction(TransactionType::Resolve, 1, 3));
+        // Rejected transactions count too
+        let _ = engine.process_transaction(transaction(TransactionType::Resolve, 1, 9));
+
+        let open: Vec<_> = engine
+            .open_disputes()
+            .map(|dispute| (dispute.tx, dispute.age_transactions, dispute.age_seconds))
+            .collect();
+        assert_eq!(open, [(2, 4, None), (1, 2, None)]);
+        assert!(engine.aged().is_empty());
+    }
+
+    #[test]
+    fn test_dispute_aging_resolves() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let published = Arc::clone(&events);
+        let mut engine = Engine::builder()
+            .dispute_aging(DisputeAging {
+                after: AgingThreshold::Transactions(2),
+                action: AgingAction::Resolve,
+            })
+            .subscribe(move |event: &Event| published.lock().unwrap().push(event.clone()))
+            .build();
+        let _ = engine.process_transaction(transaction(TransactionType::Deposit, 1, 1));
+        let _ = engine.process_transaction(transaction(TransactionType::Dispute, 1, 1));
+        let _ = engine.process_transaction(transaction(TransactionType::Deposit, 2, 2));
+        assert!(engine.aged().is_empty());
+        assert_eq!(engine.open_disputes().count(), 1);
+
+        // The second transaction after the dispute closes it
+        let _ = engine.process_transaction(transaction(TransactionType::Deposit, 2, 3));
+        assert_eq!(engine.aged(), [transaction(TransactionType::Resolve, 1, 1)]);
+        assert_eq!(engine.open_disputes().count(), 0);
+        let account = engine.accounts.get(1).unwrap();
+        assert_eq!((account.available, account.held), (dec!(10), dec!(0)));
+        assert_eq!(
+            events.lock().unwrap().last(),
+            Some(&Event::FundsReleased {
+                client: 1,
+                tx: 1,
+                amount: dec!(10)
+            })
+        );
+
+        let _ = engine.process_transaction(transaction(TransactionType::Deposit, 2, 4));
+        assert!(engine.aged().is_empty());
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_dispute_aging_by_duration() {
+        let mut engine = Engine::builder()
+            .dispute_aging(DisputeAging {
+                after: AgingThreshold::Seconds(60),
+                action: AgingAction::Resolve,
+            })
+            .build();
+        let at = |transaction: Transaction, timestamp| Transaction {
+            timestamp,
+            ..transaction
+        };
+        for tx in 1..=3 {
+            let _ = engine.process_transaction(transaction(TransactionType::Deposit, 1, tx));
+        }
+        // Opened before any timestamp, so it never ages by duration
+        let _ = engine.process_transaction(transaction(TransactionType::Dispute, 1, 1));
+        let _ =
+            engine.process_transaction(at(transaction(TransactionType::Dispute, 1, 2), Some(100)));
+        let _ =
+            engine.process_transaction(at(transaction(TransactionType::Dispute, 1, 3), Some(130)));
+
+        // Transactions without a timestamp, or an earlier one, do not move the clock
+        let _ = engine.process_transaction(transaction(TransactionType::Deposit, 2, 4));
+        let _ =
+            engine.process_transaction(at(transaction(TransactionType::Deposit, 2, 5), Some(50)));
+        let _ =
+            engine.process_transaction(at(transaction(TransactionType::Deposit, 2, 6), Some(159)));
+        assert!(engine.aged().is_empty());
+        let ages: Vec<_> = engine
+            .open_disputes()
+            .map(|dispute| (dispute.tx, dispute.age_seconds))
+            .collect();
+        assert_eq!(ages, [(1, None), (2, Some(59)), (3, Some(29))]);
+
+        let _ =
+            engine.process_transaction(at(transaction(TransactionType::Deposit, 2, 7), Some(160)));
+        assert_eq!(engine.aged(), [transaction(TransactionType::Resolve, 1, 2)]);
+
+        // Rolling back a batch turns the clock back too
+        let batch = [at(
+            transaction(TransactionType::Withdrawal, 3, 8),
+            Some(1000),
+        )];
+        assert!(engine.process_batch(batch).is_err());
+        let _ =
+            engine.process_transaction(at(transaction(TransactionType::Deposit, 2, 9), Some(189)));
+        assert!(engine.aged().is_empty());
+        let _ =
+            engine.process_transaction(at(transaction(TransactionType::Deposit, 2, 10), Some(190)));
+        assert_eq!(engine.aged(), [transaction(TransactionType::Resolve, 1, 3)]);
+
+        let open: Vec<_> = engine.open_disputes().map(|dispute| dispute.tx).collect();
+        assert_eq!(open, [1]);
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_dispute_aging_charges_back_locked_accounts() {
+        let mut engine = aging(3, AgingAction::Chargeback);
+        for tx in 1..=2 {
+            let _ = engine.process_transaction(transaction(TransactionType::Deposit, 1, tx));
+        }
+        let _ = engine.process_transaction(transaction(TransactionType::Dispute, 1, 1));
+        let _ = engine.process_transaction(transaction(TransactionType::Dispute, 1, 2));
+        let _ = engine.process_transaction(transaction(TransactionType::Chargeback, 1, 2));
+        assert!(engine.accounts.get(1).unwrap().locked);
+
+        // The dispute is charged back although the account was locked since
+        let _ = engine.process_transaction(transaction(TransactionType::Deposit, 2, 3));
+        assert_eq!(
+            engine.aged(),
+            [transaction(TransactionType::Chargeback, 1, 1)]
+        );
+        let account = engine.accounts.get(1).unwrap();
+        assert_eq!((account.total(), account.chargebacks), (dec!(0), 2));
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_dispute_aging_in_batch() {
+        let mut engine = aging(1, AgingAction::Chargeback);
+        let _ = engine.process_transaction(transaction(TransactionType::Deposit, 1, 1));
+        let savepoint = engine.savepoint();
+
+        let batch = [
+            transaction(TransactionType::Dispute, 1, 1),
+            transaction(TransactionType::Deposit, 2, 2),
+        ];
+        assert_eq!(engine.process_batch(batch.clone()), Ok(2));
+        assert_eq!(
+            engine.aged(),
+            [transaction(TransactionType::Chargeback, 1, 1)]
+        );
+        assert!(engine.accounts.get(1).unwrap().locked);
+
+        // A rejected batch rolls back its aged disputes too
+        engine.rollback_to(savepoint).unwrap();
+        let rejected = [
+            transaction(TransactionType::Dispute, 1, 1),
+            transaction(TransactionType::Deposit, 2, 2),
+            transaction(TransactionType::Withdrawal, 3, 3),
+        ];
+        assert_eq!(engine.process_batch(rejected).unwrap_err().index, 2);
+        assert!(engine.aged().is_empty());
+        assert!(!engine.accounts.get(1).unwrap().locked);
+        assert_eq!(engine.open_disputes().count(), 0);
+        assert_eq!(engine.verify(), Ok(()));
+
+        // The rolled back transactions are not counted
+        assert_eq!(engine.process_batch(batch), Ok(2));
+        assert_eq!(engine.aged().len(), 1);
+    }
+}
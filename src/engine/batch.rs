@@ -0,0 +1,191 @@
+//! All-or-nothing batch processing.
+//!
+//! [`Engine::process_batch`] applies a batch of transactions under an undo mark. If any
+//! transaction is rejected, the whole batch is rolled back, so that partner batches that
+//! must apply together never apply partially.
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use super::aging::Applied;
+use super::{Balances, Engine};
+use crate::{Error, Transaction};
+
+/// Returned by [`Engine::process_batch`] when a transaction of the batch was rejected,
+/// and the batch was rolled back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchError {
+    /// Position of the rejected transaction in the batch, starting at 0
+    pub index: usize,
+    pub transaction: Transaction,
+    /// Reason the transaction was rejected
+    pub error: Error,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "batch rolled back: transaction {} (tx {}) was rejected: {}",
+            self.index, self.transaction.tx, self.error
+        )
+    }
+}
+
+impl StdError for BatchError {}
+
+impl Engine {
+    /// Processes a batch of transactions atomically: either every transaction is applied,
+    /// or none is. Changes to accounts, deposits and processed transaction IDs are staged
+    /// as the batch is applied, and rolled back together on the first rejection.
+    ///
+    /// The before-hooks of the middleware run for every transaction as it is applied, and a
+    /// rejection by any of them also rolls back the batch. The change feed, event sinks and
+    /// after-hooks are only notified once the whole batch applied, and are not notified at
+    /// all for a rolled back batch. Disputes closed by aging (see [`Engine::aged`]) are part
+    /// of the batch, and rolled back with it.
+    ///
+    /// Returns the number of transactions applied.
+    ///
+    /// # Errors
+    /// Returns a [`BatchError`] with the position of the transaction that caused the rollback.
+    pub fn process_batch<I>(&mut self, transactions: I) -> Result<usize, BatchError>
+    where
+        I: IntoIterator<Item = Transaction>,
+    {
+        self.aged.clear();
+        let mark = self.open_mark();
+        let listen = self.has_listeners() || !self.middleware.is_empty();
+        let mut staged: Vec<Applied> = Vec::new();
+        let mut count = 0;
+        for (index, transaction) in transactions.into_iter().enumerate() {
+            self.tick(&transaction);
+            let before = Balances::of(self.accounts.get(transaction.client));
+            match self
+                .run_before_hooks(&transaction)
+                .and_then(|()| self.apply(&transaction))
+            {
+                Ok(entry) if listen => {
+                    let after = Balances::of(self.accounts.get(transaction.client));
+                    staged.push((transaction, entry, before, after));
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    self.rollback_mark(mark);
+                    return Err(BatchError {
+                        index,
+                        transaction,
+                        error,
+                    });
+                }
+            }
+            count += 1;
+            let aged = self.age_disputes();
+            if listen {
+                staged.extend(aged);
+            }
+        }
+        self.release_mark(mark);
+
+        for (transaction, entry, before, after) in staged {
+            self.notify(&transaction, &Ok(entry), before, after);
+            self.run_after_hooks(&transaction, Ok(()));
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientId, Event, TransactionType, TxId};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    fn transaction(
+        tx_type: TransactionType,
+        client: ClientId,
+        tx: TxId,
+        amount: Option<Decimal>,
+    ) -> Transaction {
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+            timestamp: None,
+        }
+    }
+
+    fn balances(engine: &Engine) -> Vec<(ClientId, Decimal, Decimal, bool)> {
+        let mut balances: Vec<_> = engine
+            .accounts()
+            .map(|account| (account.id, account.available, account.held, account.locked))
+            .collect();
+        balances.sort();
+        balances
+    }
+
+    #[test]
+    fn test_batch_commits() {
+        let mut engine = Engine::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        engine.subscribe(move |event: &Event| sink.lock().unwrap().push(event.clone()));
+
+        let applied = engine.process_batch([
+            transaction(TransactionType::Deposit, 1, 1, Some(dec!(10))),
+            transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(4))),
+            transaction(TransactionType::Dispute, 1, 1, None),
+        ]);
+        assert_eq!(applied, Ok(3));
+        assert_eq!(balances(&engine), [(1, dec!(-4), dec!(10), false)]);
+        assert_eq!(events.lock().unwrap().len(), 3);
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_batch_rolls_back() {
+        let mut engine = Engine::with_config(crate::EngineConfig {
+            keep_journal: true,
+            ..Default::default()
+        });
+        engine
+            .process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10))))
+            .unwrap();
+        let deltas = engine.change_channel();
+        let trial_balance = engine.trial_balance();
+
+        let batch = [
+            transaction(TransactionType::Deposit, 2, 2, Some(dec!(5))),
+            transaction(TransactionType::Dispute, 1, 1, None),
+            transaction(TransactionType::Chargeback, 1, 1, None),
+            transaction(TransactionType::Withdrawal, 2, 3, Some(dec!(1))),
+            transaction(TransactionType::Withdrawal, 2, 4, Some(dec!(5))),
+        ];
+        let err = engine.process_batch(batch.clone()).unwrap_err();
+        assert_eq!(err.index, 4);
+        assert_eq!(err.error, Error::InsufficientFunds);
+        assert_eq!(
+            err.to_string(),
+            "batch rolled back: transaction 4 (tx 4) was rejected: insufficient available funds (insufficient_funds)"
+        );
+
+        // Nothing of the batch remains, and nothing was published
+        assert_eq!(balances(&engine), [(1, dec!(10), dec!(0), false)]);
+        assert_eq!(engine.trial_balance(), trial_balance);
+        assert_eq!(engine.journal().len(), 1);
+        assert!(deltas.try_recv().is_err());
+        assert_eq!(engine.verify(), Ok(()));
+
+        // Rolled back transaction IDs and deposits can be used again
+        assert_eq!(engine.process_batch(batch[..4].to_vec()), Ok(4));
+        assert_eq!(
+            balances(&engine),
+            [(1, dec!(0), dec!(0), true), (2, dec!(4), dec!(0), false)]
+        );
+        assert_eq!(deltas.try_iter().count(), 4);
+        assert_eq!(engine.verify(), Ok(()));
+    }
+}
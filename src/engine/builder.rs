@@ -0,0 +1,171 @@
+//! Builder of an [`Engine`] with its configuration and extensions.
+
+use super::{
+    ChargebackPolicy, DisputeAging, DuplicatePolicy, Engine, EngineConfig, EngineConfigError,
+    EventSink, Limits, NegativeBalancePolicy, Precision, StoreConfig, TransactionMiddleware,
+    TxNamespace,
+};
+
+/// Builder of an [`Engine`], created with [`Engine::builder`].
+///
+/// Starts from the default [`EngineConfig`], or from a whole one set with
+/// [`config`](Self::config), e.g. loaded with [`EngineConfig::load`]. The other setters
+/// override single policies of the configuration.
+#[derive(Default)]
+pub struct EngineBuilder {
+    config: EngineConfig,
+    middleware: Vec<Box<dyn TransactionMiddleware + Send>>,
+    sinks: Vec<Box<dyn EventSink + Send>>,
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the [`EngineConfig`], replacing the default one.
+    pub fn config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.config.precision = precision;
+        self
+    }
+
+    pub fn keep_journal(mut self, keep_journal: bool) -> Self {
+        self.config.keep_journal = keep_journal;
+        self
+    }
+
+    pub fn tx_namespace(mut self, tx_namespace: TxNamespace) -> Self {
+        self.config.tx_namespace = tx_namespace;
+        self
+    }
+
+    pub fn duplicate_policy(mut self, duplicate_policy: DuplicatePolicy) -> Self {
+        self.config.duplicate_policy = duplicate_policy;
+        self
+    }
+
+    pub fn negative_balance_policy(mut self, policy: NegativeBalancePolicy) -> Self {
+        self.config.negative_balance_policy = policy;
+        self
+    }
+
+    pub fn chargeback_policy(mut self, policy: ChargebackPolicy) -> Self {
+        self.config.chargeback_policy = policy;
+        self
+    }
+
+    pub fn dispute_aging(mut self, aging: DisputeAging) -> Self {
+        self.config.dispute_aging = Some(aging);
+        self
+    }
+
+    pub fn store(mut self, store: StoreConfig) -> Self {
+        self.config.store = store;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    /// Registers a [`TransactionMiddleware`], run after the ones registered before it.
+    pub fn middleware(mut self, middleware: impl TransactionMiddleware + Send + 'static) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Subscribes a sink to the engine's events (see [`Engine::subscribe`]).
+    pub fn subscribe(mut self, sink: impl EventSink + Send + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Builds the engine.
+    ///
+    /// # Panics
+    /// Panics if the configuration is invalid (see [`EngineConfig::validate`]). Use
+    /// [`try_build`](Self::try_build) to handle the error instead.
+    pub fn build(self) -> Engine {
+        self.try_build().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Builds the engine, checking its configuration first.
+    ///
+    /// # Errors
+    /// Returns [`EngineConfigError::Invalid`] if the configuration is invalid.
+    pub fn try_build(self) -> Result<Engine, EngineConfigError> {
+        self.config.validate()?;
+        let mut engine = Engine::with_config(self.config);
+        engine.middleware = self.middleware;
+        engine.sinks = self.sinks;
+        Ok(engine)
+    }
+}
+
+impl Engine {
+    /// Returns a builder of an engine with its configuration, middleware and event sinks.
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ProcessedIds, Rounding};
+
+    #[test]
+    fn test_setters_override_config() {
+        let engine = Engine::builder()
+            .duplicate_policy(DuplicatePolicy::Seen)
+            .config(EngineConfig {
+                keep_journal: true,
+                ..Default::default()
+            })
+            .tx_namespace(TxNamespace::PerClient)
+            .precision(Precision {
+                rounding: Rounding::HalfUp,
+                ..Default::default()
+            })
+            .store(StoreConfig {
+                processed_ids: ProcessedIds::Bitmap,
+            })
+            .limits(Limits {
+                max_accounts: Some(100),
+                ..Default::default()
+            })
+            .build();
+        let config = engine.config();
+        // Setting a whole config replaces the policies set before it
+        assert_eq!(config.duplicate_policy, DuplicatePolicy::Accepted);
+        assert!(config.keep_journal);
+        assert_eq!(config.tx_namespace, TxNamespace::PerClient);
+        assert_eq!(config.precision.rounding, Rounding::HalfUp);
+        assert_eq!(config.store.processed_ids, ProcessedIds::Bitmap);
+        assert_eq!(config.limits.max_accounts, Some(100));
+        assert_eq!(config.limits.max_amount, None);
+    }
+
+    #[test]
+    fn test_rejects_invalid_config() {
+        let result = Engine::builder()
+            .precision(Precision {
+                scale: 29,
+                ..Default::default()
+            })
+            .try_build();
+        assert_eq!(
+            result.err().map(|err| err.to_string()),
+            Some(
+                "invalid engine config: scale 29 exceeds the 28 decimal places supported"
+                    .to_string()
+            )
+        );
+    }
+}
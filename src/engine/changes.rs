@@ -0,0 +1,72 @@
+//! Change feed of the [`Engine`](super::Engine).
+//!
+//! When a feed is registered, the engine emits an [`AccountDelta`] after every accepted
+//! transaction, so that other systems can mirror balances incrementally instead of
+//! reloading the full output.
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::dto::{ClientId, TxId};
+use crate::stores::Account;
+
+/// Balances, lock and review state of an account at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Balances {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub locked: bool,
+    /// Only serialized once a chargeback flagged the account for review
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub flagged: bool,
+}
+
+impl Balances {
+    /// Balances of the account, or zero balances if it does not exist yet.
+    pub(super) fn of(account: Option<&Account>) -> Self {
+        account.map_or_else(Self::default, |account| Self {
+            available: account.available,
+            held: account.held,
+            locked: account.locked,
+            flagged: account.flagged,
+        })
+    }
+}
+
+/// Change of a single account caused by an accepted transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountDelta {
+    pub client: ClientId,
+    /// Transaction that caused the change
+    pub tx: TxId,
+    pub before: Balances,
+    pub after: Balances,
+}
+
+/// Callback receiving the deltas of the change feed.
+pub(super) type ChangeFeed = Box<dyn FnMut(AccountDelta) + Send>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_delta_serialization() {
+        let delta = AccountDelta {
+            client: 1,
+            tx: 2,
+            before: Balances::default(),
+            after: Balances {
+                available: dec!(1.5),
+                held: dec!(0),
+                locked: false,
+                flagged: false,
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&delta).unwrap(),
+            r#"{"client":1,"tx":2,"before":{"available":"0","held":"0","locked":false},"after":{"available":"1.5","held":"0","locked":false}}"#
+        );
+    }
+}
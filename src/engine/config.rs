@@ -0,0 +1,832 @@
+//! Configuration of the [`Engine`](super::Engine).
+//!
+//! An [`EngineConfig`] can be built in code, or loaded from a TOML policy file, e.g.:
+//!
+//! ```toml
+//! keep_journal = false
+//! tx_namespace = "per-client"
+//! duplicate_policy = "seen"
+//! negative_balance_policy = "hold-available"
+//! chargeback_policy = { lock-after = 3 }
+//!
+//! [precision]
+//! scale = 4
+//! rounding = "half-even"
+//! strict = true
+//!
+//! [dispute_aging]
+//! after = "30d"
+//! action = "chargeback"
+//!
+//! [store]
+//! processed_ids = "bitmap"
+//!
+//! [limits]
+//! max_amount = 1000000
+//! max_accounts = 65536
+//! max_transactions = 10000000
+//! max_open_disputes = 1000
+//! ```
+//!
+//! Every key is optional and defaults to today's behaviour, and unknown keys are rejected.
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fmt, fs, io};
+
+use crate::dto::{TransactionType, MAX_SCALE};
+use crate::stores::{Account, ProcessedIds, TxNamespace};
+use crate::Error;
+
+/// Rounding strategy for amounts with more decimal places than the configured scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rounding {
+    /// Drop the extra decimal places, e.g. `1.23456` becomes `1.2345` at 4 dp.
+    #[default]
+    #[serde(alias = "truncate")]
+    ToZero,
+    /// Round half to even (banker's rounding), e.g. `1.00005` becomes `1.0000` at 4 dp.
+    #[serde(alias = "bankers")]
+    HalfEven,
+    /// Round half away from zero, e.g. `1.00005` becomes `1.0001` at 4 dp.
+    HalfUp,
+}
+
+impl Rounding {
+    fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::ToZero => RoundingStrategy::ToZero,
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "to-zero" | "truncate" => Ok(Rounding::ToZero),
+            "half-even" | "bankers" => Ok(Rounding::HalfEven),
+            "half-up" => Ok(Rounding::HalfUp),
+            other => Err(format!("unknown rounding strategy: {}", other)),
+        }
+    }
+}
+
+/// Which deposit and withdrawal IDs are used up, so that the same ID is rejected
+/// as a duplicate later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    /// Only IDs of accepted transactions are used up, so a rejected transaction can be
+    /// retried with the same ID.
+    #[default]
+    Accepted,
+    /// Every ID seen is used up. A transaction reusing the ID of a rejected one is
+    /// rejected with the reason of the first attempt.
+    Seen,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "accepted" => Ok(DuplicatePolicy::Accepted),
+            "seen" => Ok(DuplicatePolicy::Seen),
+            other => Err(format!("unknown duplicate policy: {}", other)),
+        }
+    }
+}
+
+/// What a dispute does when the client's available funds are less than the disputed amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NegativeBalancePolicy {
+    /// Hold the whole amount, leaving the available funds negative.
+    #[default]
+    Allow,
+    /// Reject the dispute with [`Error::InsufficientFundsForDispute`].
+    Reject,
+    /// Hold only the available funds, if any, and record the rest as a receivable
+    /// owed by the client. Resolving the dispute settles the receivable, while a
+    /// chargeback leaves it owed.
+    HoldAvailable,
+}
+
+impl FromStr for NegativeBalancePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(NegativeBalancePolicy::Allow),
+            "reject" => Ok(NegativeBalancePolicy::Reject),
+            "hold-available" => Ok(NegativeBalancePolicy::HoldAvailable),
+            other => Err(format!("unknown negative balance policy: {}", other)),
+        }
+    }
+}
+
+/// What a chargeback does to the client's account, besides removing the held funds.
+///
+/// A chargeback that does not lock the account flags it for review instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChargebackPolicy {
+    /// Lock the account on every chargeback.
+    #[default]
+    Lock,
+    /// Only flag the account for review, never lock it.
+    Flag,
+    /// Lock the account on its N-th chargeback.
+    LockAfter(u32),
+    /// Lock the account once its ratio of chargebacks to deposits is above the threshold,
+    /// e.g. `0.1` for more than one chargeback in ten deposits.
+    LockOverRatio(Decimal),
+}
+
+impl ChargebackPolicy {
+    /// Whether the account is locked, given its counts including the chargeback itself.
+    pub(crate) fn locks(self, account: &Account) -> bool {
+        match self {
+            ChargebackPolicy::Lock => true,
+            ChargebackPolicy::Flag => false,
+            ChargebackPolicy::LockAfter(count) => account.chargebacks >= count,
+            ChargebackPolicy::LockOverRatio(threshold) => {
+                let deposits = Decimal::from(account.deposits.max(1));
+                Decimal::from(account.chargebacks) / deposits > threshold
+            }
+        }
+    }
+}
+
+impl FromStr for ChargebackPolicy {
+    type Err = String;
+
+    /// Parses `lock`, `flag`, `lock-after=N` or `lock-over-ratio=R`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        let (name, value) = s.split_once('=').unwrap_or((&s, ""));
+        let invalid = || format!("invalid value for {}: '{}'", name, value);
+        match (name, value) {
+            ("lock", "") => Ok(ChargebackPolicy::Lock),
+            ("flag", "") => Ok(ChargebackPolicy::Flag),
+            ("lock-after", value) => value
+                .parse()
+                .map(ChargebackPolicy::LockAfter)
+                .map_err(|_| invalid()),
+            ("lock-over-ratio", value) => value
+                .parse()
+                .map(ChargebackPolicy::LockOverRatio)
+                .map_err(|_| invalid()),
+            _ => Err(format!("unknown chargeback policy: {}", s)),
+        }
+    }
+}
+
+/// How a dispute is closed once it is too old.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AgingAction {
+    Resolve,
+    Chargeback,
+}
+
+impl AgingAction {
+    pub(crate) fn tx_type(self) -> TransactionType {
+        match self {
+            AgingAction::Resolve => TransactionType::Resolve,
+            AgingAction::Chargeback => TransactionType::Chargeback,
+        }
+    }
+}
+
+/// How old a dispute gets before it is closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgingThreshold {
+    /// Number of transactions, accepted or rejected, processed after the dispute.
+    Transactions(u64),
+    /// Number of seconds elapsed since the dispute, measured with the transaction
+    /// timestamps. Disputes opened before any transaction had a timestamp never age.
+    Seconds(u64),
+}
+
+impl AgingThreshold {
+    /// Returns the number of transactions or seconds.
+    pub(crate) fn value(self) -> u64 {
+        match self {
+            AgingThreshold::Transactions(count) | AgingThreshold::Seconds(count) => count,
+        }
+    }
+}
+
+impl FromStr for AgingThreshold {
+    type Err = String;
+
+    /// Parses a number of transactions `N`, or a duration `Ns`, `Nm`, `Nh` or `Nd`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid dispute age: '{}', expected a number of transactions or a duration such as 7d",
+                s
+            )
+        };
+        let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (count, unit) = s.split_at(digits);
+        let count: u64 = count.parse().map_err(|_| invalid())?;
+        let seconds = match unit.to_ascii_lowercase().as_str() {
+            "" => return Ok(AgingThreshold::Transactions(count)),
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        count
+            .checked_mul(seconds)
+            .map(AgingThreshold::Seconds)
+            .ok_or_else(invalid)
+    }
+}
+
+impl<'de> Deserialize<'de> for AgingThreshold {
+    /// Deserializes a number of transactions, or a duration string (see [`FromStr`]).
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Transactions(u64),
+            Duration(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Transactions(count) => Ok(AgingThreshold::Transactions(count)),
+            Raw::Duration(duration) => duration.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
+/// Closes disputes automatically once they are older than a number of transactions, or
+/// than a duration when transactions have timestamps (see
+/// [`Engine::aged`](super::Engine::aged)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisputeAging {
+    /// Age after which a dispute is closed
+    pub after: AgingThreshold,
+    pub action: AgingAction,
+}
+
+impl FromStr for DisputeAging {
+    type Err = String;
+
+    /// Parses `resolve=AGE` or `chargeback=AGE`, where the age is a number of transactions
+    /// or a duration (see [`AgingThreshold`]).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        let (action, after) = s
+            .split_once('=')
+            .ok_or_else(|| format!("dispute aging needs an age: {}", s))?;
+        let action = match action {
+            "resolve" => AgingAction::Resolve,
+            "chargeback" => AgingAction::Chargeback,
+            other => return Err(format!("unknown dispute aging action: {}", other)),
+        };
+        Ok(DisputeAging {
+            after: after.parse()?,
+            action,
+        })
+    }
+}
+
+/// Decimal precision of transaction amounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Precision {
+    /// Number of decimal places kept, at most 28
+    #[serde(deserialize_with = "deserialize_scale")]
+    pub scale: u32,
+    pub rounding: Rounding,
+    /// Reject amounts with more decimal places than `scale`, instead of rounding them.
+    pub strict: bool,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Self {
+            scale: 4,
+            rounding: Rounding::ToZero,
+            strict: false,
+        }
+    }
+}
+
+impl Precision {
+    /// Checks that a scale fits the 28 decimal places supported by `Decimal`.
+    pub fn check_scale(scale: u32) -> Result<u32, String> {
+        if scale > MAX_SCALE {
+            return Err(format!(
+                "scale {} exceeds the {} decimal places supported",
+                scale, MAX_SCALE
+            ));
+        }
+        Ok(scale)
+    }
+
+    /// Brings an input amount to the configured scale.
+    /// Trailing zeros do not count as extra decimal places.
+    ///
+    /// # Errors
+    /// Returns [`Error::ExcessPrecision`] in strict mode if the amount would be rounded.
+    pub fn apply(&self, amount: Decimal) -> Result<Decimal, Error> {
+        let rounded = self.round(amount);
+        if self.strict && rounded != amount {
+            return Err(Error::ExcessPrecision);
+        }
+        Ok(rounded)
+    }
+
+    /// Rounds an amount to the configured scale, even in strict mode.
+    pub fn round(&self, amount: Decimal) -> Decimal {
+        amount.round_dp_with_strategy(self.scale, self.rounding.strategy())
+    }
+}
+
+fn deserialize_scale<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    Precision::check_scale(u32::deserialize(deserializer)?).map_err(D::Error::custom)
+}
+
+/// Choice of the in-memory stores behind the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    /// How the IDs of processed deposits and withdrawals are kept for duplicate detection.
+    pub processed_ids: ProcessedIds,
+}
+
+/// Upper bounds on amounts and on the state kept by the engine. There is no limit on
+/// what is `None`.
+///
+/// Transactions going over a limit are rejected, so a hostile or runaway input cannot
+/// make the engine grow without bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Largest deposit or withdrawal amount, after rounding to the precision.
+    /// Larger ones are rejected with [`Error::AmountOverLimit`].
+    pub max_amount: Option<Decimal>,
+    /// Number of accounts. Deposits opening more are rejected with
+    /// [`Error::AccountLimitReached`].
+    pub max_accounts: Option<u64>,
+    /// Number of deposit and withdrawal IDs kept for duplicate detection. Further ones are
+    /// rejected with [`Error::TransactionLimitReached`].
+    pub max_transactions: Option<u64>,
+    /// Number of disputes open at the same time. Further ones are rejected with
+    /// [`Error::OpenDisputeLimitReached`].
+    pub max_open_disputes: Option<u64>,
+}
+
+/// Options controlling how the [`Engine`](super::Engine) processes transactions.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Precision and rounding of deposit and withdrawal amounts.
+    pub precision: Precision,
+    /// Keep every ledger entry in memory (see [`Engine::journal`](super::Engine::journal)).
+    /// Costs about 28 bytes per accepted transaction, so it is off by default.
+    pub keep_journal: bool,
+    /// Scope in which transaction IDs must be unique, and disputes are looked up.
+    pub tx_namespace: TxNamespace,
+    /// Which transaction IDs count as used for duplicate detection.
+    pub duplicate_policy: DuplicatePolicy,
+    /// What a dispute does when the client's available funds do not cover it.
+    pub negative_balance_policy: NegativeBalancePolicy,
+    /// Whether a chargeback locks the account, or flags it for review.
+    pub chargeback_policy: ChargebackPolicy,
+    /// Closes disputes automatically once they are too old. Disputes stay open until
+    /// resolved or charged back if `None`.
+    pub dispute_aging: Option<DisputeAging>,
+    /// Choice of the stores behind the engine.
+    pub store: StoreConfig,
+    /// Upper bounds on amounts, accounts, transactions and open disputes.
+    pub limits: Limits,
+}
+
+impl EngineConfig {
+    /// Parses a configuration from the contents of a TOML policy file.
+    ///
+    /// # Errors
+    /// Returns a [`EngineConfigError`] if the TOML is malformed, has unknown keys, or
+    /// values of the wrong type.
+    pub fn from_toml(toml: &str) -> Result<Self, EngineConfigError> {
+        toml::from_str(toml).map_err(|err| EngineConfigError::Parse {
+            path: None,
+            source: err,
+        })
+    }
+
+    /// Checks the values that cannot be enforced by their types.
+    ///
+    /// # Errors
+    /// Returns [`EngineConfigError::Invalid`] if the precision scale exceeds 28 decimal places,
+    /// or the maximum amount is not positive.
+    pub fn validate(&self) -> Result<(), EngineConfigError> {
+        Precision::check_scale(self.precision.scale).map_err(EngineConfigError::Invalid)?;
+        if let Some(max_amount) = self.limits.max_amount {
+            if max_amount <= Decimal::ZERO {
+                return Err(EngineConfigError::Invalid(format!(
+                    "max amount {} is not positive",
+                    max_amount
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads a configuration from a TOML policy file.
+    ///
+    /// # Errors
+    /// Returns a [`EngineConfigError`] if the file cannot be read, or its contents are invalid.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EngineConfigError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|err| EngineConfigError::Io {
+            path: path.to_path_buf(),
+            source: err,
+        })?;
+        toml::from_str(&contents).map_err(|err| EngineConfigError::Parse {
+            path: Some(path.to_path_buf()),
+            source: err,
+        })
+    }
+}
+
+/// Returned when an [`EngineConfig`] cannot be loaded.
+#[derive(Debug)]
+pub enum EngineConfigError {
+    /// The policy file cannot be read.
+    Io { path: PathBuf, source: io::Error },
+    /// The policy file is not valid TOML, or does not describe an [`EngineConfig`].
+    Parse {
+        path: Option<PathBuf>,
+        source: toml::de::Error,
+    },
+    /// The configuration has an out-of-range value (see [`EngineConfig::validate`]).
+    Invalid(String),
+}
+
+impl fmt::Display for EngineConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineConfigError::Io { path, source } => {
+                write!(
+                    f,
+                    "cannot read engine config {}: {}",
+                    path.display(),
+                    source
+                )
+            }
+            EngineConfigError::Parse {
+                path: Some(path),
+                source,
+            } => write!(f, "invalid engine config {}: {}", path.display(), source),
+            EngineConfigError::Parse { path: None, source } => {
+                write!(f, "invalid engine config: {}", source)
+            }
+            EngineConfigError::Invalid(message) => write!(f, "invalid engine config: {}", message),
+        }
+    }
+}
+
+impl StdError for EngineConfigError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            EngineConfigError::Io { source, .. } => Some(source),
+            EngineConfigError::Parse { source, .. } => Some(source),
+            EngineConfigError::Invalid(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_rounding_strategies() {
+        let cases = [
+            (Rounding::ToZero, dec!(1.00005), dec!(1.0000)),
+            (Rounding::ToZero, dec!(0.123499999), dec!(0.1234)),
+            (Rounding::HalfEven, dec!(1.00005), dec!(1.0000)),
+            (Rounding::HalfEven, dec!(1.00015), dec!(1.0002)),
+            (Rounding::HalfUp, dec!(1.00005), dec!(1.0001)),
+        ];
+        for (rounding, amount, expected) in cases {
+            let precision = Precision {
+                rounding,
+                ..Default::default()
+            };
+            assert_eq!(precision.apply(amount).unwrap(), expected, "{:?}", rounding);
+        }
+    }
+
+    #[test]
+    fn test_custom_scale() {
+        let precision = Precision {
+            scale: 8,
+            ..Default::default()
+        };
+        assert_eq!(
+            precision.apply(dec!(0.123456789)).unwrap(),
+            dec!(0.12345678)
+        );
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let precision = Precision {
+            strict: true,
+            ..Default::default()
+        };
+        assert_eq!(precision.apply(dec!(1.2345)).unwrap(), dec!(1.2345));
+        // Trailing zeros are not extra decimal places
+        assert_eq!(precision.apply(dec!(1.23450000)).unwrap(), dec!(1.2345));
+        assert!(matches!(
+            precision.apply(dec!(1.23456)),
+            Err(Error::ExcessPrecision)
+        ));
+    }
+
+    #[test]
+    fn test_rounding_from_str() {
+        assert_eq!("half-even".parse(), Ok(Rounding::HalfEven));
+        assert_eq!("BANKERS".parse(), Ok(Rounding::HalfEven));
+        assert_eq!("truncate".parse(), Ok(Rounding::ToZero));
+        assert_eq!("half-up".parse(), Ok(Rounding::HalfUp));
+        assert!("ceiling".parse::<Rounding>().is_err());
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = EngineConfig::from_toml(
+            r#"
+            keep_journal = true
+            tx_namespace = "per-client"
+            duplicate_policy = "seen"
+            chargeback_policy = { lock-over-ratio = 0.25 }
+
+            [precision]
+            scale = 8
+            rounding = "bankers"
+
+            [dispute_aging]
+            after = "12h"
+            action = "resolve"
+
+            [store]
+            processed_ids = "bitmap"
+
+            [limits]
+            max_amount = "5000.50"
+            max_accounts = 10
+            max_open_disputes = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            EngineConfig {
+                precision: Precision {
+                    scale: 8,
+                    rounding: Rounding::HalfEven,
+                    strict: false,
+                },
+                keep_journal: true,
+                tx_namespace: TxNamespace::PerClient,
+                duplicate_policy: DuplicatePolicy::Seen,
+                negative_balance_policy: NegativeBalancePolicy::Allow,
+                chargeback_policy: ChargebackPolicy::LockOverRatio(dec!(0.25)),
+                dispute_aging: Some(DisputeAging {
+                    after: AgingThreshold::Seconds(12 * 60 * 60),
+                    action: AgingAction::Resolve,
+                }),
+                store: StoreConfig {
+                    processed_ids: ProcessedIds::Bitmap,
+                },
+                limits: Limits {
+                    max_amount: Some(dec!(5000.50)),
+                    max_accounts: Some(10),
+                    max_transactions: None,
+                    max_open_disputes: Some(2),
+                },
+            }
+        );
+        assert_eq!(
+            EngineConfig::from_toml("").unwrap(),
+            EngineConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_from_toml_rejects_unknown_keys() {
+        let err = EngineConfig::from_toml("keep_jornal = true").unwrap_err();
+        assert!(
+            err.to_string().contains("unknown field `keep_jornal`"),
+            "{}",
+            err
+        );
+
+        let err = EngineConfig::from_toml("[precision]\nround = \"half-up\"").unwrap_err();
+        assert!(err.to_string().contains("unknown field `round`"), "{}", err);
+
+        let err = EngineConfig::from_toml("[limits]\nmax_disputes = 1").unwrap_err();
+        assert!(
+            err.to_string().contains("unknown field `max_disputes`"),
+            "{}",
+            err
+        );
+
+        let err = EngineConfig::from_toml("[store]\nprocessed_ids = \"btree\"").unwrap_err();
+        assert!(
+            err.to_string().contains("unknown variant `btree`"),
+            "{}",
+            err
+        );
+
+        let err = EngineConfig::from_toml("duplicate_policy = \"never\"").unwrap_err();
+        assert!(
+            err.to_string().contains("unknown variant `never`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_scale_out_of_range() {
+        assert_eq!(Precision::check_scale(28), Ok(28));
+        assert_eq!(
+            Precision::check_scale(29),
+            Err("scale 29 exceeds the 28 decimal places supported".to_string())
+        );
+
+        let err = EngineConfig::from_toml("[precision]\nscale = 30").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("scale 30 exceeds the 28 decimal places supported"),
+            "{}",
+            err
+        );
+
+        let config = EngineConfig {
+            precision: Precision {
+                scale: 30,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(EngineConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_max_amount_not_positive() {
+        let config = EngineConfig::from_toml("[limits]\nmax_amount = 0").unwrap();
+        assert_eq!(
+            config.validate().map_err(|err| err.to_string()),
+            Err("invalid engine config: max amount 0 is not positive".to_string())
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let config = EngineConfig::load("data/engine.toml").unwrap();
+        assert_eq!(config.precision.rounding, Rounding::HalfEven);
+        assert!(config.precision.strict);
+        assert_eq!(config.duplicate_policy, DuplicatePolicy::Seen);
+        assert_eq!(config.store, StoreConfig::default());
+        assert_eq!(config.limits, Limits::default());
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let err = EngineConfig::load("data/does_not_exist.toml").unwrap_err();
+        assert!(matches!(err, EngineConfigError::Io { .. }));
+        assert!(err
+            .to_string()
+            .starts_with("cannot read engine config data/does_not_exist.toml"));
+    }
+
+    #[test]
+    fn test_duplicate_policy_from_str() {
+        assert_eq!("accepted".parse(), Ok(DuplicatePolicy::Accepted));
+        assert_eq!("SEEN".parse(), Ok(DuplicatePolicy::Seen));
+        assert!("never".parse::<DuplicatePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_negative_balance_policy_from_str() {
+        assert_eq!("allow".parse(), Ok(NegativeBalancePolicy::Allow));
+        assert_eq!("Reject".parse(), Ok(NegativeBalancePolicy::Reject));
+        assert_eq!(
+            "hold-available".parse(),
+            Ok(NegativeBalancePolicy::HoldAvailable)
+        );
+        assert!("clamp".parse::<NegativeBalancePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_chargeback_policy_from_str() {
+        assert_eq!("lock".parse(), Ok(ChargebackPolicy::Lock));
+        assert_eq!("FLAG".parse(), Ok(ChargebackPolicy::Flag));
+        assert_eq!("lock-after=3".parse(), Ok(ChargebackPolicy::LockAfter(3)));
+        assert_eq!(
+            "lock-over-ratio=0.1".parse(),
+            Ok(ChargebackPolicy::LockOverRatio(dec!(0.1)))
+        );
+        assert!("lock-after".parse::<ChargebackPolicy>().is_err());
+        assert!("lock-after=x".parse::<ChargebackPolicy>().is_err());
+        assert!("flag=1".parse::<ChargebackPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_dispute_aging_from_str() {
+        assert_eq!(
+            "resolve=100".parse(),
+            Ok(DisputeAging {
+                after: AgingThreshold::Transactions(100),
+                action: AgingAction::Resolve
+            })
+        );
+        assert_eq!(
+            "Chargeback=5".parse(),
+            Ok(DisputeAging {
+                after: AgingThreshold::Transactions(5),
+                action: AgingAction::Chargeback
+            })
+        );
+        assert_eq!(
+            "resolve=7D".parse(),
+            Ok(DisputeAging {
+                after: AgingThreshold::Seconds(7 * 24 * 60 * 60),
+                action: AgingAction::Resolve
+            })
+        );
+        assert!("resolve".parse::<DisputeAging>().is_err());
+        assert!("resolve=soon".parse::<DisputeAging>().is_err());
+        assert!("dispute=5".parse::<DisputeAging>().is_err());
+    }
+
+    #[test]
+    fn test_aging_threshold_from_str() {
+        assert_eq!("250".parse(), Ok(AgingThreshold::Transactions(250)));
+        assert_eq!("90s".parse(), Ok(AgingThreshold::Seconds(90)));
+        assert_eq!("30m".parse(), Ok(AgingThreshold::Seconds(30 * 60)));
+        assert_eq!("12h".parse(), Ok(AgingThreshold::Seconds(12 * 60 * 60)));
+        assert_eq!("7d".parse(), Ok(AgingThreshold::Seconds(7 * 24 * 60 * 60)));
+        assert_eq!(
+            "7w".parse::<AgingThreshold>(),
+            Err(
+                "invalid dispute age: '7w', expected a number of transactions or a duration such as 7d"
+                    .to_string()
+            )
+        );
+        assert!("d".parse::<AgingThreshold>().is_err());
+        assert!("-1".parse::<AgingThreshold>().is_err());
+        assert!(format!("{}d", u64::MAX).parse::<AgingThreshold>().is_err());
+
+        let config =
+            EngineConfig::from_toml("[dispute_aging]\nafter = 3\naction = \"chargeback\"").unwrap();
+        assert_eq!(
+            config.dispute_aging.map(|aging| aging.after),
+            Some(AgingThreshold::Transactions(3))
+        );
+        let err = EngineConfig::from_toml("[dispute_aging]\nafter = \"3w\"\naction = \"resolve\"")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("invalid dispute age: '3w'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_chargeback_policy_locks() {
+        let mut account = Account::new(1);
+        account.deposits = 10;
+        account.chargebacks = 2;
+        assert!(ChargebackPolicy::Lock.locks(&account));
+        assert!(!ChargebackPolicy::Flag.locks(&account));
+        assert!(ChargebackPolicy::LockAfter(2).locks(&account));
+        assert!(!ChargebackPolicy::LockAfter(3).locks(&account));
+        assert!(ChargebackPolicy::LockOverRatio(dec!(0.1)).locks(&account));
+        assert!(!ChargebackPolicy::LockOverRatio(dec!(0.2)).locks(&account));
+    }
+}
@@ -0,0 +1,102 @@
+//! Domain events published by the [`Engine`](super::Engine).
+//!
+//! Every processed transaction results in one or more [`Event`]s, published to the
+//! [`EventSink`]s subscribed to the engine. Sinks are the extension point for audit
+//! logs, metrics and notifications.
+
+use rust_decimal::Decimal;
+
+use crate::dto::{ClientId, TransactionType, TxId};
+use crate::Error;
+
+/// Domain event describing what a transaction did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Funds were deposited to the available balance.
+    DepositApplied {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    /// Funds were withdrawn from the available balance.
+    WithdrawalApplied {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    /// A deposit was disputed, moving its amount from available to held funds.
+    FundsHeld {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    /// A dispute was resolved, moving its amount from held back to available funds.
+    FundsReleased {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    /// A dispute ended in a chargeback, removing its amount from held funds.
+    ChargebackApplied {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    /// The account was locked by the transaction.
+    AccountLocked { client: ClientId, tx: TxId },
+    /// The account was flagged for review by a chargeback that did not lock it.
+    AccountFlagged { client: ClientId, tx: TxId },
+    /// The transaction was rejected and left the engine state unchanged.
+    TransactionRejected {
+        client: ClientId,
+        tx: TxId,
+        tx_type: TransactionType,
+        reason: Error,
+    },
+}
+
+impl Event {
+    /// Event for a transaction applied by the engine, moving the given amount.
+    pub(super) fn applied(
+        tx_type: TransactionType,
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    ) -> Self {
+        match tx_type {
+            TransactionType::Deposit => Event::DepositApplied { client, tx, amount },
+            TransactionType::Withdrawal => Event::WithdrawalApplied { client, tx, amount },
+            TransactionType::Dispute => Event::FundsHeld { client, tx, amount },
+            TransactionType::Resolve => Event::FundsReleased { client, tx, amount },
+            TransactionType::Chargeback => Event::ChargebackApplied { client, tx, amount },
+        }
+    }
+
+    /// Client whose account the event is about.
+    pub fn client(&self) -> ClientId {
+        match self {
+            Event::DepositApplied { client, .. }
+            | Event::WithdrawalApplied { client, .. }
+            | Event::FundsHeld { client, .. }
+            | Event::FundsReleased { client, .. }
+            | Event::ChargebackApplied { client, .. }
+            | Event::AccountLocked { client, .. }
+            | Event::AccountFlagged { client, .. }
+            | Event::TransactionRejected { client, .. } => *client,
+        }
+    }
+}
+
+/// Subscriber receiving the events published by the engine.
+///
+/// Implemented for closures taking an `&Event`, so that simple subscribers need no
+/// dedicated type.
+pub trait EventSink {
+    fn publish(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event)> EventSink for F {
+    fn publish(&mut self, event: &Event) {
+        self(event)
+    }
+}
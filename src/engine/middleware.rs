@@ -0,0 +1,166 @@
+//! Hooks around transaction processing.
+//!
+//! A [`TransactionMiddleware`] registered through the
+//! [`EngineBuilder`](super::EngineBuilder) sees every transaction before and after the
+//! engine processes it. Middleware is the extension point for client-specific rules,
+//! such as blocklists, amount caps or business hours, that do not belong in the engine.
+
+use super::Engine;
+use crate::{Error, Transaction};
+
+/// Hooks run around [`Engine::process_transaction`] and [`Engine::process_batch`].
+///
+/// Middleware runs in the order it was registered. Both hooks do nothing by default.
+pub trait TransactionMiddleware {
+    /// Runs before the engine processes the transaction. Returning an error rejects the
+    /// transaction without it reaching the engine, and skips the middleware registered
+    /// after this one. Use [`Error::Rejected`] for rules of your own.
+    ///
+    /// Also runs for transactions evaluated by [`Engine::simulate`], whose rollback does
+    /// not undo changes to the middleware's own state.
+    fn before(&mut self, _transaction: &Transaction) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Runs after the transaction was processed, with its outcome, including rejections
+    /// by any middleware.
+    fn after(&mut self, _transaction: &Transaction, _outcome: Result<(), Error>) {}
+}
+
+impl Engine {
+    /// Runs the before-hooks of the middleware, stopping at the first rejection.
+    pub(super) fn run_before_hooks(&mut self, transaction: &Transaction) -> Result<(), Error> {
+        self.middleware
+            .iter_mut()
+            .try_for_each(|middleware| middleware.before(transaction))
+    }
+
+    /// Runs the after-hooks of the middleware with the outcome of the transaction.
+    pub(super) fn run_after_hooks(
+        &mut self,
+        transaction: &Transaction,
+        outcome: Result<(), Error>,
+    ) {
+        for middleware in &mut self.middleware {
+            middleware.after(transaction, outcome);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientId, Event, TransactionType, TxId};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    fn deposit(client: ClientId, tx: TxId, amount: Decimal) -> Transaction {
+        Transaction {
+            tx_type: TransactionType::Deposit,
+            client,
+            tx,
+            amount: Some(amount),
+            timestamp: None,
+        }
+    }
+
+    struct Blocklist(Vec<ClientId>);
+
+    impl TransactionMiddleware for Blocklist {
+        fn before(&mut self, transaction: &Transaction) -> Result<(), Error> {
+            match self.0.contains(&transaction.client) {
+                true => Err(Error::Rejected("client_blocked")),
+                false => Ok(()),
+            }
+        }
+    }
+
+    struct AmountCap(Decimal);
+
+    impl TransactionMiddleware for AmountCap {
+        fn before(&mut self, transaction: &Transaction) -> Result<(), Error> {
+            match transaction.amount {
+                Some(amount) if amount > self.0 => Err(Error::Rejected("amount_over_cap")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    type Outcome = (TxId, Result<(), Error>);
+
+    /// Records the outcome of every transaction seen by the after-hook.
+    #[derive(Clone, Default)]
+    struct Outcomes(Arc<Mutex<Vec<Outcome>>>);
+
+    impl TransactionMiddleware for Outcomes {
+        fn after(&mut self, transaction: &Transaction, outcome: Result<(), Error>) {
+            self.0.lock().unwrap().push((transaction.tx, outcome));
+        }
+    }
+
+    #[test]
+    fn test_middleware_hooks() {
+        let outcomes = Outcomes::default();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let published = Arc::clone(&events);
+        let mut engine = Engine::builder()
+            .middleware(Blocklist(vec![2]))
+            .middleware(AmountCap(dec!(100)))
+            .middleware(outcomes.clone())
+            .subscribe(move |event: &Event| published.lock().unwrap().push(event.clone()))
+            .build();
+
+        assert_eq!(engine.process_transaction(deposit(1, 1, dec!(50))), Ok(()));
+        assert_eq!(
+            engine.process_transaction(deposit(2, 2, dec!(50))),
+            Err(Error::Rejected("client_blocked"))
+        );
+        assert_eq!(
+            engine.process_transaction(deposit(1, 3, dec!(500))),
+            Err(Error::Rejected("amount_over_cap"))
+        );
+        // Rejections by the engine itself are seen too
+        assert_eq!(
+            engine.process_transaction(deposit(1, 1, dec!(50))),
+            Err(Error::DuplicateTransaction)
+        );
+
+        assert_eq!(
+            *outcomes.0.lock().unwrap(),
+            [
+                (1, Ok(())),
+                (2, Err(Error::Rejected("client_blocked"))),
+                (3, Err(Error::Rejected("amount_over_cap"))),
+                (1, Err(Error::DuplicateTransaction)),
+            ]
+        );
+        assert_eq!(events.lock().unwrap().len(), 4);
+        assert_eq!(engine.accounts().count(), 1);
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_middleware_rejection_rolls_back_batch() {
+        let outcomes = Outcomes::default();
+        let mut engine = Engine::builder()
+            .middleware(AmountCap(dec!(100)))
+            .middleware(outcomes.clone())
+            .build();
+
+        let result = engine.process_batch([deposit(1, 1, dec!(50)), deposit(1, 2, dec!(500))]);
+        assert_eq!(
+            result.unwrap_err().error,
+            Error::Rejected("amount_over_cap")
+        );
+        assert_eq!(engine.accounts().count(), 0);
+        assert!(outcomes.0.lock().unwrap().is_empty());
+
+        // After-hooks see the transactions of a batch once it applied
+        assert_eq!(
+            engine.process_batch([deposit(1, 1, dec!(50)), deposit(1, 2, dec!(50))]),
+            Ok(2)
+        );
+        assert_eq!(*outcomes.0.lock().unwrap(), [(1, Ok(())), (2, Ok(()))]);
+    }
+}
@@ -0,0 +1,1815 @@
+//! Core transaction processing engine for the payment system.
+//!
+//! The engine is responsible for:
+//! - Processing deposits, withdrawals, and dispute-related transactions
+//! - Maintaining account balances and states
+//! - Enforcing business rules like insufficient funds checks and account locks
+//!
+//! The [`Engine`] struct serves as the main entry point for transaction processing,
+//! coordinating between the accounts and transactions stores while ensuring
+//! data consistency and transaction validity. Its behaviour can be tuned through an
+//! [`EngineConfig`] and extended with [`TransactionMiddleware`], both set through an
+//! [`EngineBuilder`], and its changes followed through a feed of [`AccountDelta`]s,
+//! or through domain [`Event`]s published to [`EventSink`]s. Transactions can also be
+//! evaluated without applying them (see [`Engine::simulate`]), or applied all-or-nothing
+//! in batches (see [`Engine::process_batch`]). Savepoints allow reverting everything
+//! processed after them (see [`Engine::savepoint`]). Disputes can be closed
+//! automatically once they are too old (see [`DisputeAging`]).
+
+mod aging;
+mod batch;
+mod builder;
+mod changes;
+mod config;
+mod events;
+mod middleware;
+mod savepoints;
+mod simulate;
+mod undo;
+mod verify;
+
+use rust_decimal::Decimal;
+use std::sync::mpsc;
+
+use crate::{
+    dto::{ClientId, Transaction, TransactionType, TxId},
+    stores::{
+        Account, AccountsStore, Ledger, LedgerAccount, LedgerEntry, TransactionsStore, TrialBalance,
+    },
+    Error,
+};
+
+pub use crate::stores::{ProcessedIds, TxNamespace};
+pub use aging::OpenDispute;
+pub use batch::BatchError;
+pub use builder::EngineBuilder;
+use changes::ChangeFeed;
+pub use changes::{AccountDelta, Balances};
+pub use config::{
+    AgingAction, AgingThreshold, ChargebackPolicy, DisputeAging, DuplicatePolicy, EngineConfig,
+    EngineConfigError, Limits, NegativeBalancePolicy, Precision, Rounding, StoreConfig,
+};
+pub use events::{Event, EventSink};
+pub use middleware::TransactionMiddleware;
+pub use savepoints::{Savepoint, UnknownSavepoint};
+pub use simulate::Simulation;
+use undo::UndoLog;
+use verify::Flows;
+pub use verify::{VerifyError, Violation};
+
+/// Core transaction processing engine for the payment system.
+#[derive(Default)]
+pub struct Engine {
+    accounts: AccountsStore,
+    transactions: TransactionsStore,
+    ledger: Ledger,
+    flows: Flows,
+    undo: UndoLog,
+    config: EngineConfig,
+    /// Number of transactions processed, the clock of dispute aging
+    processed: u64,
+    /// Latest transaction timestamp, the clock of dispute aging by duration
+    now: Option<u64>,
+    /// Transactions applied by dispute aging while processing the last transaction or batch
+    aged: Vec<Transaction>,
+    change_feed: Option<ChangeFeed>,
+    sinks: Vec<Box<dyn EventSink + Send>>,
+    middleware: Vec<Box<dyn TransactionMiddleware + Send>>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// Creates an engine with the provided [`EngineConfig`].
+    pub fn with_config(config: EngineConfig) -> Self {
+        Self {
+            accounts: AccountsStore::new(),
+            transactions: TransactionsStore::with_config(
+                config.tx_namespace,
+                config.store.processed_ids,
+            ),
+            ledger: Ledger::new(config.keep_journal),
+            flows: Flows::default(),
+            undo: UndoLog::default(),
+            config,
+            processed: 0,
+            now: None,
+            aged: Vec::new(),
+            change_feed: None,
+            sinks: Vec::new(),
+            middleware: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Registers a callback receiving an [`AccountDelta`] after every accepted transaction,
+    /// replacing any previously registered one.
+    pub fn on_change(&mut self, callback: impl FnMut(AccountDelta) + Send + 'static) {
+        self.change_feed = Some(Box::new(callback));
+    }
+
+    /// Registers a change feed delivering the [`AccountDelta`]s through a channel,
+    /// replacing any previously registered one.
+    /// Deltas are dropped once the receiver is dropped.
+    pub fn change_channel(&mut self) -> mpsc::Receiver<AccountDelta> {
+        let (sender, receiver) = mpsc::channel();
+        self.on_change(move |delta| {
+            let _ = sender.send(delta);
+        });
+        receiver
+    }
+
+    /// Subscribes a sink to the [`Event`]s published for every processed transaction.
+    /// Sinks receive the events in the order they were subscribed.
+    pub fn subscribe(&mut self, sink: impl EventSink + Send + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Processes a transaction and updates the internal state if valid.  
+    /// Returns a domain [`Error`] if the transaction can't be processed due to
+    /// invalid state, or was rejected by a [`TransactionMiddleware`].
+    ///
+    /// Then closes the disputes that became too old under the [`DisputeAging`] policy
+    /// (see [`Engine::aged`]).
+    pub fn process_transaction(&mut self, transaction: Transaction) -> Result<(), Error> {
+        self.aged.clear();
+        self.tick(&transaction);
+        let outcome = self.process(transaction);
+        for (transaction, entry, before, after) in self.age_disputes() {
+            self.notify(&transaction, &Ok(entry), before, after);
+            self.run_after_hooks(&transaction, Ok(()));
+        }
+        outcome
+    }
+
+    /// Processes a transaction, running the middleware and notifying the listeners.
+    fn process(&mut self, transaction: Transaction) -> Result<(), Error> {
+        if self.middleware.is_empty() && !self.has_listeners() {
+            return self.apply(&transaction).map(|_| ());
+        }
+        let before = Balances::of(self.accounts.get(transaction.client));
+        let result = self
+            .run_before_hooks(&transaction)
+            .and_then(|()| self.apply(&transaction));
+        let after = Balances::of(self.accounts.get(transaction.client));
+        self.notify(&transaction, &result, before, after);
+        let outcome = result.map(|_| ());
+        self.run_after_hooks(&transaction, outcome);
+        outcome
+    }
+
+    /// Whether a change feed or any event sink is registered.
+    fn has_listeners(&self) -> bool {
+        self.change_feed.is_some() || !self.sinks.is_empty()
+    }
+
+    /// Publishes the events and the account delta of a processed transaction.
+    fn notify(
+        &mut self,
+        transaction: &Transaction,
+        result: &Result<LedgerEntry, Error>,
+        before: Balances,
+        after: Balances,
+    ) {
+        let (client, tx) = (transaction.client, transaction.tx);
+        if !self.sinks.is_empty() {
+            let mut events = Vec::with_capacity(2);
+            match result {
+                Ok(entry) => {
+                    events.push(Event::applied(
+                        transaction.tx_type,
+                        client,
+                        tx,
+                        entry.amount,
+                    ));
+                    if after.locked && !before.locked {
+                        events.push(Event::AccountLocked { client, tx });
+                    }
+                    if after.flagged && !before.flagged {
+                        events.push(Event::AccountFlagged { client, tx });
+                    }
+                }
+                Err(reason) => events.push(Event::TransactionRejected {
+                    client,
+                    tx,
+                    tx_type: transaction.tx_type,
+                    reason: *reason,
+                }),
+            }
+            for sink in &mut self.sinks {
+                events.iter().for_each(|event| sink.publish(event));
+            }
+        }
+        if let (Ok(_), Some(change_feed)) = (result, &mut self.change_feed) {
+            change_feed(AccountDelta {
+                client,
+                tx,
+                before,
+                after,
+            });
+        }
+    }
+
+    /// Applies a transaction, returning the ledger entry it posted.
+    fn apply(&mut self, transaction: &Transaction) -> Result<LedgerEntry, Error> {
+        let entry = match self.plan(transaction) {
+            Ok(entry) => entry,
+            Err(reason) => {
+                self.burn(transaction, reason);
+                return Err(reason);
+            }
+        };
+        self.record(transaction);
+        self.commit(transaction.tx_type, transaction.client, entry)?;
+        Ok(entry)
+    }
+
+    /// Under [`DuplicatePolicy::Seen`], keeps the ID of a rejected deposit or withdrawal
+    /// as used, together with the reason it was rejected.
+    fn burn(&mut self, transaction: &Transaction, reason: Error) {
+        let (client, tx) = (transaction.client, transaction.tx);
+        if self.config.duplicate_policy != DuplicatePolicy::Seen
+            || !matches!(
+                transaction.tx_type,
+                TransactionType::Deposit | TransactionType::Withdrawal
+            )
+            || self.transactions.is_processed(client, tx)
+            || self.transactions.rejection(client, tx).is_some()
+        {
+            return;
+        }
+        self.record(transaction);
+        self.transactions.mark_rejected(client, tx, reason);
+    }
+
+    /// Checks a transaction against the current state, without changing it.
+    /// Returns the ledger entry the transaction would post, or the reason it would be rejected.
+    fn plan(&self, transaction: &Transaction) -> Result<LedgerEntry, Error> {
+        let (client, tx) = (transaction.client, transaction.tx);
+        if matches!(
+            transaction.tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        ) {
+            if let Some(reason) = self.transactions.rejection(client, tx) {
+                return Err(reason);
+            }
+        }
+        self.accounts.check_account_lock(client)?;
+
+        match transaction.tx_type {
+            TransactionType::Deposit => {
+                self.plan_deposit(client, tx, self.amount(transaction.amount)?)
+            }
+            TransactionType::Withdrawal => {
+                self.plan_withdrawal(client, tx, self.amount(transaction.amount)?)
+            }
+            TransactionType::Dispute => self.plan_dispute(client, tx),
+            TransactionType::Resolve => self.plan_resolve(client, tx),
+            TransactionType::Chargeback => self.plan_chargeback(client, tx),
+        }
+    }
+
+    /// Brings the amount of a deposit or withdrawal to the configured precision.
+    fn amount(&self, amount: Option<Decimal>) -> Result<Decimal, Error> {
+        self.config
+            .precision
+            .apply(amount.ok_or(Error::InvalidTransaction)?)
+    }
+
+    /// Checks that a deposit or withdrawal amount is positive and within the limit.
+    fn check_amount(&self, amount: Decimal) -> Result<(), Error> {
+        if amount <= Decimal::ZERO {
+            return Err(Error::AmountMustBePositive);
+        }
+        if self
+            .config
+            .limits
+            .max_amount
+            .is_some_and(|max_amount| amount > max_amount)
+        {
+            return Err(Error::AmountOverLimit);
+        }
+        Ok(())
+    }
+
+    /// Checks that one more transaction ID can be kept for duplicate detection.
+    fn check_transaction_limit(&self) -> Result<(), Error> {
+        match reached(
+            self.transactions.processed_count(),
+            self.config.limits.max_transactions,
+        ) {
+            true => Err(Error::TransactionLimitReached),
+            false => Ok(()),
+        }
+    }
+
+    fn plan_deposit(
+        &self,
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    ) -> Result<LedgerEntry, Error> {
+        self.check_amount(amount)?;
+        if self.transactions.is_processed(client, tx) {
+            return Err(Error::DuplicateTransaction);
+        }
+        self.check_transaction_limit()?;
+        if reached(self.accounts.count(), self.config.limits.max_accounts)
+            && self.accounts.get(client).is_none()
+        {
+            return Err(Error::AccountLimitReached);
+        }
+        Ok(LedgerEntry {
+            tx,
+            debit: LedgerAccount::External,
+            credit: LedgerAccount::Available(client),
+            amount,
+        })
+    }
+
+    fn plan_withdrawal(
+        &self,
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    ) -> Result<LedgerEntry, Error> {
+        self.check_amount(amount)?;
+        if self.transactions.is_processed(client, tx) {
+            return Err(Error::DuplicateTransaction);
+        }
+        self.check_transaction_limit()?;
+
+        let account = self.accounts.get(client).ok_or(Error::AccountNotFound)?;
+        if account.available < amount {
+            return Err(Error::InsufficientFunds);
+        }
+        Ok(LedgerEntry {
+            tx,
+            debit: LedgerAccount::Available(client),
+            credit: LedgerAccount::External,
+            amount,
+        })
+    }
+
+    fn plan_dispute(&self, client: ClientId, tx: TxId) -> Result<LedgerEntry, Error> {
+        let deposit = self.transactions.get_deposit(client, tx)?;
+        if deposit.disputed {
+            return Err(Error::TransactionAlreadyDisputed);
+        }
+        if reached(
+            self.transactions.open_dispute_count(),
+            self.config.limits.max_open_disputes,
+        ) {
+            return Err(Error::OpenDisputeLimitReached);
+        }
+
+        let available = self
+            .accounts
+            .get(client)
+            .map_or(Decimal::ZERO, |account| account.available);
+        let amount = match self.config.negative_balance_policy {
+            NegativeBalancePolicy::Reject if available < deposit.amount => {
+                return Err(Error::InsufficientFundsForDispute);
+            }
+            NegativeBalancePolicy::Allow | NegativeBalancePolicy::Reject => deposit.amount,
+            NegativeBalancePolicy::HoldAvailable => {
+                deposit.amount.min(available.max(Decimal::ZERO))
+            }
+        };
+        Ok(LedgerEntry {
+            tx,
+            debit: LedgerAccount::Available(client),
+            credit: LedgerAccount::Held(client),
+            amount,
+        })
+    }
+
+    fn plan_resolve(&self, client: ClientId, tx: TxId) -> Result<LedgerEntry, Error> {
+        let deposit = self.transactions.get_deposit(client, tx)?;
+        if !deposit.disputed {
+            return Err(Error::TransactionNotDisputed);
+        }
+        Ok(LedgerEntry {
+            tx,
+            debit: LedgerAccount::Held(client),
+            credit: LedgerAccount::Available(client),
+            amount: deposit.amount - self.transactions.shortfall(client, tx),
+        })
+    }
+
+    fn plan_chargeback(&self, client: ClientId, tx: TxId) -> Result<LedgerEntry, Error> {
+        let deposit = self.transactions.get_deposit(client, tx)?;
+        if !deposit.disputed {
+            return Err(Error::TransactionNotDisputed);
+        }
+        Ok(LedgerEntry {
+            tx,
+            debit: LedgerAccount::Held(client),
+            credit: LedgerAccount::Settlement,
+            amount: deposit.amount - self.transactions.shortfall(client, tx),
+        })
+    }
+
+    /// Records a planned transaction in the stores and posts its ledger entry.
+    /// Only fails if the stores changed since the transaction was planned.
+    fn commit(
+        &mut self,
+        tx_type: TransactionType,
+        client: ClientId,
+        entry: LedgerEntry,
+    ) -> Result<(), Error> {
+        let (tx, amount) = (entry.tx, entry.amount);
+        let receivable = self.receivable_change(tx_type, client, &entry);
+        match tx_type {
+            TransactionType::Deposit => {
+                self.transactions.store_new_deposit(tx, client, amount)?;
+                self.transactions.mark_processed(client, tx);
+                self.flows.deposited += amount;
+            }
+            TransactionType::Withdrawal => {
+                self.transactions.mark_processed(client, tx);
+                self.flows.withdrawn += amount;
+            }
+            TransactionType::Dispute => {
+                self.transactions.get_deposit_mut(client, tx)?.disputed = true;
+                self.transactions.set_shortfall(client, tx, receivable);
+                self.transactions
+                    .set_dispute_opened(client, tx, Some(self.dispute_opened()));
+            }
+            TransactionType::Resolve => {
+                self.transactions.get_deposit_mut(client, tx)?.disputed = false;
+                self.transactions.set_shortfall(client, tx, Decimal::ZERO);
+                self.transactions.set_dispute_opened(client, tx, None);
+            }
+            TransactionType::Chargeback => {
+                let deposit = self.transactions.get_deposit_mut(client, tx)?;
+                deposit.disputed = false;
+                deposit.charged_back = true;
+                self.flows.charged_back += amount;
+                self.transactions.set_dispute_opened(client, tx, None);
+            }
+        }
+
+        let account = self.accounts.get_or_create_mut(client);
+        self.ledger.post(account, entry);
+        account.receivable += receivable;
+        count_transaction(self.config.chargeback_policy, tx_type, account);
+        Ok(())
+    }
+
+    /// Change of the client's receivable by a planned transaction: a dispute adds the part
+    /// of the deposit that could not be held, and resolving it settles that part again.
+    fn receivable_change(
+        &self,
+        tx_type: TransactionType,
+        client: ClientId,
+        entry: &LedgerEntry,
+    ) -> Decimal {
+        match tx_type {
+            TransactionType::Dispute => self
+                .transactions
+                .get_deposit(client, entry.tx)
+                .map_or(Decimal::ZERO, |deposit| deposit.amount - entry.amount),
+            TransactionType::Resolve => -self.transactions.shortfall(client, entry.tx),
+            _ => Decimal::ZERO,
+        }
+    }
+
+    /// Computes the trial balance of the ledger behind the account balances.
+    pub fn trial_balance(&self) -> TrialBalance {
+        self.ledger.trial_balance(self.accounts.iter())
+    }
+
+    /// Returns every ledger entry posted so far, in posting order.
+    /// Empty unless [`EngineConfig::keep_journal`] is set.
+    pub fn journal(&self) -> &[LedgerEntry] {
+        self.ledger.journal()
+    }
+
+    /// Returns the balance of a ledger account, computed from its postings.
+    /// `None` for the accounts of a client without an account.
+    pub fn ledger_balance(&self, ledger_account: LedgerAccount) -> Option<Decimal> {
+        let account = match ledger_account {
+            LedgerAccount::Available(client) | LedgerAccount::Held(client) => {
+                self.accounts.get(client)
+            }
+            LedgerAccount::External | LedgerAccount::Settlement => None,
+        };
+        self.ledger.balance(ledger_account, account)
+    }
+
+    /// Returns an iterator over all accounts that had at least one valid deposit.
+    /// Provides no guarantees about the order of the accounts.
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.iter()
+    }
+}
+
+/// Counts an applied deposit or chargeback on the client's account, and applies the
+/// [`ChargebackPolicy`] to a chargeback: it either locks the account, or flags it for review.
+fn count_transaction(policy: ChargebackPolicy, tx_type: TransactionType, account: &mut Account) {
+    match tx_type {
+        TransactionType::Deposit => account.deposits += 1,
+        TransactionType::Chargeback => {
+            account.chargebacks += 1;
+            match policy.locks(account) {
+                true => account.locked = true,
+                false => account.flagged = true,
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether a count of accounts, transactions or disputes reached its limit, if any.
+fn reached(count: usize, limit: Option<u64>) -> bool {
+    limit.is_some_and(|limit| count as u64 >= limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    fn create_transaction(
+        tx_type: TransactionType,
+        client: ClientId,
+        tx: TxId,
+        amount: Option<Decimal>,
+    ) -> Transaction {
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_valid_deposit() {
+        let mut engine = Engine::new();
+        let tx = create_transaction(TransactionType::Deposit, 1, 1, Some(dec!(100.0)));
+
+        engine.process_transaction(tx).unwrap();
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(100.0));
+        assert_eq!(account.total(), dec!(100.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert!(!account.locked);
+    }
+
+    #[test]
+    fn test_duplicate_deposit() {
+        let mut engine = Engine::new();
+        let tx = create_transaction(TransactionType::Deposit, 1, 1, Some(dec!(100.0)));
+
+        engine.process_transaction(tx.clone()).unwrap();
+        assert!(matches!(
+            engine.process_transaction(tx),
+            Err(Error::DuplicateTransaction)
+        ));
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(100.0));
+    }
+
+    #[test]
+    fn test_valid_withdrawal() {
+        let mut engine = Engine::new();
+
+        // First deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Then withdraw
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(dec!(50.0)),
+            ))
+            .unwrap();
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(50.0));
+    }
+
+    #[test]
+    fn test_withdrawal_insufficient_funds() {
+        let mut engine = Engine::new();
+
+        // Deposit 100
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Try to withdraw 150
+        let result = engine.process_transaction(create_transaction(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(dec!(150.0)),
+        ));
+
+        assert!(matches!(result, Err(Error::InsufficientFunds)));
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(100.0));
+    }
+
+    #[test]
+    fn test_withdrawal_from_nonexistent_account() {
+        let mut engine = Engine::new();
+        let result = engine.process_transaction(create_transaction(
+            TransactionType::Withdrawal,
+            1,
+            1,
+            Some(dec!(50.0)),
+        ));
+
+        assert!(matches!(result, Err(Error::AccountNotFound)));
+        assert!(engine.accounts().next().is_none());
+    }
+
+    #[test]
+    fn test_deposit_non_positive_amount() {
+        let mut engine = Engine::new();
+
+        // Test zero amount
+        let result = engine.process_transaction(create_transaction(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(dec!(0.0)),
+        ));
+        assert!(matches!(result, Err(Error::AmountMustBePositive)));
+
+        // Test negative amount
+        let result = engine.process_transaction(create_transaction(
+            TransactionType::Deposit,
+            1,
+            2,
+            Some(dec!(-10.0)),
+        ));
+        assert!(matches!(result, Err(Error::AmountMustBePositive)));
+    }
+
+    #[test]
+    fn test_withdrawal_non_positive_amount() {
+        let mut engine = Engine::new();
+
+        // First make a deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Test zero amount
+        let result = engine.process_transaction(create_transaction(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(dec!(0.0)),
+        ));
+        assert!(matches!(result, Err(Error::AmountMustBePositive)));
+
+        // Test negative amount
+        let result = engine.process_transaction(create_transaction(
+            TransactionType::Withdrawal,
+            1,
+            3,
+            Some(dec!(-10.0)),
+        ));
+        assert!(matches!(result, Err(Error::AmountMustBePositive)));
+    }
+
+    #[test]
+    fn test_duplicate_withdrawal() {
+        let mut engine = Engine::new();
+
+        // First make a deposit to have funds
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // First withdrawal succeeds
+        let withdrawal = create_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(50.0)));
+        engine.process_transaction(withdrawal.clone()).unwrap();
+
+        // Second withdrawal with same tx ID fails
+        let result = engine.process_transaction(withdrawal);
+        assert!(matches!(result, Err(Error::DuplicateTransaction)));
+
+        // Verify account state hasn't changed after failed withdrawal
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(50.0));
+        assert_eq!(account.total(), dec!(50.0));
+    }
+
+    #[test]
+    fn test_retry_rejected_tx_id() {
+        let mut engine = Engine::new();
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(10.0)),
+            ))
+            .unwrap();
+        let withdrawal = create_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(50.0)));
+        let result = engine.process_transaction(withdrawal);
+        assert!(matches!(result, Err(Error::InsufficientFunds)));
+
+        // By default, only accepted IDs are used up, so the ID can be retried
+        let retry = create_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(5.0)));
+        engine.process_transaction(retry).unwrap();
+        assert_eq!(engine.accounts().next().unwrap().available, dec!(5.0));
+    }
+
+    #[test]
+    fn test_duplicate_policy_seen() {
+        let mut engine = Engine::with_config(EngineConfig {
+            duplicate_policy: DuplicatePolicy::Seen,
+            ..Default::default()
+        });
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(10.0)),
+            ))
+            .unwrap();
+        let withdrawal = create_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(50.0)));
+        let result = engine.process_transaction(withdrawal);
+        assert!(matches!(result, Err(Error::InsufficientFunds)));
+        let deposit = create_transaction(TransactionType::Deposit, 1, 3, Some(dec!(0)));
+        let result = engine.process_transaction(deposit);
+        assert!(matches!(result, Err(Error::AmountMustBePositive)));
+
+        // Retries are rejected with the reason of the first attempt, even if they would succeed
+        let retry = create_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(5.0)));
+        let result = engine.process_transaction(retry);
+        assert!(matches!(result, Err(Error::InsufficientFunds)));
+        let retry = create_transaction(TransactionType::Deposit, 1, 3, Some(dec!(5.0)));
+        let result = engine.process_transaction(retry);
+        assert!(matches!(result, Err(Error::AmountMustBePositive)));
+
+        // Accepted IDs are still reported as duplicates
+        let retry = create_transaction(TransactionType::Deposit, 1, 1, Some(dec!(0)));
+        let result = engine.process_transaction(retry);
+        assert!(matches!(result, Err(Error::AmountMustBePositive)));
+        let retry = create_transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0)));
+        let result = engine.process_transaction(retry);
+        assert!(matches!(result, Err(Error::DuplicateTransaction)));
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(10.0));
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_valid_dispute() {
+        let mut engine = Engine::new();
+
+        // Make a deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Dispute it
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.held, dec!(100.0));
+        assert_eq!(account.total(), dec!(100.0));
+    }
+
+    #[test]
+    fn test_duplicate_dispute() {
+        let mut engine = Engine::new();
+
+        // Make a deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // First dispute
+        let tx = create_transaction(TransactionType::Dispute, 1, 1, None);
+        engine.process_transaction(tx.clone()).unwrap();
+
+        // Second dispute
+        let result = engine.process_transaction(tx);
+
+        assert!(matches!(result, Err(Error::TransactionAlreadyDisputed)));
+    }
+
+    #[test]
+    fn test_dispute_nonexistent_transaction() {
+        let mut engine = Engine::new();
+        let result =
+            engine.process_transaction(create_transaction(TransactionType::Dispute, 1, 999, None));
+
+        assert!(matches!(result, Err(Error::TransactionNotFound)));
+    }
+
+    #[test]
+    fn test_dispute_resolve_chargeback_nonexistent_account() {
+        let mut engine = Engine::new();
+        let types = [
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Chargeback,
+        ];
+        for tx_type in types {
+            let result = engine.process_transaction(create_transaction(tx_type, 1, 1, None));
+            assert!(matches!(result, Err(Error::TransactionNotFound)));
+            assert!(engine.accounts().next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_dispute_wrong_client() {
+        let mut engine = Engine::new();
+
+        // Make a deposit for client 1
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Try to dispute it as client 2
+        let result =
+            engine.process_transaction(create_transaction(TransactionType::Dispute, 2, 1, None));
+
+        assert!(matches!(result, Err(Error::TransactionClientMismatch)));
+    }
+
+    #[test]
+    fn test_per_client_tx_namespace() {
+        let mut engine = Engine::with_config(EngineConfig {
+            tx_namespace: TxNamespace::PerClient,
+            ..Default::default()
+        });
+        for client in [1, 2] {
+            engine
+                .process_transaction(create_transaction(
+                    TransactionType::Deposit,
+                    client,
+                    1,
+                    Some(dec!(100.0)),
+                ))
+                .unwrap();
+        }
+        let result = engine.process_transaction(create_transaction(
+            TransactionType::Withdrawal,
+            1,
+            1,
+            Some(dec!(10.0)),
+        ));
+        assert!(matches!(result, Err(Error::DuplicateTransaction)));
+
+        // Disputes only see the client's own deposit
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 2, 1, None))
+            .unwrap();
+        let result =
+            engine.process_transaction(create_transaction(TransactionType::Dispute, 3, 1, None));
+        assert!(matches!(result, Err(Error::TransactionNotFound)));
+
+        let mut accounts: Vec<_> = engine
+            .accounts()
+            .map(|account| (account.id, account.available, account.held))
+            .collect();
+        accounts.sort();
+        assert_eq!(
+            accounts,
+            [(1, dec!(100.0), dec!(0)), (2, dec!(0), dec!(100.0))]
+        );
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_valid_resolve() {
+        let mut engine = Engine::new();
+
+        // Make a deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Dispute it
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        // Resolve it
+        engine
+            .process_transaction(create_transaction(TransactionType::Resolve, 1, 1, None))
+            .unwrap();
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(100.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.total(), dec!(100.0));
+    }
+
+    #[test]
+    fn test_resolve_without_dispute() {
+        let mut engine = Engine::new();
+
+        // Make a deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Try to resolve without dispute
+        let result =
+            engine.process_transaction(create_transaction(TransactionType::Resolve, 1, 1, None));
+
+        assert!(matches!(result, Err(Error::TransactionNotDisputed)));
+    }
+
+    #[test]
+    fn test_valid_chargeback() {
+        let mut engine = Engine::new();
+
+        // Make a deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Dispute it
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        // Chargeback
+        engine
+            .process_transaction(create_transaction(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.total(), dec!(0.0));
+        assert!(account.locked);
+    }
+
+    #[test]
+    fn test_chargeback_without_dispute() {
+        let mut engine = Engine::new();
+
+        // Make a deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Try chargeback without dispute
+        let result =
+            engine.process_transaction(create_transaction(TransactionType::Chargeback, 1, 1, None));
+
+        assert!(matches!(result, Err(Error::TransactionNotDisputed)));
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(100.0));
+        assert!(!account.locked);
+    }
+
+    #[test]
+    fn test_redispute_after_resolve() {
+        let mut engine = Engine::new();
+
+        // Make a deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // First dispute cycle
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(create_transaction(TransactionType::Resolve, 1, 1, None))
+            .unwrap();
+
+        // Second dispute should work
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.held, dec!(100.0));
+        assert_eq!(account.total(), dec!(100.0));
+    }
+
+    #[test]
+    fn test_chargeback_results_in_negative_balance() {
+        let mut engine = Engine::new();
+
+        // Make a deposit of 100
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Verify initial state after deposit
+        {
+            let account = engine.accounts().next().unwrap();
+            assert_eq!(account.available, dec!(100.0));
+            assert_eq!(account.held, dec!(0.0));
+            assert_eq!(account.total(), dec!(100.0));
+            assert!(!account.locked);
+        }
+
+        // Withdraw 75
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(dec!(75.0)),
+            ))
+            .unwrap();
+
+        // Verify state after withdrawal: available = 25, held = 0, total = 25
+        {
+            let account = engine.accounts().next().unwrap();
+            assert_eq!(account.available, dec!(25.0));
+            assert_eq!(account.held, dec!(0.0));
+            assert_eq!(account.total(), dec!(25.0));
+            assert!(!account.locked);
+        }
+
+        // Dispute the original deposit
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        // Verify state after dispute: available = -75, held = 100, total = 25
+        {
+            let account = engine.accounts().next().unwrap();
+            assert_eq!(account.available, dec!(-75.0));
+            assert_eq!(account.held, dec!(100.0));
+            assert_eq!(account.total(), dec!(25.0));
+            assert!(!account.locked);
+        }
+
+        // Chargeback the deposit
+        engine
+            .process_transaction(create_transaction(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
+
+        // Verify final state: available = -75, held = 0, total = -75, locked = true
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(-75.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.total(), dec!(-75.0));
+        assert!(account.locked);
+    }
+
+    #[test]
+    fn test_negative_balance_policy_reject() {
+        let mut engine = Engine::with_config(EngineConfig {
+            negative_balance_policy: NegativeBalancePolicy::Reject,
+            ..Default::default()
+        });
+        let deposit = create_transaction(TransactionType::Deposit, 1, 1, Some(dec!(100.0)));
+        let withdrawal = create_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(75.0)));
+        engine.process_transaction(deposit).unwrap();
+        engine.process_transaction(withdrawal).unwrap();
+
+        assert_eq!(
+            engine.process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None)),
+            Err(Error::InsufficientFundsForDispute)
+        );
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(25.0));
+        assert_eq!(account.held, dec!(0.0));
+
+        // A dispute the available funds cover is still accepted
+        let deposit = create_transaction(TransactionType::Deposit, 1, 3, Some(dec!(20.0)));
+        engine.process_transaction(deposit).unwrap();
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 3, None))
+            .unwrap();
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(25.0));
+        assert_eq!(account.held, dec!(20.0));
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_negative_balance_policy_hold_available() {
+        let mut engine = Engine::with_config(EngineConfig {
+            negative_balance_policy: NegativeBalancePolicy::HoldAvailable,
+            ..Default::default()
+        });
+        for (tx, amount) in [(1, dec!(100.0)), (2, dec!(50.0))] {
+            let deposit = create_transaction(TransactionType::Deposit, 1, tx, Some(amount));
+            engine.process_transaction(deposit).unwrap();
+        }
+        let withdrawal = create_transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(125.0)));
+        engine.process_transaction(withdrawal).unwrap();
+
+        // Only the available 25 can be held, the other 75 are owed by the client
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.held, dec!(25.0));
+        assert_eq!(account.receivable, dec!(75.0));
+        assert_eq!(engine.verify(), Ok(()));
+
+        // Nothing is available to hold for the second dispute
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 2, None))
+            .unwrap();
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.held, dec!(25.0));
+        assert_eq!(account.receivable, dec!(125.0));
+
+        // Resolving releases the held part and settles the receivable
+        engine
+            .process_transaction(create_transaction(TransactionType::Resolve, 1, 1, None))
+            .unwrap();
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(25.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.receivable, dec!(50.0));
+
+        // A chargeback leaves the receivable owed
+        engine
+            .process_transaction(create_transaction(TransactionType::Chargeback, 1, 2, None))
+            .unwrap();
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(25.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.receivable, dec!(50.0));
+        assert!(account.locked);
+        assert_eq!(engine.verify(), Ok(()));
+        assert!(engine.trial_balance().is_balanced());
+    }
+
+    /// Processes a deposit of 10 and charges it back, returning the account afterwards.
+    fn deposit_and_charge_back(engine: &mut Engine, client: ClientId, tx: TxId) -> Account {
+        let deposit = create_transaction(TransactionType::Deposit, client, tx, Some(dec!(10)));
+        engine.process_transaction(deposit).unwrap();
+        for tx_type in [TransactionType::Dispute, TransactionType::Chargeback] {
+            let transaction = create_transaction(tx_type, client, tx, None);
+            engine.process_transaction(transaction).unwrap();
+        }
+        engine.accounts.get(client).unwrap().clone()
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut engine = Engine::with_config(EngineConfig {
+            limits: Limits {
+                max_amount: Some(dec!(100)),
+                max_accounts: Some(2),
+                max_transactions: Some(4),
+                max_open_disputes: Some(1),
+            },
+            ..Default::default()
+        });
+        let deposit = |client, tx, amount| {
+            create_transaction(TransactionType::Deposit, client, tx, Some(amount))
+        };
+        assert_eq!(
+            engine.process_transaction(deposit(1, 1, dec!(100.01))),
+            Err(Error::AmountOverLimit)
+        );
+        let withdrawal = create_transaction(TransactionType::Withdrawal, 1, 1, Some(dec!(101)));
+        assert_eq!(
+            engine.process_transaction(withdrawal),
+            Err(Error::AmountOverLimit)
+        );
+        engine
+            .process_transaction(deposit(1, 1, dec!(100)))
+            .unwrap();
+        engine.process_transaction(deposit(2, 2, dec!(50))).unwrap();
+
+        // Existing accounts can still receive deposits, but no account can be opened
+        assert_eq!(
+            engine.process_transaction(deposit(3, 3, dec!(10))),
+            Err(Error::AccountLimitReached)
+        );
+        engine.process_transaction(deposit(2, 3, dec!(10))).unwrap();
+
+        // Only one dispute can be open at a time
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        assert_eq!(
+            engine.process_transaction(create_transaction(TransactionType::Dispute, 2, 2, None)),
+            Err(Error::OpenDisputeLimitReached)
+        );
+        engine
+            .process_transaction(create_transaction(TransactionType::Resolve, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 2, 2, None))
+            .unwrap();
+
+        // The fourth transaction ID is the last one kept
+        let withdrawal = create_transaction(TransactionType::Withdrawal, 1, 4, Some(dec!(10)));
+        engine.process_transaction(withdrawal).unwrap();
+        assert_eq!(
+            engine.process_transaction(deposit(1, 5, dec!(10))),
+            Err(Error::TransactionLimitReached)
+        );
+        assert_eq!(
+            engine.process_transaction(deposit(1, 4, dec!(10))),
+            Err(Error::DuplicateTransaction)
+        );
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_bitmap_store() {
+        let mut engine = Engine::with_config(EngineConfig {
+            store: StoreConfig {
+                processed_ids: ProcessedIds::Bitmap,
+            },
+            ..Default::default()
+        });
+        for tx in [1, 2, TxId::MAX] {
+            let deposit = create_transaction(TransactionType::Deposit, 1, tx, Some(dec!(10)));
+            engine.process_transaction(deposit).unwrap();
+        }
+        let duplicate = create_transaction(TransactionType::Deposit, 2, TxId::MAX, Some(dec!(1)));
+        assert_eq!(
+            engine.process_transaction(duplicate),
+            Err(Error::DuplicateTransaction)
+        );
+        let withdrawal = create_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(1)));
+        assert_eq!(
+            engine.process_transaction(withdrawal),
+            Err(Error::DuplicateTransaction)
+        );
+        assert_eq!(engine.accounts().next().unwrap().available, dec!(30));
+    }
+
+    #[test]
+    fn test_chargeback_policy_flag() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let published = Arc::clone(&events);
+        let mut engine = Engine::builder()
+            .chargeback_policy(ChargebackPolicy::Flag)
+            .subscribe(move |event: &Event| published.lock().unwrap().push(event.clone()))
+            .build();
+
+        let account = deposit_and_charge_back(&mut engine, 1, 1);
+        assert!(!account.locked);
+        assert!(account.flagged);
+        assert_eq!(account.chargebacks, 1);
+        assert_eq!(
+            events.lock().unwrap().last(),
+            Some(&Event::AccountFlagged { client: 1, tx: 1 })
+        );
+
+        // The flagged account keeps transacting
+        let account = deposit_and_charge_back(&mut engine, 1, 2);
+        assert!(!account.locked);
+        assert_eq!(account.chargebacks, 2);
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_chargeback_policy_lock_after() {
+        let mut engine = Engine::builder()
+            .chargeback_policy(ChargebackPolicy::LockAfter(2))
+            .build();
+
+        let account = deposit_and_charge_back(&mut engine, 1, 1);
+        assert!(!account.locked);
+        assert!(account.flagged);
+
+        let account = deposit_and_charge_back(&mut engine, 1, 2);
+        assert!(account.locked);
+        assert_eq!(account.chargebacks, 2);
+        assert_eq!(
+            engine.process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                3,
+                Some(dec!(10))
+            )),
+            Err(Error::AccountLocked)
+        );
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_chargeback_policy_lock_over_ratio() {
+        let mut engine = Engine::builder()
+            .chargeback_policy(ChargebackPolicy::LockOverRatio(dec!(0.25)))
+            .build();
+        for tx in 1..=3 {
+            let deposit = create_transaction(TransactionType::Deposit, 1, tx, Some(dec!(10)));
+            engine.process_transaction(deposit).unwrap();
+        }
+
+        // 1 chargeback in 4 deposits is not above the threshold
+        let account = deposit_and_charge_back(&mut engine, 1, 4);
+        assert_eq!((account.deposits, account.chargebacks), (4, 1));
+        assert!(!account.locked);
+        assert!(account.flagged);
+
+        // 2 in 5 is
+        let account = deposit_and_charge_back(&mut engine, 1, 5);
+        assert_eq!((account.deposits, account.chargebacks), (5, 2));
+        assert!(account.locked);
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_cannot_dispute_withdrawal() {
+        let mut engine = Engine::new();
+
+        // First deposit to have funds
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Make a withdrawal
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(dec!(50.0)),
+            ))
+            .unwrap();
+
+        // Try to dispute the withdrawal
+        let result =
+            engine.process_transaction(create_transaction(TransactionType::Dispute, 1, 2, None));
+        assert!(matches!(result, Err(Error::TransactionNotFound)));
+
+        // Verify account state hasn't changed
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(50.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.total(), dec!(50.0));
+    }
+
+    #[test]
+    fn test_locked_account_rejects_transactions() {
+        let mut engine = Engine::new();
+
+        // Make a deposit
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Lock the account through dispute + chargeback
+        engine
+            .process_transaction(create_transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(create_transaction(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
+
+        // Try various operations on locked account
+        let deposit = create_transaction(TransactionType::Deposit, 1, 2, Some(dec!(50.0)));
+        let withdrawal = create_transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(20.0)));
+        let dispute = create_transaction(TransactionType::Dispute, 1, 1, None);
+        let resolve = create_transaction(TransactionType::Resolve, 1, 1, None);
+        let chargeback = create_transaction(TransactionType::Chargeback, 1, 1, None);
+
+        for tx in [deposit, withdrawal, dispute, resolve, chargeback] {
+            assert!(matches!(
+                engine.process_transaction(tx.clone()),
+                Err(Error::AccountLocked)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_dispute_resolve_chargeback_only_for_deposits() {
+        let mut engine = Engine::new();
+
+        // First deposit to have funds
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        // Make a withdrawal
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(dec!(50.0)),
+            ))
+            .unwrap();
+
+        // Try dispute/resolve/chargeback on withdrawal
+        for tx_type in [
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Chargeback,
+        ] {
+            let result = engine.process_transaction(create_transaction(tx_type, 1, 2, None));
+            assert!(matches!(result, Err(Error::TransactionNotFound)));
+        }
+
+        // Verify account state hasn't changed
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(50.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.total(), dec!(50.0));
+    }
+
+    #[test]
+    fn test_default_precision_truncates_to_4dp() {
+        let mut engine = Engine::new();
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(0.12345)),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                2,
+                Some(dec!(0.123499999)),
+            ))
+            .unwrap();
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(0.2468));
+    }
+
+    #[test]
+    fn test_configured_precision() {
+        let mut engine = Engine::with_config(EngineConfig {
+            precision: Precision {
+                scale: 8,
+                rounding: Rounding::HalfEven,
+                strict: false,
+            },
+            ..Default::default()
+        });
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(0.123456785)),
+            ))
+            .unwrap();
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(0.12345678));
+    }
+
+    #[test]
+    fn test_strict_precision_rejects_excess_decimals() {
+        let mut engine = Engine::with_config(EngineConfig {
+            precision: Precision {
+                strict: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(100.0)),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(create_transaction(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(dec!(0.00001)),
+        ));
+        assert!(matches!(result, Err(Error::ExcessPrecision)));
+
+        let account = engine.accounts().next().unwrap();
+        assert_eq!(account.available, dec!(100.0));
+    }
+
+    #[test]
+    fn test_change_channel() {
+        let mut engine = Engine::new();
+        let deltas = engine.change_channel();
+        let transactions = [
+            create_transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0))),
+            create_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(20.0))),
+            create_transaction(TransactionType::Dispute, 1, 1, None),
+            create_transaction(TransactionType::Chargeback, 1, 1, None),
+        ];
+        for transaction in transactions {
+            let _ = engine.process_transaction(transaction);
+        }
+
+        // The rejected withdrawal emits no delta
+        let deltas: Vec<_> = deltas.try_iter().collect();
+        let balances = |available, held, locked| Balances {
+            available,
+            held,
+            locked,
+            flagged: false,
+        };
+        assert_eq!(
+            deltas
+                .iter()
+                .map(|delta| (delta.client, delta.tx, delta.before, delta.after))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    1,
+                    1,
+                    Balances::default(),
+                    balances(dec!(10.0), dec!(0), false)
+                ),
+                (
+                    1,
+                    1,
+                    balances(dec!(10.0), dec!(0), false),
+                    balances(dec!(0), dec!(10.0), false)
+                ),
+                (
+                    1,
+                    1,
+                    balances(dec!(0), dec!(10.0), false),
+                    balances(dec!(0), dec!(0), true)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_on_change_callback() {
+        let count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut engine = Engine::new();
+        let counter = count.clone();
+        engine.on_change(move |_| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        });
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(1.0)),
+            ))
+            .unwrap();
+        assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_event_sink() {
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut engine = Engine::new();
+        let sink = events.clone();
+        engine.subscribe(move |event: &Event| sink.lock().unwrap().push(event.clone()));
+
+        let transactions = [
+            create_transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0))),
+            create_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(20.0))),
+            create_transaction(TransactionType::Dispute, 1, 1, None),
+            create_transaction(TransactionType::Resolve, 1, 1, None),
+            create_transaction(TransactionType::Dispute, 1, 1, None),
+            create_transaction(TransactionType::Chargeback, 1, 1, None),
+        ];
+        for transaction in transactions {
+            let _ = engine.process_transaction(transaction);
+        }
+
+        let amount = dec!(10.0);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                Event::DepositApplied {
+                    client: 1,
+                    tx: 1,
+                    amount
+                },
+                Event::TransactionRejected {
+                    client: 1,
+                    tx: 2,
+                    tx_type: TransactionType::Withdrawal,
+                    reason: Error::InsufficientFunds
+                },
+                Event::FundsHeld {
+                    client: 1,
+                    tx: 1,
+                    amount
+                },
+                Event::FundsReleased {
+                    client: 1,
+                    tx: 1,
+                    amount
+                },
+                Event::FundsHeld {
+                    client: 1,
+                    tx: 1,
+                    amount
+                },
+                Event::ChargebackApplied {
+                    client: 1,
+                    tx: 1,
+                    amount
+                },
+                Event::AccountLocked { client: 1, tx: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_event_sink() {
+        /// Counts rejections per reason code
+        #[derive(Default)]
+        struct Rejections(std::sync::Arc<std::sync::Mutex<HashMap<&'static str, u32>>>);
+
+        impl EventSink for Rejections {
+            fn publish(&mut self, event: &Event) {
+                if let Event::TransactionRejected { reason, .. } = event {
+                    *self.0.lock().unwrap().entry(reason.code()).or_default() += 1;
+                }
+            }
+        }
+
+        let rejections = Rejections::default();
+        let counts = rejections.0.clone();
+        let mut engine = Engine::new();
+        engine.subscribe(rejections);
+        for tx in 1..=3 {
+            let _ = engine.process_transaction(create_transaction(
+                TransactionType::Withdrawal,
+                1,
+                tx,
+                Some(dec!(1.0)),
+            ));
+        }
+        assert_eq!(counts.lock().unwrap()["account_not_found"], 3);
+    }
+
+    #[test]
+    fn test_ledger_journal_and_trial_balance() {
+        let mut engine = Engine::with_config(EngineConfig {
+            keep_journal: true,
+            ..Default::default()
+        });
+        let transactions = [
+            create_transaction(TransactionType::Deposit, 1, 1, Some(dec!(10.0))),
+            create_transaction(TransactionType::Deposit, 2, 2, Some(dec!(5.0))),
+            create_transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(3.0))),
+            create_transaction(TransactionType::Withdrawal, 2, 4, Some(dec!(50.0))),
+            create_transaction(TransactionType::Dispute, 2, 2, None),
+            create_transaction(TransactionType::Chargeback, 2, 2, None),
+        ];
+        for transaction in transactions {
+            let _ = engine.process_transaction(transaction);
+        }
+
+        // The rejected withdrawal posts nothing
+        let entries: Vec<_> = engine
+            .journal()
+            .iter()
+            .map(|entry| (entry.tx, entry.debit, entry.credit))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (1, LedgerAccount::External, LedgerAccount::Available(1)),
+                (2, LedgerAccount::External, LedgerAccount::Available(2)),
+                (3, LedgerAccount::Available(1), LedgerAccount::External),
+                (2, LedgerAccount::Available(2), LedgerAccount::Held(2)),
+                (2, LedgerAccount::Held(2), LedgerAccount::Settlement),
+            ]
+        );
+        assert_eq!(
+            engine.ledger_balance(LedgerAccount::External),
+            Some(dec!(-12.0))
+        );
+        assert_eq!(
+            engine.ledger_balance(LedgerAccount::Settlement),
+            Some(dec!(5.0))
+        );
+
+        let trial_balance = engine.trial_balance();
+        assert_eq!(trial_balance.debits, dec!(28.0));
+        assert!(trial_balance.is_balanced());
+    }
+
+    #[test]
+    fn test_journal_not_kept_by_default() {
+        let mut engine = Engine::new();
+        engine
+            .process_transaction(create_transaction(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(1.0)),
+            ))
+            .unwrap();
+        assert!(engine.journal().is_empty());
+        assert!(engine.trial_balance().is_balanced());
+    }
+}
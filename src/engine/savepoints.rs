@@ -0,0 +1,198 @@
+//! Savepoints of the [`Engine`] state.
+//!
+//! A [`Savepoint`] allows processing tentative transactions, looking at the results, and
+//! then either keeping them with [`Engine::release`] or reverting them with
+//! [`Engine::rollback_to`]. While a savepoint is open, every applied transaction records
+//! the prior state of the account and deposit it touches, so setting a savepoint is cheap,
+//! and rolling back takes time linear in the number of transactions applied since.
+
+use std::error::Error;
+use std::fmt;
+
+use super::Engine;
+
+/// Savepoint set by [`Engine::savepoint`].
+///
+/// Savepoints nest: rolling back to or releasing a savepoint also closes the savepoints
+/// set after it.
+#[derive(Debug, PartialEq, Eq)]
+#[must_use = "a savepoint records changes until it is released or rolled back to"]
+pub struct Savepoint {
+    id: u64,
+}
+
+/// Returned when a savepoint was already closed by rolling back to or releasing
+/// a savepoint set before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownSavepoint;
+
+impl fmt::Display for UnknownSavepoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "savepoint was already closed by an enclosing savepoint")
+    }
+}
+
+impl Error for UnknownSavepoint {}
+
+impl Engine {
+    /// Sets a savepoint at the current state.
+    pub fn savepoint(&mut self) -> Savepoint {
+        Savepoint {
+            id: self.open_mark(),
+        }
+    }
+
+    /// Reverts every transaction applied since the savepoint was set, and closes it
+    /// together with the savepoints set after it.
+    ///
+    /// The change feed and event sinks are not notified of the rollback.
+    ///
+    /// # Errors
+    /// Returns [`UnknownSavepoint`] if the savepoint was already closed.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) -> Result<(), UnknownSavepoint> {
+        match self.rollback_mark(savepoint.id) {
+            true => Ok(()),
+            false => Err(UnknownSavepoint),
+        }
+    }
+
+    /// Keeps every transaction applied since the savepoint was set, and closes it
+    /// together with the savepoints set after it. Changes can still be rolled back by
+    /// an enclosing savepoint.
+    ///
+    /// # Errors
+    /// Returns [`UnknownSavepoint`] if the savepoint was already closed.
+    pub fn release(&mut self, savepoint: Savepoint) -> Result<(), UnknownSavepoint> {
+        match self.release_mark(savepoint.id) {
+            true => Ok(()),
+            false => Err(UnknownSavepoint),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientId, DuplicatePolicy, EngineConfig, Transaction, TransactionType, TxId};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn process(engine: &mut Engine, tx_type: TransactionType, client: ClientId, tx: TxId) {
+        let amount = matches!(
+            tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        )
+        .then_some(dec!(10.0));
+        let _ = engine.process_transaction(Transaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+            timestamp: None,
+        });
+    }
+
+    fn balances(engine: &Engine) -> Vec<(ClientId, Decimal, Decimal, bool)> {
+        let mut balances: Vec<_> = engine
+            .accounts()
+            .map(|account| (account.id, account.available, account.held, account.locked))
+            .collect();
+        balances.sort();
+        balances
+    }
+
+    #[test]
+    fn test_rollback_to_savepoint() {
+        let mut engine = Engine::with_config(EngineConfig {
+            keep_journal: true,
+            ..Default::default()
+        });
+        process(&mut engine, TransactionType::Deposit, 1, 1);
+        let before = balances(&engine);
+        let trial_balance = engine.trial_balance();
+
+        let savepoint = engine.savepoint();
+        process(&mut engine, TransactionType::Deposit, 2, 2);
+        process(&mut engine, TransactionType::Withdrawal, 1, 3);
+        process(&mut engine, TransactionType::Dispute, 2, 2);
+        process(&mut engine, TransactionType::Chargeback, 2, 2);
+        assert_eq!(
+            balances(&engine),
+            [(1, dec!(0), dec!(0), false), (2, dec!(0), dec!(0), true)]
+        );
+
+        assert_eq!(engine.rollback_to(savepoint), Ok(()));
+        assert_eq!(balances(&engine), before);
+        assert_eq!(engine.trial_balance(), trial_balance);
+        assert_eq!(engine.journal().len(), 1);
+        assert_eq!(engine.verify(), Ok(()));
+
+        // The reverted transaction IDs are free again
+        process(&mut engine, TransactionType::Withdrawal, 1, 3);
+        assert_eq!(balances(&engine), [(1, dec!(0), dec!(0), false)]);
+    }
+
+    #[test]
+    fn test_nested_savepoints() {
+        let mut engine = Engine::new();
+        let outer = engine.savepoint();
+        process(&mut engine, TransactionType::Deposit, 1, 1);
+
+        let inner = engine.savepoint();
+        process(&mut engine, TransactionType::Deposit, 1, 2);
+        assert_eq!(engine.release(inner), Ok(()));
+        assert_eq!(balances(&engine), [(1, dec!(20.0), dec!(0), false)]);
+
+        let inner = engine.savepoint();
+        process(&mut engine, TransactionType::Dispute, 1, 1);
+        assert_eq!(engine.rollback_to(inner), Ok(()));
+        assert_eq!(balances(&engine), [(1, dec!(20.0), dec!(0), false)]);
+
+        // Rolling back the outer savepoint also reverts the released changes,
+        // and closes the savepoints set after it
+        let inner = engine.savepoint();
+        assert_eq!(engine.rollback_to(outer), Ok(()));
+        assert!(balances(&engine).is_empty());
+        assert_eq!(engine.rollback_to(inner), Err(UnknownSavepoint));
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_rollback_frees_rejected_tx_ids() {
+        let mut engine = Engine::with_config(EngineConfig {
+            duplicate_policy: DuplicatePolicy::Seen,
+            ..Default::default()
+        });
+        let savepoint = engine.savepoint();
+        // Rejected for insufficient funds, and its ID used up
+        process(&mut engine, TransactionType::Withdrawal, 1, 1);
+        process(&mut engine, TransactionType::Deposit, 1, 2);
+        process(&mut engine, TransactionType::Withdrawal, 1, 1);
+        assert_eq!(balances(&engine), [(1, dec!(10.0), dec!(0), false)]);
+
+        assert_eq!(engine.rollback_to(savepoint), Ok(()));
+        process(&mut engine, TransactionType::Deposit, 1, 2);
+        process(&mut engine, TransactionType::Withdrawal, 1, 1);
+        assert_eq!(balances(&engine), [(1, dec!(0), dec!(0), false)]);
+    }
+
+    #[test]
+    fn test_batch_inside_savepoint() {
+        let mut engine = Engine::new();
+        let savepoint = engine.savepoint();
+        let batch = [1, 2].map(|tx| Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(dec!(1)),
+            timestamp: None,
+        });
+        assert_eq!(engine.process_batch(batch.clone()), Ok(2));
+        assert_eq!(balances(&engine), [(1, dec!(2), dec!(0), false)]);
+
+        // The committed batch is still reverted by the enclosing savepoint
+        assert_eq!(engine.rollback_to(savepoint), Ok(()));
+        assert!(balances(&engine).is_empty());
+        assert_eq!(engine.process_batch(batch), Ok(2));
+    }
+}
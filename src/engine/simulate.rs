@@ -0,0 +1,254 @@
+//! Dry-run evaluation of transactions.
+//!
+//! [`Engine::simulate`] answers "would this transaction succeed, and what would the
+//! balance be?" by running the transaction through the same pipeline as
+//! [`Engine::process_transaction`] under an undo mark, and rolling it back afterwards.
+//! [`Engine::simulate_batch`] evaluates a sequence of transactions the same way, each
+//! seeing the effects of the ones before it.
+
+use super::Engine;
+use crate::stores::{Account, LedgerEntry};
+use crate::{Error, Transaction};
+
+/// Would-be outcome of a transaction evaluated by [`Engine::simulate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulation {
+    /// Ledger entry the transaction would post
+    pub entry: LedgerEntry,
+    /// The client's account as it would be after the transaction
+    pub account: Account,
+}
+
+impl Engine {
+    /// Evaluates a transaction against the current state, leaving the state unchanged.
+    ///
+    /// The transaction runs through the middleware before-hooks, duplicate detection and
+    /// dispute aging exactly as when processed, so the outcome is the one processing it
+    /// would have. The after-hooks are not run, and change feeds and event sinks are not
+    /// notified. Only the engine state is rolled back: any state the before-hooks keep
+    /// is changed as if the transaction was processed.
+    ///
+    /// # Errors
+    /// Returns the domain [`Error`] the transaction would be rejected with.
+    pub fn simulate(&mut self, transaction: &Transaction) -> Result<Simulation, Error> {
+        self.simulate_batch([transaction.clone()])
+            .pop()
+            .expect("one result per transaction")
+    }
+
+    /// Evaluates the transactions in order against the current state, leaving the state
+    /// unchanged. Each transaction sees the effects of the ones before it, as if they were
+    /// processed (see [`Engine::simulate`]).
+    pub fn simulate_batch<I>(&mut self, transactions: I) -> Vec<Result<Simulation, Error>>
+    where
+        I: IntoIterator<Item = Transaction>,
+    {
+        let aged = std::mem::take(&mut self.aged);
+        let mark = self.open_mark();
+        let results = transactions
+            .into_iter()
+            .map(|transaction| self.dry_run(&transaction))
+            .collect();
+        self.rollback_mark(mark);
+        self.aged = aged;
+        results
+    }
+
+    /// Processes a transaction as [`Engine::process_transaction`] does, without running
+    /// the after-hooks or notifying the listeners, and returns its outcome.
+    fn dry_run(&mut self, transaction: &Transaction) -> Result<Simulation, Error> {
+        self.tick(transaction);
+        let result = self
+            .run_before_hooks(transaction)
+            .and_then(|()| self.apply(transaction));
+        self.age_disputes();
+        let entry = result?;
+        let account = self
+            .accounts
+            .get(transaction.client)
+            .cloned()
+            .expect("an applied transaction creates the account");
+        Ok(Simulation { entry, account })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::LedgerAccount;
+    use crate::{
+        AgingAction, AgingThreshold, ClientId, DisputeAging, DuplicatePolicy,
+        TransactionMiddleware, TransactionType, TxId,
+    };
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn transaction(
+        tx_type: TransactionType,
+        client: ClientId,
+        tx: TxId,
+        amount: Option<Decimal>,
+    ) -> Transaction {
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_simulate_does_not_change_state() {
+        let mut engine = Engine::new();
+        engine
+            .process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10))))
+            .unwrap();
+
+        let withdrawal = transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(4)));
+        let simulation = engine.simulate(&withdrawal).unwrap();
+        assert_eq!(
+            simulation.entry,
+            LedgerEntry {
+                tx: 2,
+                debit: LedgerAccount::Available(1),
+                credit: LedgerAccount::External,
+                amount: dec!(4),
+            }
+        );
+        assert_eq!(simulation.account.available, dec!(6));
+
+        // Nothing was applied, so the same withdrawal is still not a duplicate
+        assert_eq!(engine.simulate(&withdrawal), Ok(simulation));
+        assert_eq!(engine.accounts().next().unwrap().available, dec!(10));
+        assert_eq!(engine.verify(), Ok(()));
+
+        let too_much = transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(11)));
+        assert_eq!(engine.simulate(&too_much), Err(Error::InsufficientFunds));
+        let unknown = transaction(TransactionType::Dispute, 2, 1, None);
+        assert_eq!(
+            engine.simulate(&unknown),
+            Err(Error::TransactionClientMismatch)
+        );
+    }
+
+    #[test]
+    fn test_simulate_new_account_and_chargeback() {
+        let mut engine = Engine::new();
+        let deposit = transaction(TransactionType::Deposit, 1, 1, Some(dec!(5)));
+        let simulation = engine.simulate(&deposit).unwrap();
+        assert_eq!(simulation.account.available, dec!(5));
+        assert_eq!(engine.accounts().count(), 0);
+
+        engine.process_transaction(deposit).unwrap();
+        engine
+            .process_transaction(transaction(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        let chargeback = transaction(TransactionType::Chargeback, 1, 1, None);
+        let simulation = engine.simulate(&chargeback).unwrap();
+        assert_eq!(simulation.account.total(), dec!(0));
+        assert!(simulation.account.locked);
+        assert!(!engine.accounts().next().unwrap().locked);
+    }
+
+    #[test]
+    fn test_simulate_batch() {
+        let mut engine = Engine::new();
+        engine
+            .process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10))))
+            .unwrap();
+
+        let results = engine.simulate_batch([
+            transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(6))),
+            // Sees the withdrawal above
+            transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(6))),
+            transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(1))),
+            transaction(TransactionType::Deposit, 2, 4, Some(dec!(3))),
+        ]);
+        let available: Vec<_> = results
+            .into_iter()
+            .map(|result| result.map(|simulation| simulation.account.available))
+            .collect();
+        assert_eq!(
+            available,
+            [
+                Ok(dec!(4)),
+                Err(Error::InsufficientFunds),
+                Err(Error::DuplicateTransaction),
+                Ok(dec!(3)),
+            ]
+        );
+
+        // The engine itself is unchanged
+        assert_eq!(engine.accounts().count(), 1);
+        assert_eq!(engine.accounts().next().unwrap().available, dec!(10));
+    }
+
+    struct Blocklist(ClientId);
+
+    impl TransactionMiddleware for Blocklist {
+        fn before(&mut self, transaction: &Transaction) -> Result<(), Error> {
+            match transaction.client == self.0 {
+                true => Err(Error::Rejected("client_blocked")),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_simulate_runs_middleware() {
+        let mut engine = Engine::builder().middleware(Blocklist(2)).build();
+        let deposit = transaction(TransactionType::Deposit, 2, 1, Some(dec!(5)));
+        assert_eq!(
+            engine.simulate(&deposit),
+            Err(Error::Rejected("client_blocked"))
+        );
+        assert_eq!(
+            engine.process_transaction(deposit),
+            Err(Error::Rejected("client_blocked"))
+        );
+    }
+
+    #[test]
+    fn test_simulate_burns_ids_and_ages_disputes() {
+        let mut engine = Engine::builder()
+            .duplicate_policy(DuplicatePolicy::Seen)
+            .dispute_aging(DisputeAging {
+                after: AgingThreshold::Transactions(1),
+                action: AgingAction::Resolve,
+            })
+            .build();
+        engine
+            .process_transaction(transaction(TransactionType::Deposit, 1, 1, Some(dec!(10))))
+            .unwrap();
+
+        let results = engine.simulate_batch([
+            // Burns tx 2
+            transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(11))),
+            transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(1))),
+            // Resolved by aging after the next transaction
+            transaction(TransactionType::Dispute, 1, 1, None),
+            transaction(TransactionType::Deposit, 1, 3, Some(dec!(1))),
+        ]);
+        let outcomes: Vec<_> = results
+            .into_iter()
+            .map(|result| result.map(|simulation| simulation.account.held))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                Err(Error::InsufficientFunds),
+                Err(Error::InsufficientFunds),
+                Ok(dec!(10)),
+                Ok(dec!(0)),
+            ]
+        );
+
+        // Nothing was applied
+        let withdrawal = transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(1)));
+        assert!(engine.simulate(&withdrawal).is_ok());
+        assert_eq!(engine.open_disputes().count(), 0);
+        assert_eq!(engine.accounts().next().unwrap().available, dec!(10));
+        assert_eq!(engine.verify(), Ok(()));
+    }
+}
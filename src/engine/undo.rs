@@ -0,0 +1,149 @@
+//! Undo log of the [`Engine`] state.
+//!
+//! While a mark is open, every applied transaction records the prior state of the account
+//! and deposit it touches, as does every rejected transaction whose ID is used up.
+//! Rolling back to a mark puts them back in reverse order, and resets the ledger and flow
+//! totals to the ones at the mark, leaving the engine exactly as it was when the mark was
+//! opened. Marks nest, and back both savepoints and batches.
+
+use rust_decimal::Decimal;
+
+use super::verify::Flows;
+use super::Engine;
+use crate::dto::{ClientId, Transaction, TransactionType, TxId};
+use crate::stores::{Account, DisputeOpened, LedgerMark, StoredDeposit};
+
+/// State overwritten by a single applied transaction.
+struct Change {
+    tx_type: TransactionType,
+    client: ClientId,
+    tx: TxId,
+    /// The client's account before the transaction, `None` if it did not exist
+    account: Option<Account>,
+    /// The deposit with the transaction's ID before the transaction, `None` if it did not exist
+    deposit: Option<StoredDeposit>,
+    /// The shortfall of the deposit before the transaction
+    shortfall: Decimal,
+    /// When the dispute of the deposit was opened before the transaction, if it was open
+    dispute_opened: Option<DisputeOpened>,
+}
+
+/// Engine totals at the time a mark was opened.
+struct Mark {
+    id: u64,
+    ledger: LedgerMark,
+    flows: Flows,
+    /// Number of transactions processed before the mark
+    processed: u64,
+    /// Latest transaction timestamp before the mark
+    now: Option<u64>,
+    /// Number of changes recorded before the mark
+    changes: usize,
+}
+
+/// Changes applied to the engine since the outermost open mark.
+#[derive(Default)]
+pub(super) struct UndoLog {
+    changes: Vec<Change>,
+    /// Open marks, outermost first
+    marks: Vec<Mark>,
+    next_id: u64,
+}
+
+impl UndoLog {
+    /// Whether any mark is open, and applied transactions must be recorded.
+    pub fn is_recording(&self) -> bool {
+        !self.marks.is_empty()
+    }
+
+    /// Position of the open mark with the given id.
+    fn position(&self, id: u64) -> Option<usize> {
+        self.marks.iter().position(|mark| mark.id == id)
+    }
+
+    /// Drops the marks from the given position on, and the changes no mark needs anymore.
+    fn close(&mut self, position: usize) {
+        self.marks.truncate(position);
+        if self.marks.is_empty() {
+            self.changes.clear();
+        }
+    }
+}
+
+impl Engine {
+    /// Opens a mark at the current state, returning its id.
+    pub(super) fn open_mark(&mut self) -> u64 {
+        let id = self.undo.next_id;
+        self.undo.next_id += 1;
+        self.undo.marks.push(Mark {
+            id,
+            ledger: self.ledger.mark(),
+            flows: self.flows,
+            processed: self.processed,
+            now: self.now,
+            changes: self.undo.changes.len(),
+        });
+        id
+    }
+
+    /// Records the state a planned transaction is about to change, if any mark is open.
+    pub(super) fn record(&mut self, transaction: &Transaction) {
+        if !self.undo.is_recording() {
+            return;
+        }
+        let (client, tx) = (transaction.client, transaction.tx);
+        self.undo.changes.push(Change {
+            tx_type: transaction.tx_type,
+            client,
+            tx,
+            account: self.accounts.get(client).cloned(),
+            deposit: self.transactions.get_deposit(client, tx).ok().cloned(),
+            shortfall: self.transactions.shortfall(client, tx),
+            dispute_opened: self.transactions.dispute_opened(client, tx),
+        });
+    }
+
+    /// Closes the mark and the marks opened after it, keeping their changes.
+    /// Returns `false` if the mark is not open.
+    pub(super) fn release_mark(&mut self, id: u64) -> bool {
+        let Some(position) = self.undo.position(id) else {
+            return false;
+        };
+        self.undo.close(position);
+        true
+    }
+
+    /// Rolls back every change recorded since the mark, and closes it together with the
+    /// marks opened after it. Returns `false` if the mark is not open.
+    pub(super) fn rollback_mark(&mut self, id: u64) -> bool {
+        let Some(position) = self.undo.position(id) else {
+            return false;
+        };
+        let mark = &self.undo.marks[position];
+        let (ledger, flows, processed, now) = (mark.ledger, mark.flows, mark.processed, mark.now);
+        let changes = self.undo.changes.split_off(mark.changes);
+        for change in changes.into_iter().rev() {
+            self.accounts.restore(change.client, change.account);
+            self.transactions
+                .restore_deposit(change.client, change.tx, change.deposit);
+            self.transactions
+                .set_shortfall(change.client, change.tx, change.shortfall);
+            self.transactions
+                .set_dispute_opened(change.client, change.tx, change.dispute_opened);
+            if matches!(
+                change.tx_type,
+                TransactionType::Deposit | TransactionType::Withdrawal
+            ) {
+                self.transactions.unmark_processed(change.client, change.tx);
+                self.transactions.unmark_rejected(change.client, change.tx);
+            }
+        }
+        self.ledger.rollback(ledger);
+        self.flows = flows;
+        self.processed = processed;
+        self.now = now;
+        self.aged.clear();
+        self.undo.close(position);
+        true
+    }
+}
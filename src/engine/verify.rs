@@ -0,0 +1,297 @@
+//! Global invariants of the [`Engine`] state.
+//!
+//! [`Engine::verify`] cross-checks the account balances against the ledger, the funds
+//! that entered and left the engine, and the stored deposits, to catch silent balance
+//! drift as soon as it happens.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use rust_decimal::Decimal;
+
+use super::Engine;
+use crate::dto::ClientId;
+
+/// Funds that entered or left the engine through accepted transactions.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct Flows {
+    pub deposited: Decimal,
+    pub withdrawn: Decimal,
+    pub charged_back: Decimal,
+}
+
+/// A broken invariant of the engine state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The ledger's debits and credits, or its balances, do not cancel out.
+    Unbalanced {
+        debits: Decimal,
+        credits: Decimal,
+        net: Decimal,
+    },
+    /// The sum of client totals differs from deposits minus withdrawals minus chargebacks.
+    Conservation { total: Decimal, expected: Decimal },
+    /// A client's held funds differ from the sum of its disputed deposits, less the
+    /// shortfalls that could not be held.
+    Held {
+        client: ClientId,
+        held: Decimal,
+        disputed: Decimal,
+    },
+    /// A client's receivable differs from the shortfalls of its disputed and
+    /// charged back deposits.
+    Receivable {
+        client: ClientId,
+        receivable: Decimal,
+        shortfalls: Decimal,
+    },
+    /// A client's account is locked, but none of its deposits was charged back.
+    LockedWithoutChargeback { client: ClientId },
+    /// A client's chargeback count differs from the number of its charged back deposits.
+    Chargebacks {
+        client: ClientId,
+        chargebacks: u32,
+        charged_back: u32,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Unbalanced {
+                debits,
+                credits,
+                net,
+            } => write!(
+                f,
+                "ledger is unbalanced: debits {}, credits {}, net balance {}",
+                debits, credits, net
+            ),
+            Violation::Conservation { total, expected } => write!(
+                f,
+                "client totals sum to {}, expected deposits minus withdrawals minus chargebacks of {}",
+                total, expected
+            ),
+            Violation::Held {
+                client,
+                held,
+                disputed,
+            } => write!(
+                f,
+                "client {} holds {}, but its disputed deposits sum to {}",
+                client, held, disputed
+            ),
+            Violation::Receivable {
+                client,
+                receivable,
+                shortfalls,
+            } => write!(
+                f,
+                "client {} is owed {}, but the shortfalls of its disputes sum to {}",
+                client, receivable, shortfalls
+            ),
+            Violation::LockedWithoutChargeback { client } => {
+                write!(f, "client {} is locked without a chargeback", client)
+            }
+            Violation::Chargebacks {
+                client,
+                chargebacks,
+                charged_back,
+            } => write!(
+                f,
+                "client {} counts {} chargeback(s), but {} of its deposits were charged back",
+                client, chargebacks, charged_back
+            ),
+        }
+    }
+}
+
+/// Returned by [`Engine::verify`] when the engine state breaks any invariant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invariant violation(s)", self.violations.len())?;
+        for violation in &self.violations {
+            write!(f, "; {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for VerifyError {}
+
+impl Engine {
+    /// Checks the global invariants of the engine state:
+    /// * the ledger is balanced (see [`Engine::trial_balance`]),
+    /// * the sum of client totals equals accepted deposits minus withdrawals minus
+    ///   charged-back amounts,
+    /// * each client's held funds equal the sum of its currently disputed deposits, less
+    ///   the shortfalls that could not be held,
+    /// * each client's receivable equals the shortfalls of its disputed and charged back
+    ///   deposits,
+    /// * a locked account had at least one deposit charged back,
+    /// * each client's chargeback count equals its number of charged back deposits.
+    ///
+    /// Takes time linear in the number of stored deposits.
+    ///
+    /// # Errors
+    /// Returns a [`VerifyError`] listing every broken invariant.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let mut violations = Vec::new();
+
+        let trial_balance = self.trial_balance();
+        if !trial_balance.is_balanced() {
+            violations.push(Violation::Unbalanced {
+                debits: trial_balance.debits,
+                credits: trial_balance.credits,
+                net: trial_balance.net,
+            });
+        }
+
+        let total: Decimal = self.accounts().map(|account| account.total()).sum();
+        let expected = self.flows.deposited - self.flows.withdrawn - self.flows.charged_back;
+        if total != expected {
+            violations.push(Violation::Conservation { total, expected });
+        }
+
+        let mut disputed: HashMap<ClientId, Decimal> = HashMap::new();
+        let mut charged_back: HashMap<ClientId, u32> = HashMap::new();
+        for deposit in self.transactions.deposits() {
+            if deposit.disputed {
+                *disputed.entry(deposit.client).or_default() += deposit.amount;
+            }
+            if deposit.charged_back {
+                *charged_back.entry(deposit.client).or_default() += 1;
+            }
+        }
+        let mut shortfalls: HashMap<ClientId, Decimal> = HashMap::new();
+        for (deposit, shortfall) in self.transactions.shortfalls() {
+            *shortfalls.entry(deposit.client).or_default() += shortfall;
+            if deposit.disputed {
+                *disputed.entry(deposit.client).or_default() -= shortfall;
+            }
+        }
+        let mut accounts: Vec<_> = self.accounts().collect();
+        accounts.sort_by_key(|account| account.id);
+        for account in accounts {
+            let client = account.id;
+            let disputed = disputed.get(&client).copied().unwrap_or_default();
+            if account.held != disputed {
+                violations.push(Violation::Held {
+                    client,
+                    held: account.held,
+                    disputed,
+                });
+            }
+            let shortfalls = shortfalls.get(&client).copied().unwrap_or_default();
+            if account.receivable != shortfalls {
+                violations.push(Violation::Receivable {
+                    client,
+                    receivable: account.receivable,
+                    shortfalls,
+                });
+            }
+            let charged_back = charged_back.get(&client).copied().unwrap_or_default();
+            if account.locked && charged_back == 0 {
+                violations.push(Violation::LockedWithoutChargeback { client });
+            }
+            if account.chargebacks != charged_back {
+                violations.push(Violation::Chargebacks {
+                    client,
+                    chargebacks: account.chargebacks,
+                    charged_back,
+                });
+            }
+        }
+
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(VerifyError { violations }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Transaction, TransactionType, TxId};
+    use rust_decimal_macros::dec;
+
+    fn process(engine: &mut Engine, tx_type: TransactionType, client: ClientId, tx: TxId) {
+        let amount = matches!(
+            tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        )
+        .then_some(dec!(10.0));
+        let _ = engine.process_transaction(Transaction {
+            tx_type,
+            client,
+            tx,
+            amount,
+            timestamp: None,
+        });
+    }
+
+    #[test]
+    fn test_verify_after_processing() {
+        let mut engine = Engine::new();
+        process(&mut engine, TransactionType::Deposit, 1, 1);
+        process(&mut engine, TransactionType::Deposit, 1, 2);
+        process(&mut engine, TransactionType::Withdrawal, 1, 3);
+        process(&mut engine, TransactionType::Deposit, 2, 4);
+        process(&mut engine, TransactionType::Dispute, 2, 4);
+        process(&mut engine, TransactionType::Deposit, 3, 5);
+        process(&mut engine, TransactionType::Dispute, 3, 5);
+        process(&mut engine, TransactionType::Chargeback, 3, 5);
+        assert_eq!(engine.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_reports_drift() {
+        let mut engine = Engine::new();
+        process(&mut engine, TransactionType::Deposit, 1, 1);
+        process(&mut engine, TransactionType::Deposit, 2, 2);
+
+        // Simulate balance drift behind the ledger's back
+        let account = engine.accounts.get_or_create_mut(1);
+        account.held += dec!(1);
+        let account = engine.accounts.get_or_create_mut(2);
+        account.locked = true;
+        account.chargebacks += 1;
+
+        let err = engine.verify().unwrap_err();
+        assert_eq!(
+            err.violations,
+            [
+                Violation::Unbalanced {
+                    debits: dec!(20.0),
+                    credits: dec!(20.0),
+                    net: dec!(1)
+                },
+                Violation::Conservation {
+                    total: dec!(21.0),
+                    expected: dec!(20.0)
+                },
+                Violation::Held {
+                    client: 1,
+                    held: dec!(1),
+                    disputed: dec!(0)
+                },
+                Violation::LockedWithoutChargeback { client: 2 },
+                Violation::Chargebacks {
+                    client: 2,
+                    chargebacks: 1,
+                    charged_back: 0
+                },
+            ]
+        );
+        assert!(err
+            .to_string()
+            .starts_with("5 invariant violation(s); ledger is unbalanced"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 /// Domain-specific errors for the payment processing system.
 ///
 /// Contains error variants for common failure cases like:
@@ -7,16 +9,123 @@
 ///
 /// These errors represent business logic failures rather than
 /// technical errors like I/O or parsing issues.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
+    AccountLimitReached,
     AccountLocked,
     AccountNotFound,
     AmountMustBePositive,
+    AmountOverLimit,
     DuplicateTransaction,
+    ExcessPrecision,
     InsufficientFunds,
+    InsufficientFundsForDispute,
     InvalidTransaction,
+    OpenDisputeLimitReached,
     TransactionAlreadyDisputed,
     TransactionClientMismatch,
+    TransactionLimitReached,
     TransactionNotDisputed,
     TransactionNotFound,
+    /// Rejected by a [`TransactionMiddleware`](crate::TransactionMiddleware),
+    /// with the middleware's own reason code.
+    Rejected(&'static str),
+}
+
+impl Error {
+    /// Stable, machine-readable reason code for the error.
+    /// Codes are part of the outcome journal format and must not change.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::AccountLimitReached => "account_limit_reached",
+            Error::AccountLocked => "account_locked",
+            Error::AccountNotFound => "account_not_found",
+            Error::AmountMustBePositive => "amount_must_be_positive",
+            Error::AmountOverLimit => "amount_over_limit",
+            Error::DuplicateTransaction => "duplicate_transaction",
+            Error::ExcessPrecision => "excess_precision",
+            Error::InsufficientFunds => "insufficient_funds",
+            Error::InsufficientFundsForDispute => "insufficient_funds_for_dispute",
+            Error::InvalidTransaction => "invalid_transaction",
+            Error::OpenDisputeLimitReached => "open_dispute_limit_reached",
+            Error::TransactionAlreadyDisputed => "transaction_already_disputed",
+            Error::TransactionClientMismatch => "transaction_client_mismatch",
+            Error::TransactionLimitReached => "transaction_limit_reached",
+            Error::TransactionNotDisputed => "transaction_not_disputed",
+            Error::TransactionNotFound => "transaction_not_found",
+            Error::Rejected(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Error::AccountLimitReached => "maximum number of accounts reached",
+            Error::AccountLocked => "account is locked",
+            Error::AccountNotFound => "account not found",
+            Error::AmountMustBePositive => "amount must be positive",
+            Error::AmountOverLimit => "amount exceeds the maximum allowed",
+            Error::DuplicateTransaction => "transaction id was already processed",
+            Error::ExcessPrecision => "amount has more decimal places than allowed",
+            Error::InsufficientFunds => "insufficient available funds",
+            Error::InsufficientFundsForDispute => {
+                "insufficient available funds to hold the disputed amount"
+            }
+            Error::InvalidTransaction => "transaction is missing required fields",
+            Error::OpenDisputeLimitReached => "maximum number of open disputes reached",
+            Error::TransactionAlreadyDisputed => "transaction is already disputed",
+            Error::TransactionClientMismatch => "transaction belongs to a different client",
+            Error::TransactionLimitReached => "maximum number of transactions reached",
+            Error::TransactionNotDisputed => "transaction is not disputed",
+            Error::TransactionNotFound => "transaction not found",
+            Error::Rejected(_) => "rejected by middleware",
+        };
+        write!(f, "{} ({})", message, self.code())
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const ALL: [Error; 17] = [
+        Error::AccountLimitReached,
+        Error::AccountLocked,
+        Error::AccountNotFound,
+        Error::AmountMustBePositive,
+        Error::AmountOverLimit,
+        Error::DuplicateTransaction,
+        Error::ExcessPrecision,
+        Error::InsufficientFunds,
+        Error::InsufficientFundsForDispute,
+        Error::InvalidTransaction,
+        Error::OpenDisputeLimitReached,
+        Error::TransactionAlreadyDisputed,
+        Error::TransactionClientMismatch,
+        Error::TransactionLimitReached,
+        Error::TransactionNotDisputed,
+        Error::TransactionNotFound,
+        Error::Rejected("client_blocked"),
+    ];
+
+    #[test]
+    fn test_codes_are_unique() {
+        let codes: HashSet<_> = ALL.iter().map(Error::code).collect();
+        assert_eq!(codes.len(), ALL.len());
+    }
+
+    #[test]
+    fn test_display_includes_code() {
+        assert_eq!(
+            Error::InsufficientFunds.to_string(),
+            "insufficient available funds (insufficient_funds)"
+        );
+        for err in ALL {
+            assert!(err.to_string().ends_with(&format!("({})", err.code())));
+        }
+    }
 }
@@ -0,0 +1,207 @@
+//! NDJSON (newline-delimited JSON) deserialization utilities.
+//!
+//! Provides generic functions for reading NDJSON data, where every non-blank line
+//! holds exactly one JSON record.
+
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// Error produced while reading NDJSON records.
+/// Deserialization errors carry the 1-based line number of the offending record.
+#[derive(Debug)]
+pub enum NdjsonError {
+    Io(io::Error),
+    Deserialize {
+        line: u64,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for NdjsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NdjsonError::Io(err) => write!(f, "NDJSON read error: {}", err),
+            NdjsonError::Deserialize { line, source } => {
+                write!(f, "NDJSON deserialize error: line {}: {}", line, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NdjsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NdjsonError::Io(err) => Some(err),
+            NdjsonError::Deserialize { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<io::Error> for NdjsonError {
+    fn from(err: io::Error) -> Self {
+        NdjsonError::Io(err)
+    }
+}
+
+/// Creates an iterator that reads NDJSON records from a buffered reader.
+/// Blank lines are skipped, but still counted towards the reported line numbers.
+pub fn read_ndjson<T, R>(reader: R) -> impl Iterator<Item = Result<T, NdjsonError>>
+where
+    T: DeserializeOwned,
+    R: BufRead,
+{
+    reader
+        .lines()
+        .zip(1..)
+        .filter_map(|(line, line_no)| match line {
+            Ok(line) => parse_ndjson_line(&line, line_no),
+            Err(err) => Some(Err(NdjsonError::Io(err))),
+        })
+}
+
+/// Deserializes a single NDJSON line into type T.
+/// Returns `None` for blank lines, which carry no record.
+pub fn parse_ndjson_line<T>(line: &str, line_no: u64) -> Option<Result<T, NdjsonError>>
+where
+    T: DeserializeOwned,
+{
+    // Strip the UTF-8 BOM some editors add at the start of the file
+    let line = match line_no {
+        1 => line.trim_start_matches('\u{feff}').trim(),
+        _ => line.trim(),
+    };
+    if line.is_empty() {
+        return None;
+    }
+    Some(
+        serde_json::from_str(line).map_err(|source| NdjsonError::Deserialize {
+            line: line_no,
+            source,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dto::Transaction, TransactionType};
+    use rust_decimal_macros::dec;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    fn parse_all(input: &str) -> Result<Vec<Transaction>, NdjsonError> {
+        read_ndjson(input.as_bytes()).collect()
+    }
+
+    #[test]
+    fn test_read_ndjson() -> Result<(), NdjsonError> {
+        let transactions: Vec<Transaction> =
+            read_ndjson(BufReader::new(File::open("data/example_input.ndjson")?))
+                .collect::<Result<_, _>>()?;
+
+        let expected_transactions = vec![
+            Transaction {
+                tx_type: TransactionType::Deposit,
+                client: 1,
+                tx: 1,
+                amount: Some(dec!(1.0)),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Deposit,
+                client: 2,
+                tx: 2,
+                amount: Some(dec!(2.0)),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Deposit,
+                client: 1,
+                tx: 3,
+                amount: Some(dec!(2.0)),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Withdrawal,
+                client: 1,
+                tx: 4,
+                amount: Some(dec!(1.5)),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Withdrawal,
+                client: 2,
+                tx: 5,
+                amount: Some(dec!(3.0)),
+                timestamp: None,
+            },
+        ];
+        assert_eq!(transactions, expected_transactions);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_amount_as_string_or_number() -> Result<(), NdjsonError> {
+        let transactions = parse_all(
+            r#"{"type":"deposit","client":1,"tx":1,"amount":"0.12345"}
+{"type":"deposit","client":1,"tx":2,"amount":0.12345}"#,
+        )?;
+
+        // Both representations keep all their decimal places
+        assert_eq!(transactions[0].amount, Some(dec!(0.12345)));
+        assert_eq!(transactions[1].amount, Some(dec!(0.12345)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_strips_bom() -> Result<(), NdjsonError> {
+        let transactions =
+            parse_all("\u{feff}{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":1.5}")?;
+        assert_eq!(transactions[0].amount, Some(dec!(1.5)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_or_null_amount() -> Result<(), NdjsonError> {
+        let transactions = parse_all(
+            r#"{"type":"dispute","client":1,"tx":1}
+{"type":"resolve","client":1,"tx":1,"amount":null}"#,
+        )?;
+
+        assert_eq!(transactions[0].tx_type, TransactionType::Dispute);
+        assert_eq!(transactions[0].amount, None);
+        assert_eq!(transactions[1].tx_type, TransactionType::Resolve);
+        assert_eq!(transactions[1].amount, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_blank_lines_are_skipped() -> Result<(), NdjsonError> {
+        let transactions =
+            parse_all("\n{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":1}\n   \n")?;
+        assert_eq!(transactions.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_reports_line_number() {
+        let result = parse_all(
+            r#"{"type":"deposit","client":1,"tx":1,"amount":"1.0"}
+
+{"type":"deposit","client":2,"tx":2,"amount":"abc"}"#,
+        );
+
+        match result {
+            Err(NdjsonError::Deserialize { line, .. }) => assert_eq!(line, 3),
+            other => panic!("expected deserialize error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_transaction_type() {
+        let result = parse_all(r#"{"type":"Deposit","client":1,"tx":1,"amount":"1.0"}"#);
+        assert!(result.is_err());
+    }
+}
@@ -1,12 +1,27 @@
+mod binary_utils;
 mod csv_utils;
 mod dto;
 mod engine;
 mod error;
+mod json_utils;
 mod runner;
 mod stores;
 
-pub use dto::{Transaction, TransactionType};
-pub use engine::Engine;
+pub use binary_utils::{BinaryError, BinaryReader, BinaryWriter};
+pub use csv_utils::{read_csv_into_iter, CsvDialect, HeaderAliases};
+pub use dto::{ClientId, Transaction, TransactionType, TxId};
+pub use engine::{
+    AccountDelta, AgingAction, AgingThreshold, Balances, BatchError, ChargebackPolicy,
+    DisputeAging, DuplicatePolicy, Engine, EngineBuilder, EngineConfig, EngineConfigError, Event,
+    EventSink, Limits, NegativeBalancePolicy, OpenDispute, Precision, ProcessedIds, Rounding,
+    Savepoint, Simulation, StoreConfig, TransactionMiddleware, TxNamespace, UnknownSavepoint,
+    VerifyError, Violation,
+};
 pub use error::Error;
-pub use runner::{run, run_async};
-pub use stores::Account;
+pub use json_utils::{read_ndjson, NdjsonError};
+pub use runner::{
+    replay, run, run_async, run_async_with_config, run_with_config, simulate, validate,
+    Compression, InputFormat, Issue, IssueKind, ParseMode, RunConfig, RunError, Severity,
+    SnapshotConfig, SnapshotMode, TooManyRejects, ValidationReport, SNAPSHOT_MARKER,
+};
+pub use stores::{Account, LedgerAccount, LedgerEntry, TrialBalance};
@@ -2,20 +2,191 @@ use std::env;
 use std::error::Error;
 use std::process;
 
-use rusty_reckoning::run;
+use rusty_reckoning::{
+    replay, run_with_config, simulate, validate, CsvDialect, Engine, EngineConfig, ParseMode,
+    Precision, RunConfig, Severity, SnapshotConfig,
+};
+
+const USAGE: &str = "Usage: cargo run -- [validate | simulate [--base base.csv]] [--config engine.toml] [--format csv|ndjson|binary] [--compression none|gzip|zstd] [--lenient] [--max-errors N] [--rejects rejects.csv] [--outcomes outcomes.csv] [--deltas deltas.ndjson] [--precision N] [--rounding to-zero|half-even|half-up] [--strict-precision] [--tx-namespace global|per-client] [--duplicate-policy accepted|seen] [--negative-balance-policy allow|reject|hold-available] [--chargeback-policy lock|flag|lock-after=N|lock-over-ratio=R] [--dispute-aging resolve=AGE|chargeback=AGE] [--open-disputes open_disputes.csv] [--delimiter C] [--quote C] [--header-alias name=field] [--columns type,client,tx,amount] [--ignore-type-case] [--snapshot-dir DIR] [--snapshot-every N] [--snapshot-on-marker] [--snapshot-mode full|changed] [--verify] [--verify-every N] transactions.csv";
+
+/// Exit code of `validate` when the report only contains warnings.
+const EXIT_WARNINGS: i32 = 2;
+/// Exit code of `validate` when the report contains errors.
+const EXIT_ERRORS: i32 = 3;
 
 fn main() {
-    if let Err(err) = run_app() {
-        eprintln!("Error: {}", err);
-        process::exit(1);
+    match run_app() {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
     }
 }
 
-fn run_app() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        return Err("Usage: cargo run -- transactions.csv".into());
+/// Runs the requested command, returning the process exit code.
+fn run_app() -> Result<i32, Box<dyn Error>> {
+    let mut config = RunConfig::default();
+    let mut input_path = None;
+    let mut base_path = None;
+    let mut lenient = false;
+
+    // The engine policy file is loaded first, so that flags override it wherever they are
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--config") {
+        let config_path = args.get(position + 1).ok_or(USAGE)?;
+        config.engine = EngineConfig::load(config_path)?;
+    }
+
+    let mut args = args.into_iter().peekable();
+    let validate_only = args.next_if(|arg| arg == "validate").is_some();
+    let simulate_only = !validate_only && args.next_if(|arg| arg == "simulate").is_some();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--base" if simulate_only => base_path = Some(args.next().ok_or(USAGE)?),
+            "--config" => {
+                args.next();
+            }
+            "--format" => {
+                let format = args.next().ok_or(USAGE)?;
+                config.format = Some(format.parse()?);
+            }
+            "--compression" => {
+                let compression = args.next().ok_or(USAGE)?;
+                config.compression = Some(compression.parse()?);
+            }
+            "--lenient" => lenient = true,
+            "--max-errors" => {
+                let max_errors = args.next().ok_or(USAGE)?;
+                config.parse_mode = ParseMode::Lenient {
+                    max_errors: Some(max_errors.parse()?),
+                };
+            }
+            "--rejects" => {
+                let rejects_path = args.next().ok_or(USAGE)?;
+                config.rejects_path = Some(rejects_path.into());
+                lenient = true;
+            }
+            "--outcomes" => {
+                let outcomes_path = args.next().ok_or(USAGE)?;
+                config.outcomes_path = Some(outcomes_path.into());
+            }
+            "--deltas" => {
+                let deltas_path = args.next().ok_or(USAGE)?;
+                config.deltas_path = Some(deltas_path.into());
+            }
+            "--precision" => {
+                let scale = args.next().ok_or(USAGE)?;
+                config.engine.precision.scale = Precision::check_scale(scale.parse()?)?;
+            }
+            "--rounding" => {
+                let rounding = args.next().ok_or(USAGE)?;
+                config.engine.precision.rounding = rounding.parse()?;
+            }
+            "--strict-precision" => config.engine.precision.strict = true,
+            "--tx-namespace" => {
+                let namespace = args.next().ok_or(USAGE)?;
+                config.engine.tx_namespace = namespace.parse()?;
+            }
+            "--duplicate-policy" => {
+                let policy = args.next().ok_or(USAGE)?;
+                config.engine.duplicate_policy = policy.parse()?;
+            }
+            "--negative-balance-policy" => {
+                let policy = args.next().ok_or(USAGE)?;
+                config.engine.negative_balance_policy = policy.parse()?;
+            }
+            "--chargeback-policy" => {
+                let policy = args.next().ok_or(USAGE)?;
+                config.engine.chargeback_policy = policy.parse()?;
+            }
+            "--dispute-aging" => {
+                let aging = args.next().ok_or(USAGE)?;
+                config.engine.dispute_aging = Some(aging.parse()?);
+            }
+            "--open-disputes" => {
+                let open_disputes_path = args.next().ok_or(USAGE)?;
+                config.open_disputes_path = Some(open_disputes_path.into());
+            }
+            "--delimiter" => {
+                let delimiter = args.next().ok_or(USAGE)?;
+                config.csv.delimiter = CsvDialect::parse_char(&delimiter)?;
+            }
+            "--quote" => {
+                let quote = args.next().ok_or(USAGE)?;
+                config.csv.quote = CsvDialect::parse_char(&quote)?;
+            }
+            "--header-alias" => {
+                let alias = args.next().ok_or(USAGE)?;
+                let (name, field) = alias.split_once('=').ok_or(USAGE)?;
+                config.csv.header_aliases.insert(name, field)?;
+            }
+            "--columns" => {
+                let columns = args.next().ok_or(USAGE)?;
+                config.csv.columns = Some(columns.split(',').map(String::from).collect());
+            }
+            "--ignore-type-case" => config.csv.case_insensitive_types = true,
+            "--snapshot-dir" => {
+                let dir = args.next().ok_or(USAGE)?;
+                config
+                    .snapshots
+                    .get_or_insert_with(SnapshotConfig::default)
+                    .dir = dir.into();
+            }
+            "--snapshot-every" => {
+                let every = args.next().ok_or(USAGE)?;
+                config
+                    .snapshots
+                    .get_or_insert_with(SnapshotConfig::default)
+                    .every = Some(every.parse()?);
+            }
+            "--snapshot-on-marker" => {
+                config
+                    .snapshots
+                    .get_or_insert_with(SnapshotConfig::default)
+                    .on_marker = true
+            }
+            "--snapshot-mode" => {
+                let mode = args.next().ok_or(USAGE)?;
+                config
+                    .snapshots
+                    .get_or_insert_with(SnapshotConfig::default)
+                    .mode = mode.parse()?;
+            }
+            "--verify" => config.verify = true,
+            "--verify-every" => {
+                let every = args.next().ok_or(USAGE)?;
+                config.verify_every = Some(every.parse()?);
+            }
+            _ if input_path.is_none() => input_path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let input_path = input_path.ok_or(USAGE)?;
+    if lenient && config.parse_mode == ParseMode::Strict {
+        config.parse_mode = ParseMode::Lenient { max_errors: None };
+    }
+
+    if validate_only {
+        let report = validate(input_path, &config)?;
+        serde_json::to_writer_pretty(std::io::stdout(), &report)?;
+        println!();
+        return Ok(match report.severity() {
+            None => 0,
+            Some(Severity::Warning) => EXIT_WARNINGS,
+            Some(Severity::Error) => EXIT_ERRORS,
+        });
+    }
+
+    if simulate_only {
+        let mut engine = match base_path {
+            Some(base_path) => replay(base_path, &config)?,
+            None => Engine::with_config(config.engine.clone()),
+        };
+        simulate(&mut engine, input_path, std::io::stdout(), &config)?;
+        return Ok(0);
     }
 
-    run(&args[1], std::io::stdout())
+    run_with_config(input_path, std::io::stdout(), &config)?;
+    Ok(0)
 }
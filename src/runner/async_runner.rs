@@ -2,105 +2,282 @@ use std::error::Error;
 use std::io::Write;
 use std::path::Path;
 
+use super::compression::open_input_async;
+use super::processor::Processor;
+use super::rejects::{ReadError, Rejects, RowError};
+use super::source::{
+    decode_binary_record, decode_csv_record, decode_ndjson_line, CsvLayout, Entry, RawInput,
+};
+use super::sync_runner::account_rows;
+use super::{InputFormat, ParseMode, RunConfig, RunError};
 use crate::{
-    csv_utils::write_csv,
-    dto::{AccountRow, Transaction},
+    binary_utils::{check_header, header_read_error, BinaryError, HEADER_SIZE, RECORD_SIZE},
+    csv_utils::{write_csv, CsvDialect},
     Engine,
 };
 
-use csv_async::{AsyncReaderBuilder, Error as CsvError, Trim};
-use tokio::fs::File;
+use csv_async::{AsyncReaderBuilder, StringRecord, Trim};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt};
 use tokio::sync::mpsc;
-use tokio_stream::StreamExt;
+use tokio::task::JoinError;
 
 const BUFFER_SIZE: usize = 1024;
 
 type Result<T, E = Box<dyn Error + Send + Sync>> = std::result::Result<T, E>;
 
+/// Buffered, decompressed input file.
+type Input = Box<dyn AsyncBufRead + Unpin + Send>;
+
 /// Runs the payment engine async on the given input file and writes results to the provided writer.
+/// The input format is detected from the file extension (see [`InputFormat::from_path`]).
 ///
 /// Spawns two tasks:
-/// * CSV reader - streams transactions from the input file, deserializes them and sends them to the processor via channel.
+/// * Reader - streams transactions from the input file, deserializes them and sends them to the processor via channel.
 /// * Processor - receives transactions from the channel and processes them until the channel is closed.
 ///
 /// # Arguments
-/// * `input_path` - Path to the input CSV file containing transactions
+/// * `input_path` - Path to the input file containing transactions
 /// * `writer` - Where to write the account balances (e.g. stdout)
 ///
 /// # Errors
-/// Returns an error if:
+/// Returns a [`RunError`] if:
 /// * The input file cannot be read
-/// * The CSV is malformed
+/// * The input is malformed
 /// * Writing to the output fails
-pub async fn run<P, W>(input_path: P, writer: W) -> Result<()>
+pub async fn run<P, W>(input_path: P, writer: W) -> Result<(), RunError>
 where
     P: AsRef<Path>,
     W: Write,
 {
+    run_with_config(input_path, writer, &RunConfig::default()).await
+}
+
+/// Runs the payment engine async on the given input file with the provided [`RunConfig`],
+/// and writes results to the provided writer.
+///
+/// # Errors
+/// See [`run`].
+pub async fn run_with_config<P, W>(
+    input_path: P,
+    writer: W,
+    config: &RunConfig,
+) -> Result<(), RunError>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let input_path = input_path.as_ref();
+    config.check(input_path)?;
+    let engine = process_input(input_path, config)
+        .await
+        .map_err(|err| RunError::input(input_path, err))?;
+
+    // Write account balances to the provided writer
+    write_csv(writer, account_rows(&engine).into_iter()).map_err(|err| RunError::Output(err.into()))
+}
+
+/// Spawns the reader and processor tasks, returning the final engine state.
+async fn process_input(input_path: &Path, config: &RunConfig) -> Result<Engine> {
     // Create channel for passing transactions from reader to processor
     let (tx, rx) = mpsc::channel(BUFFER_SIZE);
-    let input_path = input_path.as_ref().to_owned();
+    let format = config.input_format(input_path);
+    let lenient = matches!(config.parse_mode, ParseMode::Lenient { .. });
+    let rejects = Rejects::new(config)?;
+    let processor = Processor::new(config)?;
+    let input = open_input_async(input_path, config.compression).await?;
 
-    let reader_handle = tokio::spawn(read_transactions(input_path, tx));
-    let processor_handle = tokio::spawn(process_transactions(rx));
+    let dialect = config.csv.clone();
+    let markers = config.snapshot_markers();
+    let reader_handle = tokio::spawn(read_transactions(
+        input, format, dialect, lenient, markers, rejects, tx,
+    ));
+    let processor_handle = tokio::spawn(process_transactions(processor, rx));
 
     // Wait for reader to finish and propagate any errors
-    reader_handle.await??;
+    task_output(reader_handle.await)?;
 
     // Get final engine state
-    let engine = processor_handle.await?;
+    task_output(processor_handle.await)
+}
 
-    // Sort accounts by client ID for deterministic output
-    let mut accounts: Vec<_> = engine.accounts().map(AccountRow::from).collect();
-    accounts.sort_by_key(|row| row.client);
+/// Returns the output of a finished task, resuming its panic if it panicked.
+/// The tasks are never cancelled, so a join error always carries a panic.
+fn task_output<T>(result: Result<T, JoinError>) -> T {
+    result.unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+}
 
-    // Write account balances to the provided writer
-    write_csv(writer, accounts.into_iter())?;
+/// Reads and deserializes transactions from the input in the given format.
+/// Returns them through the provided channel, while malformed records are handed
+/// over to [`Rejects`].
+async fn read_transactions(
+    input: Input,
+    format: InputFormat,
+    dialect: CsvDialect,
+    lenient: bool,
+    markers: bool,
+    mut rejects: Rejects,
+    tx: mpsc::Sender<(u64, Entry)>,
+) -> Result<()> {
+    match format {
+        InputFormat::Csv => {
+            read_csv_transactions(input, &dialect, lenient, markers, &mut rejects, tx).await?
+        }
+        InputFormat::Ndjson => read_ndjson_transactions(input, markers, &mut rejects, tx).await?,
+        InputFormat::Binary => read_binary_transactions(input, &mut rejects, tx).await?,
+    }
+    rejects.finish()?;
     Ok(())
 }
 
-/// Reads and deserializes transactions from a CSV file.
-/// Returns them through the provided channel.
-async fn read_transactions(
-    input_path: impl AsRef<Path> + Send,
-    tx: mpsc::Sender<Transaction>,
-) -> Result<(), CsvError> {
-    let file = File::open(input_path).await?;
+/// Reads and deserializes transactions from a CSV file in the given dialect.
+async fn read_csv_transactions(
+    input: Input,
+    dialect: &CsvDialect,
+    lenient: bool,
+    markers: bool,
+    rejects: &mut Rejects,
+    tx: mpsc::Sender<(u64, Entry)>,
+) -> Result<()> {
+    // The raw contents of malformed records are only kept if they are logged
+    let raw = rejects.logs_records().then(RawInput::default);
+    let input: Box<dyn AsyncRead + Unpin + Send> = match &raw {
+        Some(raw) => Box::new(raw.tee(input)),
+        None => Box::new(input),
+    };
     let mut csv_reader = AsyncReaderBuilder::new()
-        .has_headers(true)
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .has_headers(dialect.columns.is_none())
         .trim(Trim::All)
-        .create_deserializer(file);
+        .flexible(lenient)
+        .create_reader(input);
+    let header_row = match dialect.columns {
+        Some(_) => None,
+        None => Some(csv_reader.headers().await?.clone()),
+    };
+    let layout = CsvLayout::new(dialect, header_row.as_ref(), markers);
+    let headers = StringRecord::from(layout.headers.clone());
 
-    let mut records = csv_reader.deserialize::<Transaction>();
-    while let Some(result) = records.next().await {
-        match result {
-            Ok(transaction) => {
-                if tx.send(transaction).await.is_err() {
+    let mut record = StringRecord::new();
+    loop {
+        let (start, transaction) = match csv_reader.read_record(&mut record).await {
+            Ok(true) => (
+                record.position().map_or(0, |pos| pos.byte()),
+                decode_csv_record(&record, &headers, &layout),
+            ),
+            Ok(false) => break,
+            Err(err) if err.is_io_error() => return Err(err.into()),
+            Err(err) => (
+                err.position().map_or(0, |pos| pos.byte()),
+                Err(ReadError::Row(RowError {
+                    line: err.position().map_or(0, |pos| pos.line()),
+                    raw: String::new(),
+                    source: err.into(),
+                })),
+            ),
+        };
+        let transaction = match &raw {
+            Some(raw) => raw.complete(transaction, start, csv_reader.position().byte()),
+            None => transaction,
+        };
+        match transaction {
+            Ok(record) => {
+                if tx.send(record).await.is_err() {
                     // Receiver dropped, exit gracefully
                     break;
                 }
             }
-            // CSV parsing errors are critical - propagate them
-            Err(e) => return Err(e),
+            Err(err) => rejects.handle(err)?,
         }
     }
     Ok(())
 }
 
-/// Processes transactions received through the channel.
+/// Reads and deserializes transactions from an NDJSON file.
+async fn read_ndjson_transactions(
+    input: Input,
+    markers: bool,
+    rejects: &mut Rejects,
+    tx: mpsc::Sender<(u64, Entry)>,
+) -> Result<()> {
+    let mut lines = input.lines();
+    let mut line_no = 0;
+    while let Some(line) = lines.next_line().await? {
+        line_no += 1;
+        match decode_ndjson_line(&line, line_no, markers) {
+            Some(Ok(record)) => {
+                if tx.send(record).await.is_err() {
+                    // Receiver dropped, exit gracefully
+                    break;
+                }
+            }
+            Some(Err(err)) => rejects.handle(err)?,
+            None => continue,
+        }
+    }
+    Ok(())
+}
+
+/// Reads and decodes transactions from a binary file.
+async fn read_binary_transactions(
+    mut input: Input,
+    rejects: &mut Rejects,
+    tx: mpsc::Sender<(u64, Entry)>,
+) -> Result<()> {
+    let mut header = [0u8; HEADER_SIZE];
+    input
+        .read_exact(&mut header)
+        .await
+        .map_err(header_read_error)?;
+    let scale = check_header(&header)?;
+
+    let mut record = [0u8; RECORD_SIZE];
+    let mut record_no = 0;
+    loop {
+        let mut filled = 0;
+        while filled < RECORD_SIZE {
+            match input.read(&mut record[filled..]).await? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        record_no += 1;
+        if filled < RECORD_SIZE {
+            return Err(BinaryError::TruncatedRecord { record: record_no }.into());
+        }
+        match decode_binary_record(&record, record_no, scale) {
+            Ok(record) => {
+                if tx.send(record).await.is_err() {
+                    // Receiver dropped, exit gracefully
+                    break;
+                }
+            }
+            Err(err) => rejects.handle(err)?,
+        }
+    }
+    Ok(())
+}
+
+/// Processes entries received through the channel with the [`Processor`].
 /// Returns the final engine state once the channel is closed by the reader.
-async fn process_transactions(mut rx: mpsc::Receiver<Transaction>) -> Engine {
-    let mut engine = Engine::new();
-    while let Some(transaction) = rx.recv().await {
-        // Transaction processing errors should be ignored per spec
-        let _ = engine.process_transaction(transaction);
+async fn process_transactions(
+    mut processor: Processor,
+    mut rx: mpsc::Receiver<(u64, Entry)>,
+) -> Result<Engine> {
+    while let Some((line, entry)) = rx.recv().await {
+        processor.process(line, entry)?;
     }
-    engine
+    processor.finish()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{Compression, CsvDialect, HeaderAliases, SnapshotConfig, SnapshotMode};
+    use crate::{EngineConfig, Precision};
 
     #[tokio::test]
     async fn test_example_input() -> Result<()> {
@@ -136,6 +313,19 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_verify() -> Result<()> {
+        let config = RunConfig {
+            verify: true,
+            verify_every: Some(7),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/10_clients.csv", &mut output, &config).await?;
+        assert!(String::from_utf8(output)?.starts_with("client,available,held,total,locked"));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_10000_clients() -> Result<()> {
         let mut output = Vec::new();
@@ -162,12 +352,44 @@ mod tests {
         let mut output = Vec::new();
         let result = run("data/invalid.csv", &mut output).await;
 
-        // The error should be propagated from the CSV reader
-        assert!(result.is_err());
+        // The error should be propagated from the CSV reader, along with its position
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err,
+            RunError::Csv {
+                line: Some(3),
+                byte: Some(38),
+                ..
+            }
+        ));
+        assert!(err
+            .to_string()
+            .starts_with("CSV deserialize error: record 2 (line 3, byte: 38)"));
         // The output should be empty since we encountered an error
         assert!(output.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_missing_input_file() {
+        let mut output = Vec::new();
+        let result = run("data/does_not_exist.csv", &mut output).await;
+        assert!(matches!(
+            result,
+            Err(RunError::Io { path, .. }) if path == Path::new("data/does_not_exist.csv")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_log_overwrites_input() {
+        let config = RunConfig {
+            outcomes_path: Some("data/example_input.csv".into()),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let result = run_with_config("data/example_input.csv", &mut output, &config).await;
+        assert!(matches!(result, Err(RunError::Config(_))));
+    }
+
     #[tokio::test]
     async fn test_dispute_with_amount() -> Result<()> {
         let mut output = Vec::new();
@@ -182,4 +404,379 @@ mod tests {
         assert_eq!(String::from_utf8(output)?, expected);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_example_input_ndjson() -> Result<()> {
+        let mut output = Vec::new();
+        run("data/example_input.ndjson", &mut output).await?;
+
+        let expected = "client,available,held,total,locked
+1,1.5,0,1.5,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_ndjson() {
+        let mut output = Vec::new();
+        let result = run("data/invalid.ndjson", &mut output).await;
+
+        // The error should point at the offending line
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().contains("line 2"),
+            "unexpected error: {}",
+            err
+        );
+        assert!(matches!(err, RunError::Ndjson { line: 2, .. }));
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_explicit_format_overrides_extension() {
+        let mut output = Vec::new();
+        let config = RunConfig {
+            format: Some(InputFormat::Ndjson),
+            ..Default::default()
+        };
+        // A CSV file read as NDJSON fails on its header line
+        let result = run_with_config("data/example_input.csv", &mut output, &config).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "wide-ids"))]
+    async fn test_example_input_binary() -> Result<()> {
+        let mut output = Vec::new();
+        run("data/example_input.bin", &mut output).await?;
+
+        let expected = "client,available,held,total,locked
+1,1.5,0,1.5,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_csv_read_as_binary() {
+        let mut output = Vec::new();
+        let config = RunConfig {
+            format: Some(InputFormat::Binary),
+            ..Default::default()
+        };
+        // A CSV file does not start with the binary header
+        let result = run_with_config("data/example_input.csv", &mut output, &config).await;
+        assert!(result.is_err());
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_malformed_csv_lenient() -> Result<()> {
+        let rejects_path = std::env::temp_dir().join("async_test_malformed_csv_rejects.csv");
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient { max_errors: None },
+            rejects_path: Some(rejects_path.clone()),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/malformed.csv", &mut output, &config).await?;
+
+        let expected = "client,available,held,total,locked
+1,1.0,0,1.0,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+
+        // Every skipped row is logged with its line number
+        let rejects = std::fs::read_to_string(rejects_path)?;
+        let lines: Vec<_> = rejects
+            .lines()
+            .skip(1)
+            .map(|row| row.split(',').next().unwrap())
+            .collect();
+        assert_eq!(lines, ["3", "4", "6"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_malformed_ndjson_lenient() -> Result<()> {
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient { max_errors: None },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/malformed.ndjson", &mut output, &config).await?;
+
+        let expected = "client,available,held,total,locked
+1,1.0,0,1.0,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lenient_max_errors_exceeded() {
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient {
+                max_errors: Some(2),
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let result = run_with_config("data/malformed.csv", &mut output, &config).await;
+
+        assert!(result.is_err());
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_outcomes_journal() -> Result<()> {
+        let outcomes_path = std::env::temp_dir().join("async_test_outcomes.csv");
+        let config = RunConfig {
+            outcomes_path: Some(outcomes_path.clone()),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/example_input.csv", &mut output, &config).await?;
+
+        let expected = "line,type,client,tx,status,reason
+2,deposit,1,1,accepted,
+3,deposit,2,2,accepted,
+4,deposit,1,3,accepted,
+5,withdrawal,1,4,accepted,
+6,withdrawal,2,5,rejected,insufficient_funds
+";
+        assert_eq!(std::fs::read_to_string(outcomes_path)?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_precision() -> Result<()> {
+        let config = RunConfig {
+            engine: EngineConfig {
+                precision: Precision {
+                    scale: 8,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/precision.csv", &mut output, &config).await?;
+
+        let expected = "client,available,held,total,locked
+1,0.73456789,0,0.73456789,false
+2,0.00005,0,0.00005,false
+3,2.5,0,2.5,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_csv_dialect() -> Result<()> {
+        let mut header_aliases = HeaderAliases::default();
+        header_aliases.insert("kind", "type")?;
+        header_aliases.insert("transaction_id", "tx")?;
+        let config = RunConfig {
+            csv: CsvDialect {
+                delimiter: b';',
+                header_aliases,
+                case_insensitive_types: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/dialect.csv", &mut output, &config).await?;
+
+        let expected = "client,available,held,total,locked
+1,1.0,0,1.0,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+
+        // Type names are case-sensitive by default
+        let config = RunConfig {
+            csv: CsvDialect {
+                case_insensitive_types: false,
+                ..config.csv
+            },
+            ..Default::default()
+        };
+        let result = run_with_config("data/dialect.csv", &mut Vec::new(), &config).await;
+        assert!(matches!(result, Err(RunError::Csv { line: Some(2), .. })));
+
+        // Rejected rows are logged as they appear in the input
+        let rejects_path = std::env::temp_dir().join("async_test_csv_dialect_rejects.csv");
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient { max_errors: None },
+            rejects_path: Some(rejects_path.clone()),
+            ..config
+        };
+        run_with_config("data/dialect.csv", &mut Vec::new(), &config).await?;
+        let rejects = std::fs::read_to_string(rejects_path)?;
+        let rows: Vec<_> = rejects.lines().skip(1).collect();
+        assert!(rows[0].starts_with("2,Deposit; 1; 1; 1.5,"));
+        assert!(rows[1].starts_with("3,DEPOSIT; 2; 2; 2,"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_headerless_csv() -> Result<()> {
+        // The file starts with a UTF-8 BOM and has no header row
+        let config = RunConfig {
+            format: Some(InputFormat::Csv),
+            csv: CsvDialect {
+                delimiter: b'\t',
+                columns: Some(
+                    ["client", "tx", "type", "amount"]
+                        .map(String::from)
+                        .to_vec(),
+                ),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/headerless.tsv", &mut output, &config).await?;
+
+        let expected = "client,available,held,total,locked
+1,2,0,2,false
+2,5,0,5,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compressed_input() -> Result<()> {
+        let expected = "client,available,held,total,locked
+1,1.5,0,1.5,false
+2,2,0,2,false
+";
+        // Compression is detected from the magic bytes and the format from the inner extension
+        for path in [
+            "data/example_input.csv.gz",
+            "data/example_input.csv.zst",
+            "data/example_input.ndjson.gz",
+        ] {
+            let mut output = Vec::new();
+            run(path, &mut output).await?;
+            assert_eq!(String::from_utf8(output)?, expected, "{}", path);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compressed_input_without_extension() -> Result<()> {
+        // zstd-compressed content behind a plain `.csv` name
+        let mut compressed = Vec::new();
+        run("data/10_clients_compressed.csv", &mut compressed).await?;
+        let mut plain = Vec::new();
+        run("data/10_clients.csv", &mut plain).await?;
+        assert_eq!(compressed, plain);
+
+        // An explicit compression overrides the detection
+        let config = RunConfig {
+            compression: Some(Compression::None),
+            ..Default::default()
+        };
+        let result =
+            run_with_config("data/10_clients_compressed.csv", &mut Vec::new(), &config).await;
+        assert!(matches!(result, Err(RunError::Csv { .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_on_marker() -> Result<()> {
+        let dir = std::env::temp_dir().join("async_test_snapshots_on_marker");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = RunConfig {
+            snapshots: Some(SnapshotConfig {
+                on_marker: true,
+                dir: dir.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/snapshots.csv", &mut output, &config).await?;
+
+        let first = "client,available,held,total,locked
+1,1,0,1,false
+2,2,0,2,false
+";
+        let second = "client,available,held,total,locked
+1,1.5,0,1.5,false
+2,2,0,2,false
+";
+        let config = config.snapshots.unwrap();
+        assert_eq!(std::fs::read_to_string(config.path(1))?, first);
+        assert_eq!(std::fs::read_to_string(config.path(2))?, second);
+        assert!(!config.path(3).exists());
+        assert_eq!(String::from_utf8(output)?, second);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_every_n_changed() -> Result<()> {
+        let dir = std::env::temp_dir().join("async_test_snapshots_every_n");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = RunConfig {
+            snapshots: Some(SnapshotConfig {
+                every: Some(2),
+                mode: SnapshotMode::Changed,
+                dir: dir.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        run_with_config("data/example_input.csv", &mut Vec::new(), &config).await?;
+
+        // Only client 1 changed between the 2nd and 4th transaction
+        let config = config.snapshots.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(config.path(1))?,
+            "client,available,held,total,locked\n1,1,0,1,false\n2,2,0,2,false\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(config.path(2))?,
+            "client,available,held,total,locked\n1,1.5,0,1.5,false\n"
+        );
+        assert!(!config.path(3).exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_marker_disabled() {
+        // Without markers enabled, a marker row is malformed
+        let result = run("data/snapshots.csv", &mut Vec::new()).await;
+        assert!(matches!(result, Err(RunError::Csv { line: Some(4), .. })));
+    }
+
+    #[tokio::test]
+    async fn test_deltas() -> Result<()> {
+        let deltas_path = std::env::temp_dir().join("async_test_deltas.ndjson");
+        let config = RunConfig {
+            deltas_path: Some(deltas_path.clone()),
+            ..Default::default()
+        };
+        run_with_config("data/example_input.csv", &mut Vec::new(), &config).await?;
+
+        // The rejected withdrawal of tx 5 has no delta
+        let deltas = std::fs::read_to_string(deltas_path)?;
+        let lines: Vec<_> = deltas.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[3],
+            r#"{"client":1,"tx":4,"before":{"available":"3","held":"0","locked":false},"after":{"available":"1.5","held":"0","locked":false}}"#
+        );
+        Ok(())
+    }
 }
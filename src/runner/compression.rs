@@ -0,0 +1,147 @@
+//! Transparent decompression of input files.
+//!
+//! Compressed inputs are detected by their magic bytes, or by their extension, and
+//! decompressed on the fly in front of the format readers, so that no temporary
+//! files are needed.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use flate2::bufread::MultiGzDecoder;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of an input file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the compression from the first bytes of the file, falling back to
+    /// the file extension (`.gz`, `.zst` or `.zstd`).
+    pub fn detect(path: impl AsRef<Path>, first_bytes: &[u8]) -> Self {
+        if first_bytes.starts_with(GZIP_MAGIC) {
+            return Compression::Gzip;
+        }
+        if first_bytes.starts_with(ZSTD_MAGIC) {
+            return Compression::Zstd;
+        }
+        Self::from_extension(path)
+    }
+
+    /// Detects the compression from the file extension only.
+    pub fn from_extension(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("gz") | Some("gzip") => Compression::Gzip,
+            Some("zst") | Some("zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            other => Err(format!("unknown compression: {}", other)),
+        }
+    }
+}
+
+/// Opens the input file, decompressing it on the fly if needed.
+/// If `compression` is `None`, it is detected with [`Compression::detect`].
+pub(super) fn open_input(
+    path: &Path,
+    compression: Option<Compression>,
+) -> io::Result<Box<dyn BufRead + Send>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let compression = match compression {
+        Some(compression) => compression,
+        None => Compression::detect(path, reader.fill_buf()?),
+    };
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+    })
+}
+
+/// Async counterpart of [`open_input`].
+pub(super) async fn open_input_async(
+    path: &Path,
+    compression: Option<Compression>,
+) -> io::Result<Box<dyn AsyncBufRead + Unpin + Send>> {
+    let mut reader = tokio::io::BufReader::new(tokio::fs::File::open(path).await?);
+    let compression = match compression {
+        Some(compression) => compression,
+        None => Compression::detect(path, reader.fill_buf().await?),
+    };
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::new(tokio::io::BufReader::new(decoder))
+        }
+        Compression::Zstd => Box::new(tokio::io::BufReader::new(ZstdDecoder::new(reader))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Compression::detect("in.csv", &[0x1f, 0x8b, 0x08]),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::detect("in.csv", &[0x28, 0xb5, 0x2f, 0xfd]),
+            Compression::Zstd
+        );
+        // Without magic bytes, the extension decides
+        assert_eq!(Compression::detect("in.gz", b"type"), Compression::Gzip);
+        assert_eq!(Compression::detect("in.csv", b"type"), Compression::None);
+        assert_eq!(Compression::detect("in.csv.ZST", b""), Compression::Zstd);
+    }
+
+    #[test]
+    fn test_compression_from_str() {
+        assert_eq!("gzip".parse(), Ok(Compression::Gzip));
+        assert_eq!("ZSTD".parse(), Ok(Compression::Zstd));
+        assert_eq!("none".parse(), Ok(Compression::None));
+        assert!("bzip2".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn test_open_compressed_input() -> io::Result<()> {
+        let expected = std::fs::read_to_string("data/example_input.csv")?;
+        for path in ["data/example_input.csv.gz", "data/example_input.csv.zst"] {
+            let mut contents = String::new();
+            open_input(Path::new(path), None)?.read_to_string(&mut contents)?;
+            assert_eq!(contents, expected, "{}", path);
+        }
+        Ok(())
+    }
+}
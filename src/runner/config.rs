@@ -0,0 +1,242 @@
+//! Configuration shared by the sync and async runners.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::{Compression, RunError, SnapshotConfig};
+use crate::{CsvDialect, EngineConfig, EngineConfigError};
+
+/// Input file format understood by the runners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    /// Comma-separated values with a `type,client,tx,amount` header row.
+    #[default]
+    Csv,
+    /// Newline-delimited JSON, one transaction object per line.
+    Ndjson,
+    /// Fixed-width binary records (see [`BinaryReader`](crate::BinaryReader)).
+    Binary,
+}
+
+impl InputFormat {
+    /// Detects the input format from the file extension.
+    /// `.ndjson` and `.jsonl` files are read as NDJSON, `.bin` files as binary,
+    /// and anything else as CSV. A compression extension is skipped, so that
+    /// e.g. `.ndjson.gz` files are read as NDJSON.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let mut path = path.as_ref();
+        if Compression::from_extension(path) != Compression::None {
+            path = Path::new(path.file_stem().unwrap_or_default());
+        }
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("ndjson") | Some("jsonl") => InputFormat::Ndjson,
+            Some("bin") => InputFormat::Binary,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(InputFormat::Csv),
+            "ndjson" | "jsonl" => Ok(InputFormat::Ndjson),
+            "binary" | "bin" => Ok(InputFormat::Binary),
+            other => Err(format!("unknown input format: {}", other)),
+        }
+    }
+}
+
+/// How the runner treats input records that cannot be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Abort the run on the first malformed record.
+    #[default]
+    Strict,
+    /// Skip malformed records and keep going.
+    /// The run is still aborted once more than `max_errors` records were skipped.
+    Lenient { max_errors: Option<u64> },
+}
+
+/// Options controlling how a runner reads its input and sets up the engine.
+#[derive(Debug, Clone, Default)]
+pub struct RunConfig {
+    /// Input format. If `None`, the format is detected from the file extension.
+    pub format: Option<InputFormat>,
+    /// Compression of the input file. If `None`, it is detected from the magic bytes
+    /// or the file extension.
+    pub compression: Option<Compression>,
+    /// Layout of CSV input files.
+    pub csv: CsvDialect,
+    /// Whether malformed records abort the run or are skipped.
+    pub parse_mode: ParseMode,
+    /// Where to write records skipped in lenient mode, as CSV with
+    /// `line,raw,error` columns. Ignored in strict mode.
+    pub rejects_path: Option<PathBuf>,
+    /// Where to journal the outcome of every transaction, as CSV with
+    /// `line,type,client,tx,status,reason` columns.
+    pub outcomes_path: Option<PathBuf>,
+    /// Where to write the change feed of account deltas, as NDJSON
+    /// (see [`AccountDelta`](crate::AccountDelta)).
+    pub deltas_path: Option<PathBuf>,
+    /// Where to write the disputes still open at the end of the run, oldest first, as CSV
+    /// with `client,tx,amount,age_transactions,age_seconds` columns
+    /// (see [`OpenDispute`](crate::OpenDispute)).
+    pub open_disputes_path: Option<PathBuf>,
+    /// Where and when to write snapshots of the account state during the run.
+    pub snapshots: Option<SnapshotConfig>,
+    /// Whether to check the engine invariants (see [`Engine::verify`](crate::Engine::verify))
+    /// at the end of the run.
+    pub verify: bool,
+    /// Also check the engine invariants after every `verify_every` transactions.
+    /// Implies `verify`.
+    pub verify_every: Option<u64>,
+    /// Configuration of the engine processing the transactions.
+    pub engine: EngineConfig,
+}
+
+impl RunConfig {
+    /// Resolves the input format for the given path.
+    pub fn input_format(&self, path: impl AsRef<Path>) -> InputFormat {
+        self.format.unwrap_or_else(|| InputFormat::from_path(path))
+    }
+
+    /// Whether `snapshot` marker rows are decoded rather than reported as malformed.
+    pub(super) fn snapshot_markers(&self) -> bool {
+        self.snapshots
+            .as_ref()
+            .is_some_and(|snapshots| snapshots.on_marker)
+    }
+
+    /// Checks that the log files do not overwrite the input or each other,
+    /// and that the engine, snapshot and verify settings are valid.
+    pub fn check(&self, input_path: impl AsRef<Path>) -> Result<(), RunError> {
+        let input_path = input_path.as_ref();
+        if let Err(EngineConfigError::Invalid(message)) = self.engine.validate() {
+            return Err(RunError::Config(message));
+        }
+        if let Some(snapshots) = &self.snapshots {
+            snapshots.check()?;
+        }
+        if self.verify_every == Some(0) {
+            return Err(RunError::Config(
+                "verify interval must be at least 1".to_string(),
+            ));
+        }
+        let logs = [
+            ("rejects", &self.rejects_path),
+            ("outcomes", &self.outcomes_path),
+            ("deltas", &self.deltas_path),
+            ("open disputes", &self.open_disputes_path),
+        ];
+        for (i, (name, path)) in logs.iter().enumerate() {
+            let Some(path) = path else {
+                continue;
+            };
+            if path == input_path {
+                return Err(RunError::Config(format!(
+                    "{} path {} is the input file",
+                    name,
+                    input_path.display()
+                )));
+            }
+            if let Some((other, _)) = logs[..i]
+                .iter()
+                .find(|(_, other)| other.as_ref() == Some(path))
+            {
+                return Err(RunError::Config(format!(
+                    "{} and {} paths are both {}",
+                    other,
+                    name,
+                    path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            InputFormat::from_path("data/example_input.csv"),
+            InputFormat::Csv
+        );
+        assert_eq!(
+            InputFormat::from_path("data/example_input.ndjson"),
+            InputFormat::Ndjson
+        );
+        assert_eq!(InputFormat::from_path("in.JSONL"), InputFormat::Ndjson);
+        assert_eq!(InputFormat::from_path("in.bin"), InputFormat::Binary);
+        assert_eq!(InputFormat::from_path("no_extension"), InputFormat::Csv);
+        assert_eq!(InputFormat::from_path("in.ndjson.gz"), InputFormat::Ndjson);
+        assert_eq!(InputFormat::from_path("in.bin.zst"), InputFormat::Binary);
+        assert_eq!(InputFormat::from_path("in.csv.gz"), InputFormat::Csv);
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("csv".parse(), Ok(InputFormat::Csv));
+        assert_eq!("NDJSON".parse(), Ok(InputFormat::Ndjson));
+        assert_eq!("jsonl".parse(), Ok(InputFormat::Ndjson));
+        assert_eq!("binary".parse(), Ok(InputFormat::Binary));
+        assert!("xml".parse::<InputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_explicit_format_overrides_extension() {
+        let config = RunConfig {
+            format: Some(InputFormat::Ndjson),
+            ..Default::default()
+        };
+        assert_eq!(config.input_format("transactions.csv"), InputFormat::Ndjson);
+        assert_eq!(
+            RunConfig::default().input_format("transactions.csv"),
+            InputFormat::Csv
+        );
+    }
+
+    #[test]
+    fn test_check_log_paths() {
+        let config = RunConfig {
+            rejects_path: Some("rejects.csv".into()),
+            outcomes_path: Some("outcomes.csv".into()),
+            ..Default::default()
+        };
+        assert!(config.check("in.csv").is_ok());
+        assert!(matches!(
+            config.check("outcomes.csv"),
+            Err(RunError::Config(_))
+        ));
+
+        let config = RunConfig {
+            rejects_path: Some("log.csv".into()),
+            outcomes_path: Some("log.csv".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.check("in.csv").unwrap_err().to_string(),
+            "Invalid configuration: rejects and outcomes paths are both log.csv"
+        );
+
+        let config = RunConfig {
+            outcomes_path: Some("log.csv".into()),
+            deltas_path: Some("log.csv".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.check("in.csv").unwrap_err().to_string(),
+            "Invalid configuration: outcomes and deltas paths are both log.csv"
+        );
+    }
+}
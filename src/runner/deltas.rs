@@ -0,0 +1,59 @@
+//! Change feed of account deltas, written as NDJSON.
+//!
+//! When a deltas path is configured, the runner subscribes to the engine's change feed
+//! and writes one [`AccountDelta`] object per line, in processing order.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::mpsc;
+
+use super::rejects::BoxError;
+use super::{RunConfig, RunError};
+use crate::{AccountDelta, Engine};
+
+/// Writes the engine's change feed to the deltas NDJSON file, if configured.
+pub(super) struct Deltas {
+    feed: Option<(mpsc::Receiver<AccountDelta>, BufWriter<File>)>,
+}
+
+impl Deltas {
+    /// Creates the deltas file and subscribes to the engine's change feed,
+    /// if a deltas path is configured.
+    pub fn new(config: &RunConfig, engine: &mut Engine) -> Result<Self, BoxError> {
+        let Some(path) = &config.deltas_path else {
+            return Ok(Self { feed: None });
+        };
+        let file = File::create(path).map_err(|source| RunError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        Ok(Self {
+            feed: Some((engine.change_channel(), BufWriter::new(file))),
+        })
+    }
+
+    /// Writes the deltas emitted since the last call.
+    pub fn write(&mut self) -> Result<(), BoxError> {
+        if let Some((receiver, writer)) = &mut self.feed {
+            for delta in receiver.try_iter() {
+                write_delta(writer, &delta).map_err(RunError::Output)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining deltas and flushes the deltas file.
+    pub fn finish(&mut self) -> Result<(), BoxError> {
+        self.write()?;
+        if let Some((_, writer)) = &mut self.feed {
+            writer.flush().map_err(|err| RunError::Output(err.into()))?;
+        }
+        Ok(())
+    }
+}
+
+fn write_delta(writer: &mut impl Write, delta: &AccountDelta) -> Result<(), BoxError> {
+    serde_json::to_writer(&mut *writer, delta)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
@@ -0,0 +1,20 @@
+//! Report of the disputes still open at the end of a run.
+
+use std::fs::File;
+use std::path::Path;
+
+use super::rejects::BoxError;
+use super::RunError;
+use crate::csv_utils::write_csv;
+use crate::Engine;
+
+/// Writes the disputes still open, oldest first, as CSV with
+/// `client,tx,amount,age_transactions,age_seconds` columns.
+pub(super) fn write_open_disputes(path: &Path, engine: &Engine) -> Result<(), BoxError> {
+    let file = File::create(path).map_err(|source| RunError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    write_csv(file, engine.open_disputes()).map_err(|err| RunError::Output(err.into()))?;
+    Ok(())
+}
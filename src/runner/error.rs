@@ -0,0 +1,304 @@
+//! Error type returned by the runners.
+//!
+//! Internally, the sources and runners pass errors around as boxed trait objects.
+//! They are classified into a [`RunError`] once, at the runner boundary, so that
+//! library callers can tell the failure cases apart and locate the offending record.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::rejects::{BoxError, RowError};
+use super::TooManyRejects;
+use crate::{BinaryError, NdjsonError, VerifyError};
+
+/// Error returned by the runners.
+///
+/// The [`Display`](fmt::Display) output is the message of the underlying error,
+/// while the variants carry the file and position of the failure.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RunError {
+    /// A file could not be opened or read.
+    Io { path: PathBuf, source: io::Error },
+    /// The CSV input is malformed.
+    Csv {
+        path: PathBuf,
+        line: Option<u64>,
+        byte: Option<u64>,
+        source: BoxError,
+    },
+    /// The NDJSON input is malformed.
+    Ndjson {
+        path: PathBuf,
+        line: u64,
+        source: serde_json::Error,
+    },
+    /// The binary input is malformed.
+    Binary { path: PathBuf, source: BinaryError },
+    /// A record does not match the expected schema, e.g. it has the wrong number of fields.
+    Row {
+        path: PathBuf,
+        line: u64,
+        source: BoxError,
+    },
+    /// Lenient mode skipped more records than allowed.
+    TooManyRejects(TooManyRejects),
+    /// The engine state broke an invariant, checked after the given line,
+    /// or at the end of the run if `line` is `None`.
+    Verify {
+        line: Option<u64>,
+        source: VerifyError,
+    },
+    /// Writing the account balances, rejects or outcomes failed.
+    Output(BoxError),
+    /// The [`RunConfig`](super::RunConfig) is invalid.
+    Config(String),
+    /// Processing the input failed for any other reason.
+    Input(BoxError),
+}
+
+impl RunError {
+    /// Returns the line of the offending record (record number for binary input), if known.
+    pub fn line(&self) -> Option<u64> {
+        match self {
+            RunError::Csv { line, .. } => *line,
+            RunError::Ndjson { line, .. } => Some(*line),
+            RunError::Row { line, .. } => Some(*line),
+            RunError::Verify { line, .. } => *line,
+            RunError::Binary {
+                source:
+                    BinaryError::InvalidRecord { record, .. } | BinaryError::TruncatedRecord { record },
+                ..
+            } => Some(*record),
+            _ => None,
+        }
+    }
+
+    /// Classifies an error raised while reading the given input file.
+    pub(super) fn input(path: &Path, err: BoxError) -> Self {
+        let path = path.to_owned();
+        let err = match err.downcast::<RunError>() {
+            Ok(err) => return *err,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<RowError>() {
+            Ok(row) => return Self::row(path, *row),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<csv::Error>() {
+            Ok(err) => return Self::csv(path, *err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<csv_async::Error>() {
+            Ok(err) => return Self::csv_async(path, *err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<NdjsonError>() {
+            Ok(err) => {
+                return match *err {
+                    NdjsonError::Io(source) => RunError::Io { path, source },
+                    NdjsonError::Deserialize { line, source } => {
+                        RunError::Ndjson { path, line, source }
+                    }
+                }
+            }
+            Err(err) => err,
+        };
+        let err = match err.downcast::<BinaryError>() {
+            Ok(err) => {
+                return match *err {
+                    BinaryError::Io(source) => RunError::Io { path, source },
+                    source => RunError::Binary { path, source },
+                }
+            }
+            Err(err) => err,
+        };
+        let err = match err.downcast::<TooManyRejects>() {
+            Ok(err) => return RunError::TooManyRejects(*err),
+            Err(err) => err,
+        };
+        match err.downcast::<io::Error>() {
+            Ok(source) => RunError::Io {
+                path,
+                source: *source,
+            },
+            Err(err) => RunError::Input(err),
+        }
+    }
+
+    /// Classifies a malformed record. Errors of the input format keep their own variant.
+    fn row(path: PathBuf, row: RowError) -> Self {
+        let source = &row.source;
+        if source.is::<csv::Error>()
+            || source.is::<csv_async::Error>()
+            || source.is::<NdjsonError>()
+            || source.is::<BinaryError>()
+        {
+            return Self::input(&path, row.source);
+        }
+        RunError::Row {
+            path,
+            line: row.line,
+            source: row.source,
+        }
+    }
+
+    fn csv(path: PathBuf, err: csv::Error) -> Self {
+        let position = err.position().map(|pos| (pos.line(), pos.byte()));
+        if err.is_io_error() {
+            if let csv::ErrorKind::Io(source) = err.into_kind() {
+                return RunError::Io { path, source };
+            }
+            unreachable!("is_io_error() implies an Io error kind");
+        }
+        RunError::Csv {
+            path,
+            line: position.map(|(line, _)| line),
+            byte: position.map(|(_, byte)| byte),
+            source: err.into(),
+        }
+    }
+
+    fn csv_async(path: PathBuf, err: csv_async::Error) -> Self {
+        let position = err.position().map(|pos| (pos.line(), pos.byte()));
+        if err.is_io_error() {
+            if let csv_async::ErrorKind::Io(source) = err.into_kind() {
+                return RunError::Io { path, source };
+            }
+            unreachable!("is_io_error() implies an Io error kind");
+        }
+        RunError::Csv {
+            path,
+            line: position.map(|(line, _)| line),
+            byte: position.map(|(_, byte)| byte),
+            source: err.into(),
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Io { source, .. } => source.fmt(f),
+            RunError::Csv { source, .. } => source.fmt(f),
+            RunError::Ndjson { line, source, .. } => {
+                write!(f, "NDJSON deserialize error: line {}: {}", line, source)
+            }
+            RunError::Binary { source, .. } => source.fmt(f),
+            RunError::Row { line, source, .. } => write!(f, "line {}: {}", line, source),
+            RunError::TooManyRejects(err) => err.fmt(f),
+            RunError::Verify {
+                line: Some(line),
+                source,
+            } => write!(f, "Verification failed after line {}: {}", line, source),
+            RunError::Verify { line: None, source } => {
+                write!(f, "Verification failed at the end of the run: {}", source)
+            }
+            RunError::Output(source) => source.fmt(f),
+            RunError::Config(message) => write!(f, "Invalid configuration: {}", message),
+            RunError::Input(source) => source.fmt(f),
+        }
+    }
+}
+
+impl Error for RunError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RunError::Io { source, .. } => Some(source),
+            RunError::Csv { source, .. } => Some(source.as_ref()),
+            RunError::Ndjson { source, .. } => Some(source),
+            RunError::Binary { source, .. } => Some(source),
+            RunError::Row { source, .. } => Some(source.as_ref()),
+            RunError::TooManyRejects(err) => Some(err),
+            RunError::Verify { source, .. } => Some(source),
+            RunError::Output(source) => Some(source.as_ref()),
+            RunError::Config(_) => None,
+            RunError::Input(source) => Some(source.as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classifies_csv_errors() {
+        let mut reader =
+            csv::Reader::from_reader("type,client,tx,amount\ndeposit,1,1,abc\n".as_bytes());
+        let err = reader
+            .deserialize::<crate::Transaction>()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        let message = err.to_string();
+
+        let err = RunError::input(Path::new("in.csv"), err.into());
+        assert!(matches!(
+            &err,
+            RunError::Csv { path, line: Some(2), byte: Some(22), .. } if path == Path::new("in.csv")
+        ));
+        assert_eq!(err.line(), Some(2));
+        // The message is the one of the underlying CSV error
+        assert_eq!(err.to_string(), message);
+    }
+
+    #[test]
+    fn test_classifies_row_errors() {
+        let row = RowError {
+            line: 3,
+            raw: "deposit,1".to_string(),
+            source: "found record with 2 fields, but the header has 4 fields".into(),
+        };
+        let err = RunError::input(Path::new("in.csv"), row.into());
+        assert!(matches!(err, RunError::Row { line: 3, .. }));
+        assert_eq!(err.line(), Some(3));
+        assert_eq!(
+            err.to_string(),
+            "line 3: found record with 2 fields, but the header has 4 fields"
+        );
+    }
+
+    #[test]
+    fn test_classifies_io_errors() {
+        let source = io::Error::new(io::ErrorKind::NotFound, "not found");
+        let err = RunError::input(Path::new("in.csv"), csv::Error::from(source).into());
+        assert!(matches!(err, RunError::Io { .. }));
+        assert_eq!(err.line(), None);
+    }
+
+    #[test]
+    fn test_classifies_other_errors() {
+        let err = RunError::input(Path::new("in.csv"), "unexpected failure".into());
+        assert!(matches!(err, RunError::Input(_)));
+        assert_eq!(err.line(), None);
+        assert_eq!(err.to_string(), "unexpected failure");
+    }
+
+    #[test]
+    fn test_verify_error_line() {
+        let source = VerifyError {
+            violations: vec![crate::Violation::LockedWithoutChargeback { client: 1 }],
+        };
+        let err = RunError::Verify {
+            line: Some(7),
+            source,
+        };
+        assert_eq!(err.line(), Some(7));
+        assert_eq!(
+            err.to_string(),
+            "Verification failed after line 7: 1 invariant violation(s); client 1 is locked without a chargeback"
+        );
+    }
+
+    #[test]
+    fn test_passes_through_run_errors() {
+        let err = RunError::input(
+            Path::new("in.csv"),
+            RunError::Config("bad".to_string()).into(),
+        );
+        assert_eq!(err.to_string(), "Invalid configuration: bad");
+    }
+}
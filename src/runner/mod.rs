@@ -1,10 +1,32 @@
-//! The runner is responsible for setting up a file stream for reading from CSV,
-//! processing the transactions, and writing the output to a writer.
+//! The runner is responsible for setting up a file stream for reading transactions
+//! (CSV, NDJSON or binary, optionally gzip or zstd compressed), processing them,
+//! and writing the output to a writer, optionally with periodic snapshots along the way.
 //!
-//! This module provides both a synchronous and an asynchronous runner implementations.
+//! This module provides both a synchronous and an asynchronous runner implementations,
+//! as well as a validator that checks an input file without applying it, and a what-if
+//! evaluation reporting the would-be outcome of every transaction in a file.
 //!
 mod async_runner;
+mod compression;
+mod config;
+mod deltas;
+mod disputes;
+mod error;
+mod outcomes;
+mod processor;
+mod rejects;
+mod simulate;
+mod snapshots;
+mod source;
 mod sync_runner;
+mod validate;
 
-pub use async_runner::run as run_async;
-pub use sync_runner::run;
+pub use async_runner::{run as run_async, run_with_config as run_async_with_config};
+pub use compression::Compression;
+pub use config::{InputFormat, ParseMode, RunConfig};
+pub use error::RunError;
+pub use rejects::TooManyRejects;
+pub use simulate::simulate;
+pub use snapshots::{SnapshotConfig, SnapshotMode, SNAPSHOT_MARKER};
+pub use sync_runner::{replay, run, run_with_config};
+pub use validate::{validate, Issue, IssueKind, Severity, ValidationReport};
@@ -0,0 +1,68 @@
+//! Per-transaction outcome journal.
+//!
+//! When an outcomes path is configured, every decoded transaction is logged with its
+//! line, tx id, and whether the engine accepted or rejected it, together with the
+//! reason code of the rejection. Disputes closed by the engine because they were too old
+//! are logged as synthetic resolves or chargebacks with the `aged` status, on the line
+//! of the transaction after which they were closed. Malformed records never reach the
+//! engine and are logged to the rejects CSV instead.
+
+use std::fs::File;
+
+use super::rejects::{open_log, BoxError};
+use super::{RunConfig, RunError};
+use crate::dto::{OutcomeRow, OutcomeStatus, Transaction};
+use crate::Engine;
+
+/// Applies transactions to the engine, journaling their outcomes if configured.
+pub(super) struct Outcomes {
+    writer: Option<csv::Writer<File>>,
+}
+
+impl Outcomes {
+    /// Creates the journal, opening the outcomes CSV if one is configured.
+    pub fn new(config: &RunConfig) -> Result<Self, BoxError> {
+        let writer = match &config.outcomes_path {
+            Some(path) => Some(open_log(path)?),
+            None => None,
+        };
+        Ok(Self { writer })
+    }
+
+    /// Processes a single transaction read from the given line.
+    /// Processing errors are not propagated, only failures to write the journal are.
+    pub fn apply(
+        &mut self,
+        engine: &mut Engine,
+        line: u64,
+        transaction: Transaction,
+    ) -> Result<(), BoxError> {
+        let Some(writer) = &mut self.writer else {
+            // Transaction processing errors should be ignored per spec
+            let _ = engine.process_transaction(transaction);
+            return Ok(());
+        };
+        let result = engine.process_transaction(transaction.clone());
+        writer
+            .serialize(OutcomeRow::new(line, &transaction, &result))
+            .map_err(|err| RunError::Output(err.into()))?;
+        for aged in engine.aged() {
+            let row = OutcomeRow {
+                status: OutcomeStatus::Aged,
+                ..OutcomeRow::new(line, aged, &Ok(()))
+            };
+            writer
+                .serialize(row)
+                .map_err(|err| RunError::Output(err.into()))?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the outcomes CSV.
+    pub fn finish(&mut self) -> Result<(), BoxError> {
+        if let Some(writer) = &mut self.writer {
+            writer.flush().map_err(|err| RunError::Output(err.into()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//! Processing side of the runners.
+//!
+//! The [`Processor`] applies decoded entries to the engine, and feeds the configured
+//! outcome journal, snapshots and deltas along the way, checking the engine invariants
+//! if requested. At the end of the run, it reports the disputes still open if requested.
+//! It is shared by the sync runner and the processor task of the async runner.
+
+use std::path::PathBuf;
+
+use super::deltas::Deltas;
+use super::disputes::write_open_disputes;
+use super::outcomes::Outcomes;
+use super::rejects::BoxError;
+use super::snapshots::Snapshots;
+use super::source::Entry;
+use super::{RunConfig, RunError};
+use crate::Engine;
+
+/// Applies entries to the engine, according to the [`RunConfig`].
+pub(super) struct Processor {
+    engine: Engine,
+    outcomes: Outcomes,
+    snapshots: Snapshots,
+    deltas: Deltas,
+    open_disputes_path: Option<PathBuf>,
+    verify: bool,
+    verify_every: Option<u64>,
+    /// Transactions processed since the start of the run
+    count: u64,
+}
+
+impl Processor {
+    /// Creates a new engine, opening the configured output files.
+    pub fn new(config: &RunConfig) -> Result<Self, BoxError> {
+        let mut engine = Engine::with_config(config.engine.clone());
+        let outcomes = Outcomes::new(config)?;
+        let snapshots = Snapshots::new(config)?;
+        let deltas = Deltas::new(config, &mut engine)?;
+        Ok(Self {
+            engine,
+            outcomes,
+            snapshots,
+            deltas,
+            open_disputes_path: config.open_disputes_path.clone(),
+            verify: config.verify || config.verify_every.is_some(),
+            verify_every: config.verify_every,
+            count: 0,
+        })
+    }
+
+    /// Processes a single entry read from the given line.
+    pub fn process(&mut self, line: u64, entry: Entry) -> Result<(), BoxError> {
+        match entry {
+            Entry::Transaction(transaction) => {
+                self.outcomes.apply(&mut self.engine, line, transaction)?;
+                self.deltas.write()?;
+                self.snapshots.transaction(&self.engine)?;
+                self.count += 1;
+                match self.verify_every {
+                    Some(every) if self.count.is_multiple_of(every) => self.verify(Some(line)),
+                    _ => Ok(()),
+                }
+            }
+            Entry::Marker => self.snapshots.marker(&self.engine),
+        }
+    }
+
+    /// Flushes the output files and checks the final engine state if requested,
+    /// returning it.
+    pub fn finish(mut self) -> Result<Engine, BoxError> {
+        self.outcomes.finish()?;
+        self.deltas.finish()?;
+        if let Some(path) = &self.open_disputes_path {
+            write_open_disputes(path, &self.engine)?;
+        }
+        if self.verify {
+            self.verify(None)?;
+        }
+        Ok(self.engine)
+    }
+
+    /// Checks the engine invariants, after the given line or at the end of the run.
+    fn verify(&self, line: Option<u64>) -> Result<(), BoxError> {
+        self.engine
+            .verify()
+            .map_err(|source| RunError::Verify { line, source }.into())
+    }
+}
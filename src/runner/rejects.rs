@@ -0,0 +1,207 @@
+//! Handling of malformed input records.
+//!
+//! In strict mode the first malformed record aborts the run. In lenient mode malformed
+//! records are skipped, optionally logged to a rejects CSV, and the run is only aborted
+//! once the configured maximum error count is exceeded.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+use super::{ParseMode, RunConfig, RunError};
+use crate::dto::RejectRow;
+
+pub(super) type BoxError = Box<dyn Error + Send + Sync>;
+
+/// An input record that could not be decoded into a transaction.
+#[derive(Debug)]
+pub(super) struct RowError {
+    /// Line number of the record (record number for binary input)
+    pub line: u64,
+    /// Raw contents of the record, as read from the input
+    pub raw: String,
+    pub source: BoxError,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl Error for RowError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Error produced while reading transactions from the input.
+#[derive(Debug)]
+pub(super) enum ReadError {
+    /// A single record is malformed; reading can continue with the next one.
+    Row(RowError),
+    /// The input cannot be read any further.
+    Fatal(BoxError),
+}
+
+impl<E: Into<BoxError>> From<E> for ReadError {
+    fn from(err: E) -> Self {
+        ReadError::Fatal(err.into())
+    }
+}
+
+/// Returned when lenient mode skips more records than allowed.
+#[derive(Debug)]
+pub struct TooManyRejects {
+    pub max_errors: u64,
+}
+
+impl fmt::Display for TooManyRejects {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Too many malformed records: limit of {} exceeded",
+            self.max_errors
+        )
+    }
+}
+
+impl Error for TooManyRejects {}
+
+/// Decides what happens to malformed records, according to the [`ParseMode`].
+pub(super) struct Rejects {
+    mode: ParseMode,
+    writer: Option<csv::Writer<File>>,
+    count: u64,
+}
+
+impl Rejects {
+    /// Creates the handler, opening the rejects CSV if one is configured in lenient mode.
+    pub fn new(config: &RunConfig) -> Result<Self, BoxError> {
+        let writer = match (&config.parse_mode, &config.rejects_path) {
+            (ParseMode::Lenient { .. }, Some(path)) => Some(open_log(path)?),
+            _ => None,
+        };
+        Ok(Self {
+            mode: config.parse_mode,
+            writer,
+            count: 0,
+        })
+    }
+
+    /// Handles a read error. Fatal errors are always propagated, while malformed
+    /// records are only propagated in strict mode.
+    pub fn handle(&mut self, err: ReadError) -> Result<(), BoxError> {
+        let row = match err {
+            ReadError::Row(row) => row,
+            ReadError::Fatal(err) => return Err(err),
+        };
+        let max_errors = match self.mode {
+            ParseMode::Strict => return Err(row.into()),
+            ParseMode::Lenient { max_errors } => max_errors,
+        };
+
+        self.count += 1;
+        if let Some(writer) = &mut self.writer {
+            writer
+                .serialize(RejectRow {
+                    line: row.line,
+                    raw: &row.raw,
+                    error: row.source.to_string(),
+                })
+                .map_err(|err| RunError::Output(err.into()))?;
+        }
+        match max_errors {
+            Some(max_errors) if self.count > max_errors => {
+                self.finish()?;
+                Err(TooManyRejects { max_errors }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether malformed records are logged to the rejects CSV, which needs their raw
+    /// contents.
+    pub fn logs_records(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Flushes the rejects CSV, returning the number of skipped records.
+    pub fn finish(&mut self) -> Result<u64, BoxError> {
+        if let Some(writer) = &mut self.writer {
+            writer.flush().map_err(|err| RunError::Output(err.into()))?;
+        }
+        Ok(self.count)
+    }
+}
+
+/// Creates a CSV log file, such as the rejects or outcomes CSV.
+pub(super) fn open_log(path: &Path) -> Result<csv::Writer<File>, RunError> {
+    let file = File::create(path).map_err(|source| RunError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    Ok(csv::Writer::from_writer(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_error(line: u64) -> ReadError {
+        ReadError::Row(RowError {
+            line,
+            raw: "deposit,1,1,abc".to_string(),
+            source: "invalid amount".into(),
+        })
+    }
+
+    #[test]
+    fn test_strict_mode_propagates_row_errors() {
+        let mut rejects = Rejects::new(&RunConfig::default()).unwrap();
+        let err = rejects.handle(row_error(2)).unwrap_err();
+        assert_eq!(err.to_string(), "invalid amount");
+    }
+
+    #[test]
+    fn test_lenient_mode_skips_row_errors() {
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient { max_errors: None },
+            ..Default::default()
+        };
+        let mut rejects = Rejects::new(&config).unwrap();
+        for line in 2..100 {
+            rejects.handle(row_error(line)).unwrap();
+        }
+        assert_eq!(rejects.finish().unwrap(), 98);
+    }
+
+    #[test]
+    fn test_lenient_mode_propagates_fatal_errors() {
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient { max_errors: None },
+            ..Default::default()
+        };
+        let mut rejects = Rejects::new(&config).unwrap();
+        assert!(rejects
+            .handle(ReadError::Fatal("disk on fire".into()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_lenient_mode_max_errors() {
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient {
+                max_errors: Some(2),
+            },
+            ..Default::default()
+        };
+        let mut rejects = Rejects::new(&config).unwrap();
+        rejects.handle(row_error(2)).unwrap();
+        rejects.handle(row_error(3)).unwrap();
+
+        let err = rejects.handle(row_error(4)).unwrap_err();
+        assert!(err.is::<TooManyRejects>());
+    }
+}
@@ -0,0 +1,134 @@
+//! What-if evaluation of input files.
+//!
+//! Every transaction of the file is evaluated against the state of an engine with
+//! [`Engine::simulate_batch`], and its would-be outcome reported, leaving the engine
+//! unchanged. Malformed records are handled according to the [`ParseMode`], as in a
+//! regular run.
+
+use std::io::Write;
+use std::path::Path;
+
+use super::compression::open_input;
+use super::rejects::{BoxError, ReadError, Rejects};
+use super::source::{binary_source, csv_source, ndjson_source, Entry};
+use super::{InputFormat, ParseMode, RunConfig, RunError};
+use crate::dto::{SimulationRow, Transaction};
+use crate::{csv_utils::write_csv, Engine};
+
+/// Evaluates every transaction of the given input file against the state of the engine,
+/// and writes a report with the would-be outcome of each one to the provided writer.
+///
+/// The report is a CSV with `line,type,client,tx,status,reason` columns as in the
+/// outcome journal, followed by the `available,held,total,locked` balances the client's
+/// account would have after the transaction, left empty for rejected ones.
+/// Transactions see the effects of the accepted ones before them in the file.
+///
+/// # Errors
+/// Returns a [`RunError`] if:
+/// * The input file cannot be read
+/// * The input is malformed
+/// * Writing the report fails
+pub fn simulate<P, W>(
+    engine: &mut Engine,
+    input_path: P,
+    writer: W,
+    config: &RunConfig,
+) -> Result<(), RunError>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let input_path = input_path.as_ref();
+    let transactions =
+        read_transactions(input_path, config).map_err(|err| RunError::input(input_path, err))?;
+
+    let results = engine.simulate_batch(
+        transactions
+            .iter()
+            .map(|(_, transaction)| transaction.clone()),
+    );
+    let rows = transactions
+        .iter()
+        .zip(&results)
+        .map(|((line, transaction), result)| SimulationRow::new(*line, transaction, result));
+    write_csv(writer, rows).map_err(|err| RunError::Output(err.into()))
+}
+
+/// Reads every transaction from the input file, together with its line.
+fn read_transactions(
+    input_path: &Path,
+    config: &RunConfig,
+) -> Result<Vec<(u64, Transaction)>, BoxError> {
+    let mut rejects = Rejects::new(config)?;
+    let raw_records = rejects.logs_records();
+    let mut transactions = Vec::new();
+    let mut collect = |entry: Result<(u64, Entry), ReadError>| -> Result<(), BoxError> {
+        match entry {
+            Ok((line, Entry::Transaction(transaction))) => transactions.push((line, transaction)),
+            Ok((_, Entry::Marker)) => {}
+            Err(err) => rejects.handle(err)?,
+        }
+        Ok(())
+    };
+
+    let lenient = matches!(config.parse_mode, ParseMode::Lenient { .. });
+    let markers = config.snapshot_markers();
+    let input = open_input(input_path, config.compression)?;
+    match config.input_format(input_path) {
+        InputFormat::Csv => csv_source(input, &config.csv, lenient, markers, raw_records)?
+            .try_for_each(&mut collect)?,
+        InputFormat::Ndjson => ndjson_source(input, markers)?.try_for_each(&mut collect)?,
+        InputFormat::Binary => binary_source(input)?.try_for_each(&mut collect)?,
+    }
+    rejects.finish()?;
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay;
+    use std::error::Error;
+
+    #[test]
+    fn test_simulate_against_replayed_state() -> Result<(), Box<dyn Error>> {
+        let config = RunConfig::default();
+        let mut engine = replay("data/example_input.csv", &config)?;
+
+        let mut output = Vec::new();
+        simulate(&mut engine, "data/example_input.csv", &mut output, &config)?;
+
+        // Replaying the same file again only hits duplicates, except for the withdrawal
+        // that was rejected the first time
+        let expected = "line,type,client,tx,status,reason,available,held,total,locked
+2,deposit,1,1,rejected,duplicate_transaction,,,,
+3,deposit,2,2,rejected,duplicate_transaction,,,,
+4,deposit,1,3,rejected,duplicate_transaction,,,,
+5,withdrawal,1,4,rejected,duplicate_transaction,,,,
+6,withdrawal,2,5,rejected,insufficient_funds,,,,
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate_from_empty_state() -> Result<(), Box<dyn Error>> {
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient { max_errors: None },
+            ..Default::default()
+        };
+        let mut engine = Engine::new();
+
+        let mut output = Vec::new();
+        simulate(&mut engine, "data/malformed.csv", &mut output, &config)?;
+        let expected = "line,type,client,tx,status,reason,available,held,total,locked
+2,deposit,1,1,accepted,,1.5,0,1.5,false
+5,deposit,2,4,accepted,,2,0,2,false
+7,withdrawal,1,6,accepted,,1.0,0,1.0,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        // The engine itself is unchanged
+        assert_eq!(engine.accounts().count(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+//! Periodic snapshots of the account state during a run.
+//!
+//! Snapshots are written every N transactions, and/or whenever a `snapshot` marker row
+//! appears in the input, to numbered CSV files in the configured directory
+//! (`snapshot-000001.csv`, `snapshot-000002.csv`, ...). Each file has the same layout
+//! as the final output, and contains either every account or only the accounts that
+//! changed since the previous snapshot.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use super::rejects::BoxError;
+use super::sync_runner::account_rows;
+use super::{RunConfig, RunError};
+use crate::dto::{AccountRow, ClientId};
+use crate::{csv_utils::write_csv, Engine};
+
+/// Type name of the marker rows that trigger a snapshot.
+pub const SNAPSHOT_MARKER: &str = "snapshot";
+
+/// Which accounts a snapshot contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnapshotMode {
+    /// Every account.
+    #[default]
+    Full,
+    /// Only the accounts that changed since the previous snapshot.
+    Changed,
+}
+
+impl FromStr for SnapshotMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "full" => Ok(SnapshotMode::Full),
+            "changed" => Ok(SnapshotMode::Changed),
+            other => Err(format!("unknown snapshot mode: {}", other)),
+        }
+    }
+}
+
+/// When and where to write snapshots of the account state during a run.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SnapshotConfig {
+    /// Directory the numbered snapshot CSVs are written to, created if missing.
+    /// Defaults to the working directory.
+    pub dir: PathBuf,
+    /// Write a snapshot after every `every` transactions.
+    pub every: Option<u64>,
+    /// Write a snapshot whenever a [`SNAPSHOT_MARKER`] row appears in CSV or NDJSON input.
+    pub on_marker: bool,
+    pub mode: SnapshotMode,
+}
+
+impl SnapshotConfig {
+    /// Returns the path of the snapshot with the given sequence number.
+    pub fn path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("snapshot-{:06}.csv", seq))
+    }
+
+    /// Checks that at least one trigger is set, and that the interval is not zero.
+    pub(super) fn check(&self) -> Result<(), RunError> {
+        match (self.every, self.on_marker) {
+            (Some(0), _) => Err(RunError::Config(
+                "snapshot interval must be at least 1".to_string(),
+            )),
+            (None, false) => Err(RunError::Config(
+                "snapshots need an interval or marker rows".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Balances, lock, review flag and receivable of an account row.
+type RowState = (Decimal, Decimal, bool, Option<bool>, Option<Decimal>);
+
+/// Writes snapshots of the engine state, according to the [`SnapshotConfig`].
+pub(super) struct Snapshots {
+    config: Option<SnapshotConfig>,
+    /// Transactions processed since the start of the run
+    count: u64,
+    /// Sequence number of the last snapshot
+    seq: u64,
+    /// Balances as of the last snapshot, used to find changed accounts
+    last: HashMap<ClientId, RowState>,
+}
+
+impl Snapshots {
+    /// Creates the writer, creating the snapshot directory if snapshots are configured.
+    pub fn new(config: &RunConfig) -> Result<Self, BoxError> {
+        if let Some(snapshots) = &config.snapshots {
+            fs::create_dir_all(&snapshots.dir).map_err(|source| RunError::Io {
+                path: snapshots.dir.clone(),
+                source,
+            })?;
+        }
+        Ok(Self {
+            config: config.snapshots.clone(),
+            count: 0,
+            seq: 0,
+            last: HashMap::new(),
+        })
+    }
+
+    /// Counts a processed transaction, writing a snapshot once the interval is reached.
+    pub fn transaction(&mut self, engine: &Engine) -> Result<(), BoxError> {
+        self.count += 1;
+        match self.config.as_ref().and_then(|config| config.every) {
+            Some(every) if self.count.is_multiple_of(every) => self.write(engine),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes a snapshot for a marker row.
+    pub fn marker(&mut self, engine: &Engine) -> Result<(), BoxError> {
+        self.write(engine)
+    }
+
+    fn write(&mut self, engine: &Engine) -> Result<(), BoxError> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+        let mut rows = account_rows(engine);
+        if config.mode == SnapshotMode::Changed {
+            let last = &mut self.last;
+            rows.retain(|row| {
+                let balances = (
+                    row.available,
+                    row.held,
+                    row.locked,
+                    row.flagged,
+                    row.receivable,
+                );
+                last.insert(row.client, balances) != Some(balances)
+            });
+        }
+
+        self.seq += 1;
+        let path = config.path(self.seq);
+        write_snapshot(&path, rows)
+    }
+}
+
+fn write_snapshot(path: &Path, rows: Vec<AccountRow>) -> Result<(), BoxError> {
+    let file = File::create(path).map_err(|source| RunError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    write_csv(file, rows.into_iter()).map_err(|err| RunError::Output(err.into()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut config = SnapshotConfig::default();
+        assert!(config.check().is_err());
+        config.on_marker = true;
+        assert!(config.check().is_ok());
+        config.every = Some(0);
+        assert!(config.check().is_err());
+        config.every = Some(10);
+        assert!(config.check().is_ok());
+    }
+
+    #[test]
+    fn test_path() {
+        let config = SnapshotConfig {
+            dir: "snapshots".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.path(12),
+            Path::new("snapshots").join("snapshot-000012.csv")
+        );
+    }
+}
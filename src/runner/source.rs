@@ -0,0 +1,409 @@
+//! Transaction sources for the runners.
+//!
+//! Every source yields decoded entries along with their line number (record
+//! number for binary input), reporting malformed records as
+//! [`ReadError::Row`] along with their line number and raw contents, so that the
+//! caller can decide whether to abort or skip them.
+
+use std::io::{self, BufRead, Read};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use super::rejects::{BoxError, ReadError, RowError};
+use super::SNAPSHOT_MARKER;
+use crate::{
+    binary_utils::{decode_record, BinaryError, BinaryReader, RECORD_SIZE},
+    csv_utils::CsvDialect,
+    dto::Transaction,
+    json_utils::{parse_ndjson_line, NdjsonError},
+};
+
+/// Entry read from the input.
+#[derive(Debug)]
+pub(super) enum Entry {
+    Transaction(Transaction),
+    /// A [`SNAPSHOT_MARKER`] row, only decoded if markers are enabled.
+    Marker,
+}
+
+/// Creates an iterator over the entries of a CSV file in the given dialect.
+///
+/// In lenient mode, records with the wrong number of fields are reported as malformed
+/// records rather than CSV errors, so that their raw contents can be logged.
+/// The raw contents are the bytes of the record as they appear in the input, and are
+/// only kept if `raw_records` is set.
+pub(super) fn csv_source(
+    input: impl Read + 'static,
+    dialect: &CsvDialect,
+    lenient: bool,
+    markers: bool,
+    raw_records: bool,
+) -> Result<impl Iterator<Item = Result<(u64, Entry), ReadError>>, BoxError> {
+    let raw = raw_records.then(RawInput::default);
+    let input: Box<dyn Read> = match &raw {
+        Some(raw) => Box::new(raw.tee(input)),
+        None => Box::new(input),
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .has_headers(dialect.columns.is_none())
+        .trim(csv::Trim::All)
+        .flexible(lenient)
+        .from_reader(input);
+    let header_row = match dialect.columns {
+        Some(_) => None,
+        None => Some(reader.headers()?.clone()),
+    };
+    let layout = CsvLayout::new(dialect, header_row.as_ref(), markers);
+    let headers = csv::StringRecord::from(layout.headers.clone());
+
+    let mut record = csv::StringRecord::new();
+    Ok(std::iter::from_fn(move || {
+        let (start, result) = match reader.read_record(&mut record) {
+            Ok(true) => (
+                record.position().map_or(0, |pos| pos.byte()),
+                decode_csv_record(&record, &headers, &layout),
+            ),
+            Ok(false) => return None,
+            Err(err) => (
+                err.position().map_or(0, |pos| pos.byte()),
+                Err(csv_read_error(err)),
+            ),
+        };
+        Some(match &raw {
+            Some(raw) => raw.complete(result, start, reader.position().byte()),
+            None => result,
+        })
+    }))
+}
+
+/// Bytes read from a CSV input that may belong to records not decoded yet, kept so that
+/// malformed records can be reported with their raw contents.
+#[derive(Clone, Default)]
+pub(super) struct RawInput(Arc<Mutex<RawBytes>>);
+
+#[derive(Default)]
+struct RawBytes {
+    bytes: Vec<u8>,
+    /// Byte offset of the first kept byte in the input
+    offset: u64,
+}
+
+impl RawInput {
+    /// Wraps the input to keep the bytes read from it.
+    pub fn tee<R>(&self, input: R) -> Tee<R> {
+        Tee {
+            input,
+            raw: self.clone(),
+        }
+    }
+
+    fn push(&self, bytes: &[u8]) {
+        self.0.lock().unwrap().bytes.extend_from_slice(bytes);
+    }
+
+    /// Fills in the raw contents of a malformed record spanning the given byte offsets,
+    /// then discards the bytes up to the end of the record.
+    pub fn complete<T>(
+        &self,
+        mut result: Result<T, ReadError>,
+        start: u64,
+        end: u64,
+    ) -> Result<T, ReadError> {
+        let raw = &mut *self.0.lock().unwrap();
+        if let Err(ReadError::Row(row)) = &mut result {
+            let from = start.saturating_sub(raw.offset) as usize;
+            let to = (end.saturating_sub(raw.offset) as usize).min(raw.bytes.len());
+            if let Some(bytes) = raw.bytes.get(from..to) {
+                let record = String::from_utf8_lossy(bytes);
+                row.raw = record.trim_end_matches(['\r', '\n']).to_string();
+            }
+        }
+        let consumed = (end.saturating_sub(raw.offset) as usize).min(raw.bytes.len());
+        raw.bytes.drain(..consumed);
+        raw.offset = raw.offset.max(end);
+        result
+    }
+}
+
+/// Input that keeps the bytes read from it in a [`RawInput`].
+pub(super) struct Tee<R> {
+    input: R,
+    raw: RawInput,
+}
+
+impl<R: Read> Read for Tee<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.input.read(buf)?;
+        self.raw.push(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Tee<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut this.input).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            this.raw.push(&buf.filled()[filled..]);
+        }
+        poll
+    }
+}
+
+/// Record type of the sync and async CSV readers.
+pub(super) trait CsvRecord: FromIterator<String> {
+    /// Line number of the record
+    fn line(&self) -> u64;
+    fn field_count(&self) -> usize;
+    fn decode<T: DeserializeOwned>(&self, headers: &Self) -> Result<T, BoxError>;
+}
+
+impl CsvRecord for csv::StringRecord {
+    fn line(&self) -> u64 {
+        self.position().map_or(0, |pos| pos.line())
+    }
+
+    fn field_count(&self) -> usize {
+        self.len()
+    }
+
+    fn decode<T: DeserializeOwned>(&self, headers: &Self) -> Result<T, BoxError> {
+        Ok(self.deserialize(Some(headers))?)
+    }
+}
+
+impl CsvRecord for csv_async::StringRecord {
+    fn line(&self) -> u64 {
+        self.position().map_or(0, |pos| pos.line())
+    }
+
+    fn field_count(&self) -> usize {
+        self.len()
+    }
+
+    fn decode<T: DeserializeOwned>(&self, headers: &Self) -> Result<T, BoxError> {
+        Ok(self.deserialize(Some(headers))?)
+    }
+}
+
+/// Header names of a CSV file resolved to transaction fields, according to a [`CsvDialect`].
+pub(super) struct CsvLayout {
+    /// Transaction field held by each column
+    pub headers: Vec<String>,
+    /// Index of the `type` column, if type names are matched case-insensitively
+    lowercase_column: Option<usize>,
+    /// Index of the `type` column, if marker rows are decoded
+    marker_column: Option<usize>,
+}
+
+impl CsvLayout {
+    /// Resolves the header row of the file, or the configured column order of
+    /// headerless files.
+    pub fn new<'a>(
+        dialect: &CsvDialect,
+        header_row: Option<impl IntoIterator<Item = &'a str>>,
+        markers: bool,
+    ) -> Self {
+        let headers: Vec<String> = match (header_row, &dialect.columns) {
+            (Some(header_row), _) => header_row
+                .into_iter()
+                .map(|name| dialect.resolve_column(name))
+                .collect(),
+            (None, Some(columns)) => columns
+                .iter()
+                .map(|name| dialect.resolve_column(name))
+                .collect(),
+            (None, None) => Vec::new(),
+        };
+        let type_column = headers.iter().position(|name| name == "type");
+        Self {
+            headers,
+            lowercase_column: type_column.filter(|_| dialect.case_insensitive_types),
+            marker_column: type_column.filter(|_| markers),
+        }
+    }
+
+    /// Whether the record is a [`SNAPSHOT_MARKER`] row.
+    pub fn is_marker<'a, R>(&self, record: &'a R) -> bool
+    where
+        &'a R: IntoIterator<Item = &'a str>,
+    {
+        self.marker_column
+            .and_then(|column| record.into_iter().nth(column))
+            .is_some_and(|tx_type| tx_type.eq_ignore_ascii_case(SNAPSHOT_MARKER))
+    }
+
+    /// Returns a copy of the record with its type name lowercased, or `None` if the
+    /// record can be deserialized as is.
+    pub fn normalize<'a, R>(&self, record: &'a R) -> Option<R>
+    where
+        &'a R: IntoIterator<Item = &'a str>,
+        R: FromIterator<String>,
+    {
+        let column = self.lowercase_column?;
+        let tx_type = record.into_iter().nth(column)?;
+        if !tx_type.bytes().any(|byte| byte.is_ascii_uppercase()) {
+            return None;
+        }
+        Some(
+            record
+                .into_iter()
+                .enumerate()
+                .map(|(i, field)| match i == column {
+                    true => field.to_ascii_lowercase(),
+                    false => field.to_string(),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Deserializes a CSV record into a transaction, or a marker.
+/// The raw contents of malformed records are left for [`RawInput::complete`] to fill in.
+pub(super) fn decode_csv_record<R>(
+    record: &R,
+    headers: &R,
+    layout: &CsvLayout,
+) -> Result<(u64, Entry), ReadError>
+where
+    R: CsvRecord,
+    for<'a> &'a R: IntoIterator<Item = &'a str>,
+{
+    let line = record.line();
+    let row_error = |source| {
+        ReadError::Row(RowError {
+            line,
+            raw: String::new(),
+            source,
+        })
+    };
+    if record.field_count() != headers.field_count() {
+        return Err(row_error(
+            format!(
+                "found record with {} fields, but the header has {} fields",
+                record.field_count(),
+                headers.field_count()
+            )
+            .into(),
+        ));
+    }
+    layout
+        .normalize(record)
+        .as_ref()
+        .unwrap_or(record)
+        .decode(headers)
+        .map(|transaction| (line, Entry::Transaction(transaction)))
+        .or_else(|err| match layout.is_marker(record) {
+            true => Ok((line, Entry::Marker)),
+            false => Err(row_error(err)),
+        })
+}
+
+/// Classifies a CSV reader error. Only I/O errors prevent reading further records.
+fn csv_read_error(err: csv::Error) -> ReadError {
+    if err.is_io_error() {
+        return ReadError::Fatal(err.into());
+    }
+    ReadError::Row(RowError {
+        line: err.position().map_or(0, |pos| pos.line()),
+        raw: String::new(),
+        source: err.into(),
+    })
+}
+
+/// Creates an iterator over the entries of an NDJSON file.
+pub(super) fn ndjson_source(
+    input: impl BufRead,
+    markers: bool,
+) -> Result<impl Iterator<Item = Result<(u64, Entry), ReadError>>, BoxError> {
+    let lines = input.lines();
+    Ok(lines
+        .zip(1..)
+        .filter_map(move |(line, line_no)| match line {
+            Ok(line) => decode_ndjson_line(&line, line_no, markers),
+            Err(err) => Some(Err(err.into())),
+        }))
+}
+
+/// Deserializes an NDJSON line into a transaction, or a marker if markers are enabled.
+/// Returns `None` for blank lines.
+pub(super) fn decode_ndjson_line(
+    line: &str,
+    line_no: u64,
+    markers: bool,
+) -> Option<Result<(u64, Entry), ReadError>> {
+    let result = parse_ndjson_line(line, line_no)?;
+    Some(
+        result
+            .map(|transaction| (line_no, Entry::Transaction(transaction)))
+            .or_else(|err| match markers && is_ndjson_marker(line) {
+                true => Ok((line_no, Entry::Marker)),
+                false => Err(err),
+            })
+            .map_err(|err| match err {
+                NdjsonError::Deserialize { line: line_no, .. } => ReadError::Row(RowError {
+                    line: line_no,
+                    raw: line.to_string(),
+                    source: err.into(),
+                }),
+                NdjsonError::Io(_) => err.into(),
+            }),
+    )
+}
+
+/// Whether the NDJSON line is a [`SNAPSHOT_MARKER`] object.
+fn is_ndjson_marker(line: &str) -> bool {
+    #[derive(Deserialize)]
+    struct Marker<'a> {
+        #[serde(rename = "type")]
+        tx_type: &'a str,
+    }
+    serde_json::from_str::<Marker>(line.trim_start_matches('\u{feff}'))
+        .is_ok_and(|marker| marker.tx_type.eq_ignore_ascii_case(SNAPSHOT_MARKER))
+}
+
+/// Creates an iterator over the transactions of a binary file.
+/// The binary format has no marker records.
+pub(super) fn binary_source(
+    input: impl Read,
+) -> Result<impl Iterator<Item = Result<(u64, Entry), ReadError>>, BoxError> {
+    let mut reader = BinaryReader::new(input)?;
+    Ok(std::iter::from_fn(move || match reader.read_record() {
+        Ok(Some((record_no, record))) => {
+            Some(decode_binary_record(&record, record_no, reader.scale()))
+        }
+        Ok(None) => None,
+        Err(err) => Some(Err(err.into())),
+    }))
+}
+
+/// Decodes a binary record into a transaction, with amounts of the given scale.
+/// The raw contents of malformed records are reported as hex.
+pub(super) fn decode_binary_record(
+    record: &[u8; RECORD_SIZE],
+    record_no: u64,
+    scale: u32,
+) -> Result<(u64, Entry), ReadError> {
+    decode_record(record, record_no, scale)
+        .map(|transaction| (record_no, Entry::Transaction(transaction)))
+        .map_err(|err| match err {
+            BinaryError::InvalidRecord { .. } => ReadError::Row(RowError {
+                line: record_no,
+                raw: record.iter().map(|byte| format!("{:02x}", byte)).collect(),
+                source: err.into(),
+            }),
+            _ => err.into(),
+        })
+}
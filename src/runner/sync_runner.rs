@@ -1,51 +1,138 @@
-use std::error::Error;
 use std::io::Write;
 use std::path::Path;
 
+use super::compression::open_input;
+use super::processor::Processor;
+use super::rejects::{BoxError, ReadError, Rejects};
+use super::source::{binary_source, csv_source, ndjson_source, Entry};
+use super::{InputFormat, ParseMode, RunConfig, RunError};
 use crate::{
-    csv_utils::{read_csv_into_iter, write_csv},
-    dto::{AccountRow, Transaction},
-    Engine,
+    csv_utils::write_csv, dto::AccountRow, ChargebackPolicy, Engine, NegativeBalancePolicy,
 };
 
 /// Runs the payment engine on the given input file and writes results to the provided writer.
+/// The input format is detected from the file extension (see [`InputFormat::from_path`]).
 ///
 /// # Arguments
-/// * `input_path` - Path to the input CSV file containing transactions
+/// * `input_path` - Path to the input file containing transactions
 /// * `writer` - Where to write the account balances (e.g. stdout)
 ///
 /// # Errors
-/// Returns an error if:
+/// Returns a [`RunError`] if:
 /// * The input file cannot be read
-/// * The CSV is malformed
+/// * The input is malformed
 /// * Writing to the output fails
-pub fn run<P, W>(input_path: P, writer: W) -> Result<(), Box<dyn Error>>
+pub fn run<P, W>(input_path: P, writer: W) -> Result<(), RunError>
 where
     P: AsRef<Path>,
     W: Write,
 {
-    let mut engine = Engine::new();
-
-    let transactions_iter = read_csv_into_iter::<Transaction, _>(input_path)?;
-    for transaction in transactions_iter {
-        // CSV parsing errors are critical - propagate them
-        let transaction = transaction?;
-        // Transaction processing errors should be ignored per spec
-        let _ = engine.process_transaction(transaction);
-    }
+    run_with_config(input_path, writer, &RunConfig::default())
+}
 
-    // Sort accounts by client ID for deterministic output
-    let mut accounts: Vec<_> = engine.accounts().map(AccountRow::from).collect();
-    accounts.sort_by_key(|row| row.client);
+/// Runs the payment engine on the given input file with the provided [`RunConfig`],
+/// and writes results to the provided writer.
+///
+/// # Errors
+/// See [`run`].
+pub fn run_with_config<P, W>(input_path: P, writer: W, config: &RunConfig) -> Result<(), RunError>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let engine = replay(input_path, config)?;
 
     // Write account balances to the provided writer
-    write_csv(writer, accounts.into_iter())?;
+    write_csv(writer, account_rows(&engine).into_iter()).map_err(|err| RunError::Output(err.into()))
+}
+
+/// Output rows of the engine's accounts, sorted by client ID for deterministic output.
+/// Amounts are rounded to the engine's precision. Flags and receivables are only included
+/// if chargebacks and disputes can leave one.
+pub(super) fn account_rows(engine: &Engine) -> Vec<AccountRow> {
+    let config = engine.config();
+    let with_flagged = config.chargeback_policy != ChargebackPolicy::Lock;
+    let with_receivable = config.negative_balance_policy == NegativeBalancePolicy::HoldAvailable;
+    let mut rows: Vec<_> = engine
+        .accounts()
+        .map(|account| {
+            AccountRow {
+                flagged: with_flagged.then_some(account.flagged),
+                receivable: with_receivable.then_some(account.receivable),
+                ..AccountRow::from(account)
+            }
+            .rounded(&config.precision)
+        })
+        .collect();
+    rows.sort_by_key(|row| row.client);
+    rows
+}
+
+/// Replays the given input file into a new engine with the provided [`RunConfig`],
+/// returning the final engine state instead of writing the account balances.
+///
+/// # Errors
+/// See [`run`].
+pub fn replay<P>(input_path: P, config: &RunConfig) -> Result<Engine, RunError>
+where
+    P: AsRef<Path>,
+{
+    let input_path = input_path.as_ref();
+    config.check(input_path)?;
+    process_input(input_path, config).map_err(|err| RunError::input(input_path, err))
+}
+
+/// Reads every transaction from the input file and feeds it into a new engine.
+fn process_input(input_path: &Path, config: &RunConfig) -> Result<Engine, BoxError> {
+    let mut processor = Processor::new(config)?;
+    let mut rejects = Rejects::new(config)?;
+
+    let lenient = matches!(config.parse_mode, ParseMode::Lenient { .. });
+    let markers = config.snapshot_markers();
+    let input = open_input(input_path, config.compression)?;
+    match config.input_format(input_path) {
+        InputFormat::Csv => {
+            let source = csv_source(input, &config.csv, lenient, markers, rejects.logs_records())?;
+            process_all(&mut processor, source, &mut rejects)?
+        }
+        InputFormat::Ndjson => {
+            let source = ndjson_source(input, markers)?;
+            process_all(&mut processor, source, &mut rejects)?
+        }
+        InputFormat::Binary => {
+            let source = binary_source(input)?;
+            process_all(&mut processor, source, &mut rejects)?
+        }
+    }
+    rejects.finish()?;
+    processor.finish()
+}
+
+/// Feeds every entry from the source into the processor.
+/// Malformed records are handed over to [`Rejects`], which decides whether to abort.
+fn process_all<I>(
+    processor: &mut Processor,
+    entries: I,
+    rejects: &mut Rejects,
+) -> Result<(), BoxError>
+where
+    I: Iterator<Item = Result<(u64, Entry), ReadError>>,
+{
+    for entry in entries {
+        match entry {
+            Ok((line, entry)) => processor.process(line, entry)?,
+            Err(err) => rejects.handle(err)?,
+        }
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{AgingAction, AgingThreshold, DisputeAging, EngineConfig, Precision, Rounding};
+    use crate::{Compression, CsvDialect, HeaderAliases, SnapshotConfig, SnapshotMode};
+    use std::error::Error;
 
     #[test]
     fn test_example_input() -> Result<(), Box<dyn Error>> {
@@ -81,6 +168,73 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_verify() -> Result<(), Box<dyn Error>> {
+        let config = RunConfig {
+            verify_every: Some(7),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/10_clients.csv", &mut output, &config)?;
+        assert!(String::from_utf8(output)?.starts_with("client,available,held,total,locked"));
+
+        let config = RunConfig {
+            verify_every: Some(0),
+            ..Default::default()
+        };
+        let result = run_with_config("data/10_clients.csv", &mut Vec::new(), &config);
+        assert!(matches!(result, Err(RunError::Config(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_negative_balance_policy_hold_available() -> Result<(), Box<dyn Error>> {
+        let mut output = Vec::new();
+        run("data/negative_balance.csv", &mut output)?;
+        let expected = "client,available,held,total,locked
+1,-75,0,-75,true
+2,0,10,10,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+
+        let config = RunConfig {
+            engine: EngineConfig {
+                negative_balance_policy: NegativeBalancePolicy::HoldAvailable,
+                ..Default::default()
+            },
+            verify: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/negative_balance.csv", &mut output, &config)?;
+        let expected = "client,available,held,total,locked,receivable
+1,0,0,0,true,75
+2,0,10,10,false,0
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chargeback_policy_flag() -> Result<(), Box<dyn Error>> {
+        let config = RunConfig {
+            engine: EngineConfig {
+                chargeback_policy: ChargebackPolicy::Flag,
+                ..Default::default()
+            },
+            verify: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/negative_balance.csv", &mut output, &config)?;
+        let expected = "client,available,held,total,locked,flagged
+1,-75,0,-75,false,true
+2,0,10,10,false,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
     #[test]
     fn test_10000_clients() -> Result<(), Box<dyn Error>> {
         let mut output = Vec::new();
@@ -107,12 +261,44 @@ mod tests {
         let mut output = Vec::new();
         let result = run("data/invalid.csv", &mut output);
 
-        // The error should be propagated from the CSV reader
-        assert!(result.is_err());
+        // The error should be propagated from the CSV reader, along with its position
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err,
+            RunError::Csv {
+                line: Some(3),
+                byte: Some(38),
+                ..
+            }
+        ));
+        assert!(err
+            .to_string()
+            .starts_with("CSV deserialize error: record 2 (line: 3, byte: 38)"));
         // The output should be empty since we encountered an error
         assert!(output.is_empty());
     }
 
+    #[test]
+    fn test_missing_input_file() {
+        let mut output = Vec::new();
+        let result = run("data/does_not_exist.csv", &mut output);
+        assert!(matches!(
+            result,
+            Err(RunError::Io { path, .. }) if path == Path::new("data/does_not_exist.csv")
+        ));
+    }
+
+    #[test]
+    fn test_log_overwrites_input() {
+        let config = RunConfig {
+            outcomes_path: Some("data/example_input.csv".into()),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let result = run_with_config("data/example_input.csv", &mut output, &config);
+        assert!(matches!(result, Err(RunError::Config(_))));
+    }
+
     #[test]
     fn test_dispute_with_amount() -> Result<(), Box<dyn Error>> {
         let mut output = Vec::new();
@@ -127,4 +313,537 @@ mod tests {
         assert_eq!(String::from_utf8(output)?, expected);
         Ok(())
     }
+
+    #[test]
+    fn test_example_input_ndjson() -> Result<(), Box<dyn Error>> {
+        let mut output = Vec::new();
+        run("data/example_input.ndjson", &mut output)?;
+
+        let expected = "client,available,held,total,locked
+1,1.5,0,1.5,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_ndjson() {
+        let mut output = Vec::new();
+        let result = run("data/invalid.ndjson", &mut output);
+
+        // The error should point at the offending line
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().contains("line 2"),
+            "unexpected error: {}",
+            err
+        );
+        assert!(matches!(err, RunError::Ndjson { line: 2, .. }));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_explicit_format_overrides_extension() {
+        let mut output = Vec::new();
+        let config = RunConfig {
+            format: Some(InputFormat::Ndjson),
+            ..Default::default()
+        };
+        // A CSV file read as NDJSON fails on its header line
+        let result = run_with_config("data/example_input.csv", &mut output, &config);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "wide-ids"))]
+    fn test_example_input_binary() -> Result<(), Box<dyn Error>> {
+        let mut output = Vec::new();
+        run("data/example_input.bin", &mut output)?;
+
+        let expected = "client,available,held,total,locked
+1,1.5,0,1.5,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_read_as_binary() {
+        let mut output = Vec::new();
+        let config = RunConfig {
+            format: Some(InputFormat::Binary),
+            ..Default::default()
+        };
+        // A CSV file does not start with the binary header
+        let result = run_with_config("data/example_input.csv", &mut output, &config);
+        assert!(result.is_err());
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_csv_strict() {
+        let mut output = Vec::new();
+        let result = run("data/malformed.csv", &mut output);
+        assert!(result.is_err());
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_csv_lenient() -> Result<(), Box<dyn Error>> {
+        let rejects_path = std::env::temp_dir().join("sync_test_malformed_csv_rejects.csv");
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient { max_errors: None },
+            rejects_path: Some(rejects_path.clone()),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/malformed.csv", &mut output, &config)?;
+
+        let expected = "client,available,held,total,locked
+1,1.0,0,1.0,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+
+        // Every skipped row is logged with its line number and raw contents
+        let rejects = std::fs::read_to_string(rejects_path)?;
+        let rows: Vec<_> = rejects.lines().collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0], "line,raw,error");
+        assert!(rows[1].starts_with("3,\"deposit,2,2,abc\",\"CSV deserialize error"));
+        assert_eq!(
+            rows[2],
+            "4,\"withdrawal,1,3\",\"found record with 3 fields, but the header has 4 fields\""
+        );
+        assert!(rows[3].starts_with("6,\"transfer,1,5,1.0\","));
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_ndjson_lenient() -> Result<(), Box<dyn Error>> {
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient { max_errors: None },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/malformed.ndjson", &mut output, &config)?;
+
+        let expected = "client,available,held,total,locked
+1,1.0,0,1.0,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient_max_errors_exceeded() {
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient {
+                max_errors: Some(2),
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let result = run_with_config("data/malformed.csv", &mut output, &config);
+
+        // The third malformed row exceeds the limit and aborts the run
+        assert!(matches!(result, Err(RunError::TooManyRejects(_))));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_outcomes_journal() -> Result<(), Box<dyn Error>> {
+        let outcomes_path = std::env::temp_dir().join("sync_test_outcomes.csv");
+        let config = RunConfig {
+            outcomes_path: Some(outcomes_path.clone()),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/example_input.csv", &mut output, &config)?;
+
+        let expected = "line,type,client,tx,status,reason
+2,deposit,1,1,accepted,
+3,deposit,2,2,accepted,
+4,deposit,1,3,accepted,
+5,withdrawal,1,4,accepted,
+6,withdrawal,2,5,rejected,insufficient_funds
+";
+        assert_eq!(std::fs::read_to_string(outcomes_path)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispute_aging() -> Result<(), Box<dyn Error>> {
+        let outcomes_path = std::env::temp_dir().join("sync_test_aging_outcomes.csv");
+        let open_disputes_path = std::env::temp_dir().join("sync_test_open_disputes.csv");
+        let config = RunConfig {
+            outcomes_path: Some(outcomes_path.clone()),
+            open_disputes_path: Some(open_disputes_path.clone()),
+            engine: EngineConfig {
+                dispute_aging: Some(DisputeAging {
+                    after: AgingThreshold::Transactions(3),
+                    action: AgingAction::Chargeback,
+                }),
+                ..Default::default()
+            },
+            verify: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/dispute_aging.csv", &mut output, &config)?;
+
+        let expected = "client,available,held,total,locked
+1,0,5,5,true
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        // The first dispute is charged back after the third transaction following it
+        let expected = "line,type,client,tx,status,reason
+2,deposit,1,1,accepted,
+3,deposit,1,2,accepted,
+4,dispute,1,1,accepted,
+5,dispute,1,2,accepted,
+6,deposit,2,3,accepted,
+7,deposit,2,4,accepted,
+7,chargeback,1,1,aged,
+";
+        assert_eq!(std::fs::read_to_string(outcomes_path)?, expected);
+        let expected = "client,tx,amount,age_transactions,age_seconds
+1,2,5,2,
+";
+        assert_eq!(std::fs::read_to_string(open_disputes_path)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispute_aging_by_duration() -> Result<(), Box<dyn Error>> {
+        let outcomes_path = std::env::temp_dir().join("sync_test_aging_duration_outcomes.csv");
+        let config = RunConfig {
+            outcomes_path: Some(outcomes_path.clone()),
+            engine: EngineConfig {
+                dispute_aging: Some("resolve=1m".parse()?),
+                ..Default::default()
+            },
+            verify: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/dispute_aging_timestamps.csv", &mut output, &config)?;
+
+        let expected = "client,available,held,total,locked
+1,10,5,15,false
+2,3,0,3,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        // The first dispute is resolved once a transaction is a minute younger than it
+        let expected = "line,type,client,tx,status,reason
+2,deposit,1,1,accepted,
+3,deposit,1,2,accepted,
+4,dispute,1,1,accepted,
+5,deposit,2,3,accepted,
+6,dispute,1,2,accepted,
+7,deposit,2,4,accepted,
+8,deposit,2,5,accepted,
+8,resolve,1,1,aged,
+";
+        assert_eq!(std::fs::read_to_string(outcomes_path)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_precision() -> Result<(), Box<dyn Error>> {
+        let cases = [
+            (
+                Precision::default(),
+                "1,0.7345,0,0.7345,false\n3,2.5,0,2.5,false\n",
+            ),
+            (
+                Precision {
+                    scale: 8,
+                    ..Default::default()
+                },
+                "1,0.73456789,0,0.73456789,false\n2,0.00005,0,0.00005,false\n3,2.5,0,2.5,false\n",
+            ),
+            (
+                Precision {
+                    rounding: Rounding::HalfUp,
+                    ..Default::default()
+                },
+                "1,0.7346,0,0.7346,false\n2,0.0001,0,0.0001,false\n3,2.5,0,2.5,false\n",
+            ),
+            (
+                Precision {
+                    strict: true,
+                    ..Default::default()
+                },
+                "3,2.5,0,2.5,false\n",
+            ),
+        ];
+        for (precision, expected) in cases {
+            let config = RunConfig {
+                engine: EngineConfig {
+                    precision,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            run_with_config("data/precision.csv", &mut output, &config)?;
+            assert_eq!(
+                String::from_utf8(output)?,
+                format!("client,available,held,total,locked\n{}", expected),
+                "{:?}",
+                precision
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_dialect() -> Result<(), Box<dyn Error>> {
+        let mut header_aliases = HeaderAliases::default();
+        header_aliases.insert("kind", "type")?;
+        header_aliases.insert("transaction_id", "tx")?;
+        let config = RunConfig {
+            csv: CsvDialect {
+                delimiter: b';',
+                header_aliases,
+                case_insensitive_types: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/dialect.csv", &mut output, &config)?;
+
+        let expected = "client,available,held,total,locked
+1,1.0,0,1.0,false
+2,2,0,2,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+
+        // Type names are case-sensitive by default
+        let config = RunConfig {
+            csv: CsvDialect {
+                case_insensitive_types: false,
+                ..config.csv
+            },
+            ..Default::default()
+        };
+        let result = run_with_config("data/dialect.csv", &mut Vec::new(), &config);
+        assert!(matches!(result, Err(RunError::Csv { line: Some(2), .. })));
+
+        // Rejected rows are logged as they appear in the input
+        let rejects_path = std::env::temp_dir().join("sync_test_csv_dialect_rejects.csv");
+        let config = RunConfig {
+            parse_mode: ParseMode::Lenient { max_errors: None },
+            rejects_path: Some(rejects_path.clone()),
+            ..config
+        };
+        run_with_config("data/dialect.csv", &mut Vec::new(), &config)?;
+        let rejects = std::fs::read_to_string(rejects_path)?;
+        let rows: Vec<_> = rejects.lines().skip(1).collect();
+        assert!(rows[0].starts_with("2,Deposit; 1; 1; 1.5,"));
+        assert!(rows[1].starts_with("3,DEPOSIT; 2; 2; 2,"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_headerless_csv() -> Result<(), Box<dyn Error>> {
+        // The file starts with a UTF-8 BOM and has no header row
+        let config = RunConfig {
+            format: Some(InputFormat::Csv),
+            csv: CsvDialect {
+                delimiter: b'\t',
+                columns: Some(
+                    ["client", "tx", "type", "amount"]
+                        .map(String::from)
+                        .to_vec(),
+                ),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/headerless.tsv", &mut output, &config)?;
+
+        let expected = "client,available,held,total,locked
+1,2,0,2,false
+2,5,0,5,false
+";
+        assert_eq!(String::from_utf8(output)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_headerless_csv_field_count() {
+        // In strict mode, records not matching the configured columns abort the run
+        let config = RunConfig {
+            format: Some(InputFormat::Csv),
+            csv: CsvDialect {
+                delimiter: b'\t',
+                columns: Some(["client", "tx", "type"].map(String::from).to_vec()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = run_with_config("data/headerless.tsv", &mut Vec::new(), &config);
+        let err = result.unwrap_err();
+        assert!(matches!(err, RunError::Row { line: 1, .. }));
+        assert_eq!(
+            err.to_string(),
+            "line 1: found record with 4 fields, but the header has 3 fields"
+        );
+    }
+
+    #[test]
+    fn test_compressed_input() -> Result<(), Box<dyn Error>> {
+        let expected = "client,available,held,total,locked
+1,1.5,0,1.5,false
+2,2,0,2,false
+";
+        // Compression is detected from the magic bytes and the format from the inner extension
+        for path in [
+            "data/example_input.csv.gz",
+            "data/example_input.csv.zst",
+            "data/example_input.ndjson.gz",
+        ] {
+            let mut output = Vec::new();
+            run(path, &mut output)?;
+            assert_eq!(String::from_utf8(output)?, expected, "{}", path);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_input_without_extension() -> Result<(), Box<dyn Error>> {
+        // zstd-compressed content behind a plain `.csv` name
+        let mut compressed = Vec::new();
+        run("data/10_clients_compressed.csv", &mut compressed)?;
+        let mut plain = Vec::new();
+        run("data/10_clients.csv", &mut plain)?;
+        assert_eq!(compressed, plain);
+
+        // An explicit compression overrides the detection
+        let config = RunConfig {
+            compression: Some(Compression::None),
+            ..Default::default()
+        };
+        let result = run_with_config("data/10_clients_compressed.csv", &mut Vec::new(), &config);
+        assert!(matches!(result, Err(RunError::Csv { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshots_on_marker() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join("sync_test_snapshots_on_marker");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = RunConfig {
+            snapshots: Some(SnapshotConfig {
+                on_marker: true,
+                dir: dir.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        run_with_config("data/snapshots.csv", &mut output, &config)?;
+
+        let first = "client,available,held,total,locked
+1,1,0,1,false
+2,2,0,2,false
+";
+        let second = "client,available,held,total,locked
+1,1.5,0,1.5,false
+2,2,0,2,false
+";
+        let config = config.snapshots.unwrap();
+        assert_eq!(std::fs::read_to_string(config.path(1))?, first);
+        assert_eq!(std::fs::read_to_string(config.path(2))?, second);
+        assert!(!config.path(3).exists());
+        assert_eq!(String::from_utf8(output)?, second);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshots_every_n_changed() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join("sync_test_snapshots_every_n");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = RunConfig {
+            snapshots: Some(SnapshotConfig {
+                every: Some(2),
+                mode: SnapshotMode::Changed,
+                dir: dir.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        run_with_config("data/example_input.csv", &mut Vec::new(), &config)?;
+
+        // Only client 1 changed between the 2nd and 4th transaction
+        let config = config.snapshots.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(config.path(1))?,
+            "client,available,held,total,locked\n1,1,0,1,false\n2,2,0,2,false\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(config.path(2))?,
+            "client,available,held,total,locked\n1,1.5,0,1.5,false\n"
+        );
+        assert!(!config.path(3).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshots_ndjson_marker() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join("sync_test_snapshots_ndjson");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = RunConfig {
+            snapshots: Some(SnapshotConfig {
+                on_marker: true,
+                dir: dir.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        run_with_config("data/snapshots.ndjson", &mut Vec::new(), &config)?;
+
+        let config = config.snapshots.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(config.path(1))?,
+            "client,available,held,total,locked\n1,1.0,0,1.0,false\n"
+        );
+        assert!(!config.path(2).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_marker_disabled() {
+        // Without markers enabled, a marker row is malformed
+        let result = run("data/snapshots.csv", &mut Vec::new());
+        assert!(matches!(result, Err(RunError::Csv { line: Some(4), .. })));
+    }
+
+    #[test]
+    fn test_deltas() -> Result<(), Box<dyn Error>> {
+        let deltas_path = std::env::temp_dir().join("sync_test_deltas.ndjson");
+        let config = RunConfig {
+            deltas_path: Some(deltas_path.clone()),
+            ..Default::default()
+        };
+        run_with_config("data/example_input.csv", &mut Vec::new(), &config)?;
+
+        // The rejected withdrawal of tx 5 has no delta
+        let deltas = std::fs::read_to_string(deltas_path)?;
+        let lines: Vec<_> = deltas.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[3],
+            r#"{"client":1,"tx":4,"before":{"available":"3","held":"0","locked":false},"after":{"available":"1.5","held":"0","locked":false}}"#
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,385 @@
+//! Pre-flight validation of input files.
+//!
+//! The validator reads the whole file without applying it to an engine, and collects
+//! every problem it finds instead of stopping at the first one.
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::compression::open_input;
+use super::rejects::{BoxError, ReadError};
+use super::source::{binary_source, csv_source, ndjson_source, Entry};
+use super::{InputFormat, RunConfig, RunError};
+use crate::dto::{ClientId, TransactionType, TxId};
+use crate::{Precision, TxNamespace};
+
+/// How serious a validation issue is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The record is valid on its own, but may not behave as intended.
+    Warning,
+    /// The record would be rejected by the engine or abort the run.
+    Error,
+}
+
+/// Kind of problem found in the input file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// The record cannot be decoded into a transaction.
+    SchemaError,
+    /// A deposit or withdrawal has no amount.
+    MissingAmount,
+    /// A deposit or withdrawal has a zero or negative amount.
+    NonPositiveAmount,
+    /// A deposit or withdrawal has more decimal places than allowed in strict precision mode.
+    ExcessPrecision,
+    /// A deposit or withdrawal reuses a tx id seen earlier in the file.
+    DuplicateTx,
+    /// A dispute, resolve or chargeback refers to a tx id that does not appear as a
+    /// deposit or withdrawal earlier in the file.
+    UnknownTxReference,
+}
+
+impl IssueKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueKind::UnknownTxReference => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// A single problem found in the input file.
+#[derive(Debug, Serialize)]
+pub struct Issue {
+    /// Line number of the record (record number for binary input)
+    pub line: u64,
+    pub severity: Severity,
+    pub kind: IssueKind,
+    pub tx: Option<TxId>,
+    pub message: String,
+}
+
+/// Machine-readable result of validating an input file.
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    /// Number of records that were decoded successfully
+    pub records: u64,
+    pub errors: u64,
+    pub warnings: u64,
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Returns the highest severity among the issues, or `None` if the file is clean.
+    pub fn severity(&self) -> Option<Severity> {
+        self.issues.iter().map(|issue| issue.severity).max()
+    }
+
+    fn push(&mut self, line: u64, kind: IssueKind, tx: Option<TxId>, message: String) {
+        let severity = kind.severity();
+        match severity {
+            Severity::Warning => self.warnings += 1,
+            Severity::Error => self.errors += 1,
+        }
+        self.issues.push(Issue {
+            line,
+            severity,
+            kind,
+            tx,
+            message,
+        });
+    }
+}
+
+/// Validates the given input file without applying it, reporting every problem found.
+/// Parse mode and rejects settings of the [`RunConfig`] are ignored, as malformed
+/// records are always reported. Amounts are checked against the engine precision.
+///
+/// # Errors
+/// Returns a [`RunError`] only if the input file cannot be read at all.
+pub fn validate<P>(input_path: P, config: &RunConfig) -> Result<ValidationReport, RunError>
+where
+    P: AsRef<Path>,
+{
+    let input_path = input_path.as_ref();
+    let mut validator = Validator {
+        precision: config.engine.precision,
+        tx_namespace: config.engine.tx_namespace,
+        ..Default::default()
+    };
+    let markers = config.snapshot_markers();
+    let result = open_input(input_path, config.compression)
+        .map_err(Into::into)
+        .and_then(|input| match config.input_format(input_path) {
+            InputFormat::Csv => csv_source(input, &config.csv, true, markers, false)
+                .and_then(|mut source| source.try_for_each(|record| validator.check(record))),
+            InputFormat::Ndjson => ndjson_source(input, markers)
+                .and_then(|mut source| source.try_for_each(|record| validator.check(record))),
+            InputFormat::Binary => binary_source(input)
+                .and_then(|mut source| source.try_for_each(|record| validator.check(record))),
+        });
+    result.map_err(|err| RunError::input(input_path, err))?;
+    Ok(validator.finish())
+}
+
+#[derive(Default)]
+struct Validator {
+    report: ValidationReport,
+    precision: Precision,
+    tx_namespace: TxNamespace,
+    /// First line of every deposit/withdrawal tx id, scoped as in the engine
+    seen: HashMap<(Option<ClientId>, TxId), u64>,
+}
+
+impl Validator {
+    /// Checks a single record. Only fatal read errors are propagated.
+    fn check(&mut self, record: Result<(u64, Entry), ReadError>) -> Result<(), BoxError> {
+        let (line, transaction) = match record {
+            Ok((line, Entry::Transaction(transaction))) => (line, transaction),
+            Ok((_, Entry::Marker)) => return Ok(()),
+            Err(ReadError::Row(row)) => {
+                self.report.push(
+                    row.line,
+                    IssueKind::SchemaError,
+                    None,
+                    row.source.to_string(),
+                );
+                return Ok(());
+            }
+            Err(ReadError::Fatal(err)) => return Err(err),
+        };
+        self.report.records += 1;
+
+        let tx = transaction.tx;
+        let client = (self.tx_namespace == TxNamespace::PerClient).then_some(transaction.client);
+        match transaction.tx_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                match transaction.amount {
+                    None => self.report.push(
+                        line,
+                        IssueKind::MissingAmount,
+                        Some(tx),
+                        format!("{} without an amount", type_name(&transaction.tx_type)),
+                    ),
+                    Some(amount) if amount <= Decimal::ZERO => self.report.push(
+                        line,
+                        IssueKind::NonPositiveAmount,
+                        Some(tx),
+                        format!("amount must be positive, got {}", amount),
+                    ),
+                    Some(amount) if self.precision.apply(amount).is_err() => self.report.push(
+                        line,
+                        IssueKind::ExcessPrecision,
+                        Some(tx),
+                        format!(
+                            "amount {} has more than {} decimal places",
+                            amount, self.precision.scale
+                        ),
+                    ),
+                    Some(_) => {}
+                }
+                if let Some(first_line) = self.seen.get(&(client, tx)) {
+                    self.report.push(
+                        line,
+                        IssueKind::DuplicateTx,
+                        Some(tx),
+                        format!("tx {} already used on line {}", tx, first_line),
+                    );
+                } else {
+                    self.seen.insert((client, tx), line);
+                }
+            }
+            // Like the engine, only transactions processed before can be referenced
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                if !self.seen.contains_key(&(client, tx)) {
+                    self.report.push(
+                        line,
+                        IssueKind::UnknownTxReference,
+                        Some(tx),
+                        format!("tx {} does not appear earlier in the file", tx),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> ValidationReport {
+        self.report
+    }
+}
+
+fn type_name(tx_type: &TransactionType) -> &'static str {
+    match tx_type {
+        TransactionType::Deposit => "deposit",
+        TransactionType::Withdrawal => "withdrawal",
+        TransactionType::Dispute => "dispute",
+        TransactionType::Resolve => "resolve",
+        TransactionType::Chargeback => "chargeback",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EngineConfig;
+    use std::error::Error;
+
+    #[test]
+    fn test_valid_file() -> Result<(), Box<dyn Error>> {
+        let report = validate("data/10_clients.csv", &RunConfig::default())?;
+        assert_eq!(report.records, 1000);
+        assert!(report.issues.is_empty());
+        assert_eq!(report.severity(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reports_every_issue() -> Result<(), Box<dyn Error>> {
+        let report = validate("data/validate.csv", &RunConfig::default())?;
+
+        let issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.line, issue.kind))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (3, IssueKind::SchemaError),
+                (4, IssueKind::MissingAmount),
+                (5, IssueKind::NonPositiveAmount),
+                (6, IssueKind::NonPositiveAmount),
+                (7, IssueKind::DuplicateTx),
+                (9, IssueKind::UnknownTxReference),
+                (10, IssueKind::SchemaError),
+            ]
+        );
+        assert_eq!(report.records, 7);
+        assert_eq!(report.errors, 6);
+        assert_eq!(report.warnings, 1);
+        assert_eq!(report.severity(), Some(Severity::Error));
+        Ok(())
+    }
+
+    #[test]
+    fn test_warnings_only() -> Result<(), Box<dyn Error>> {
+        let report = validate("data/dispute_with_amount.csv", &RunConfig::default())?;
+        assert!(report.issues.is_empty());
+
+        let report = validate("data/unknown_reference.ndjson", &RunConfig::default())?;
+        assert_eq!(report.warnings, 1);
+        assert_eq!(report.errors, 0);
+        assert_eq!(report.severity(), Some(Severity::Warning));
+        Ok(())
+    }
+
+    #[test]
+    fn test_forward_reference() -> Result<(), Box<dyn Error>> {
+        // The dispute comes before its deposit, so the engine would reject it
+        let report = validate("data/forward_reference.csv", &RunConfig::default())?;
+        let issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.line, issue.kind))
+            .collect();
+        assert_eq!(issues, [(2, IssueKind::UnknownTxReference)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_precision() -> Result<(), Box<dyn Error>> {
+        let report = validate("data/precision.csv", &RunConfig::default())?;
+        assert!(report.issues.is_empty());
+
+        let config = RunConfig {
+            engine: EngineConfig {
+                precision: Precision {
+                    strict: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let report = validate("data/precision.csv", &config)?;
+        let issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.line, issue.kind))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (2, IssueKind::ExcessPrecision),
+                (3, IssueKind::ExcessPrecision)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_per_client_tx_namespace() -> Result<(), Box<dyn Error>> {
+        let config = RunConfig {
+            engine: EngineConfig {
+                tx_namespace: TxNamespace::PerClient,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let report = validate("data/validate.csv", &config)?;
+        let issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.line, issue.kind))
+            .collect();
+        // Client 2 can reuse tx 1 of client 1
+        assert_eq!(
+            issues,
+            [
+                (3, IssueKind::SchemaError),
+                (4, IssueKind::MissingAmount),
+                (5, IssueKind::NonPositiveAmount),
+                (6, IssueKind::NonPositiveAmount),
+                (9, IssueKind::UnknownTxReference),
+                (10, IssueKind::SchemaError),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_serialization() -> Result<(), Box<dyn Error>> {
+        let report = validate("data/unknown_reference.ndjson", &RunConfig::default())?;
+        let json = serde_json::to_value(&report)?;
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "records": 2,
+                "errors": 0,
+                "warnings": 1,
+                "issues": [{
+                    "line": 2,
+                    "severity": "warning",
+                    "kind": "unknown_tx_reference",
+                    "tx": 7,
+                    "message": "tx 7 does not appear earlier in the file",
+                }],
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unreadable_file() {
+        assert!(matches!(
+            validate("data/does_not_exist.csv", &RunConfig::default()),
+            Err(RunError::Io { .. })
+        ));
+    }
+}
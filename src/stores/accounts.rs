@@ -9,26 +9,56 @@
 use rust_decimal::Decimal;
 use std::collections::HashMap;
 
+use super::ClientPostings;
+use crate::dto::ClientId;
 use crate::Error;
 
 /// Account state including balance and lock status.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Account {
-    pub id: u16,
+    pub id: ClientId,
     pub available: Decimal,
     pub held: Decimal,
+    /// Part of disputed deposits that could not be held, owed by the client. Not part of
+    /// the balances (see [`NegativeBalancePolicy`](crate::NegativeBalancePolicy)).
+    pub receivable: Decimal,
     pub locked: bool,
+    /// Set by a chargeback that did not lock the account, for review
+    /// (see [`ChargebackPolicy`](crate::ChargebackPolicy)).
+    pub flagged: bool,
+    /// Number of accepted deposits
+    pub deposits: u32,
+    /// Number of charged back deposits
+    pub chargebacks: u32,
+    /// Debits and credits posted to the client's ledger accounts, kept to audit `available`
+    /// and `held` (see [`Ledger::trial_balance`](super::Ledger::trial_balance)).
+    pub(crate) postings: ClientPostings,
 }
 
 impl Account {
+    /// Creates an empty, unlocked account.
+    pub fn new(id: ClientId) -> Self {
+        Self {
+            id,
+            available: Decimal::ZERO,
+            held: Decimal::ZERO,
+            receivable: Decimal::ZERO,
+            locked: false,
+            flagged: false,
+            deposits: 0,
+            chargebacks: 0,
+            postings: ClientPostings::default(),
+        }
+    }
+
     pub fn total(&self) -> Decimal {
         self.available + self.held
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct AccountsStore {
-    accounts: HashMap<u16, Account>,
+    accounts: HashMap<ClientId, Account>,
 }
 
 impl AccountsStore {
@@ -41,7 +71,7 @@ impl AccountsStore {
     /// Checks that an account is not locked.
     /// This check should be performed early in the pipeline to avoid handling
     /// transactions for locked accounts.
-    pub fn check_account_lock(&self, client: u16) -> Result<(), Error> {
+    pub fn check_account_lock(&self, client: ClientId) -> Result<(), Error> {
         if let Some(account) = self.accounts.get(&client) {
             if account.locked {
                 return Err(Error::AccountLocked);
@@ -51,18 +81,28 @@ impl AccountsStore {
     }
 
     /// Gets a mutable account entry, or creates one if it doesn't exist.
-    pub fn get_or_create_mut(&mut self, client: u16) -> &mut Account {
-        self.accounts.entry(client).or_insert_with(|| Account {
-            id: client,
-            available: Decimal::ZERO,
-            held: Decimal::ZERO,
-            locked: false,
-        })
+    pub fn get_or_create_mut(&mut self, client: ClientId) -> &mut Account {
+        self.accounts
+            .entry(client)
+            .or_insert_with(|| Account::new(client))
+    }
+
+    /// Gets an account entry, if it exists.
+    pub fn get(&self, client: ClientId) -> Option<&Account> {
+        self.accounts.get(&client)
     }
 
-    /// Gets an account entry, or returns an error if it doesn't exist.
-    pub fn get_mut(&mut self, client: u16) -> Result<&mut Account, Error> {
-        self.accounts.get_mut(&client).ok_or(Error::AccountNotFound)
+    /// Puts back an account as it was before a change, removing it if it did not exist.
+    pub fn restore(&mut self, client: ClientId, account: Option<Account>) {
+        match account {
+            Some(account) => self.accounts.insert(client, account),
+            None => self.accounts.remove(&client),
+        };
+    }
+
+    /// Returns the number of accounts.
+    pub fn count(&self) -> usize {
+        self.accounts.len()
     }
 
     /// Returns an iterator over all accounts.
@@ -105,14 +145,14 @@ mod tests {
         }
 
         // Get it again
-        let account = store.get_mut(1).unwrap();
+        let account = store.get(1).unwrap();
         assert_eq!(account.available, dec!(100));
     }
 
     #[test]
     fn test_get_nonexistent_account() {
-        let mut store = AccountsStore::new();
-        assert!(matches!(store.get_mut(1), Err(Error::AccountNotFound)));
+        let store = AccountsStore::new();
+        assert!(store.get(1).is_none());
     }
 
     #[test]
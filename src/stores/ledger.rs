@@ -0,0 +1,362 @@
+//! Double-entry ledger behind the account balances.
+//!
+//! Every accepted transaction posts a balanced entry, debiting one ledger account and
+//! crediting another by the same amount. The ledger keeps the debit and credit totals of
+//! the external and settlement accounts, while the totals of a client's ledger accounts
+//! are kept on its [`Account`], so that posting needs no lookup. Posting moves the client's
+//! balances directly, and the totals are only kept for auditing: the trial balance checks
+//! them against the balances, proving where money came from.
+//!
+//! Balances follow the liability convention: an account's balance is its credits
+//! minus its debits. Funds deposited by clients are thus credited to their available
+//! balance and debited from [`LedgerAccount::External`], which goes negative.
+
+use rust_decimal::Decimal;
+
+use super::Account;
+use crate::dto::{ClientId, TxId};
+
+/// Account of the double-entry ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LedgerAccount {
+    /// Available funds of a client
+    Available(ClientId),
+    /// Funds of a client held by disputes
+    Held(ClientId),
+    /// Outside world, where deposits come from and withdrawals go to
+    External,
+    /// Funds returned by chargebacks
+    Settlement,
+}
+
+/// Balanced entry moving an amount from the debited to the credited ledger account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedgerEntry {
+    pub tx: TxId,
+    pub debit: LedgerAccount,
+    pub credit: LedgerAccount,
+    pub amount: Decimal,
+}
+
+/// Totals of the ledger, as returned by [`Engine::trial_balance`](crate::Engine::trial_balance).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrialBalance {
+    /// Sum of the debits posted to every ledger account
+    pub debits: Decimal,
+    /// Sum of the credits posted to every ledger account
+    pub credits: Decimal,
+    /// Sum of the client account totals and the external and settlement balances
+    pub net: Decimal,
+}
+
+impl TrialBalance {
+    /// Whether debits equal credits, and the balances of all ledger accounts cancel out.
+    pub fn is_balanced(&self) -> bool {
+        self.debits == self.credits && self.net.is_zero()
+    }
+}
+
+/// Debits and credits posted to a single ledger account.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Postings {
+    pub debits: Decimal,
+    pub credits: Decimal,
+}
+
+impl Postings {
+    /// Balance of the ledger account: its credits minus its debits.
+    pub fn balance(&self) -> Decimal {
+        self.credits - self.debits
+    }
+}
+
+/// Postings to the ledger accounts of a client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientPostings {
+    pub available: Postings,
+    pub held: Postings,
+}
+
+/// Totals of the non-client ledger accounts at a point in time, as returned by
+/// [`Ledger::mark`]. The postings of client ledger accounts are restored with their
+/// [`Account`].
+#[derive(Debug, Clone, Copy)]
+pub struct LedgerMark {
+    external: Postings,
+    settlement: Postings,
+    journal_len: usize,
+}
+
+#[derive(Default, Clone)]
+pub struct Ledger {
+    external: Postings,
+    settlement: Postings,
+    /// Every posted entry, if the journal is kept
+    journal: Option<Vec<LedgerEntry>>,
+}
+
+impl Ledger {
+    /// Creates an empty ledger, keeping a journal of every posted entry if requested.
+    pub fn new(keep_journal: bool) -> Self {
+        Self {
+            journal: keep_journal.then(Vec::new),
+            ..Default::default()
+        }
+    }
+
+    /// Posts an entry, moving the balances of the client `account` it refers to.
+    pub fn post(&mut self, account: &mut Account, entry: LedgerEntry) {
+        if let Some(balance) = client_balance_mut(account, entry.debit) {
+            *balance -= entry.amount;
+        }
+        if let Some(balance) = client_balance_mut(account, entry.credit) {
+            *balance += entry.amount;
+        }
+        self.postings_mut(account, entry.debit).debits += entry.amount;
+        self.postings_mut(account, entry.credit).credits += entry.amount;
+        if let Some(journal) = &mut self.journal {
+            journal.push(entry);
+        }
+    }
+
+    /// Marks the current totals of the ledger, to roll back to later.
+    pub fn mark(&self) -> LedgerMark {
+        LedgerMark {
+            external: self.external,
+            settlement: self.settlement,
+            journal_len: self.journal().len(),
+        }
+    }
+
+    /// Rolls back every entry posted since the mark was taken.
+    /// Client postings are not part of the mark, and are restored with the client's
+    /// [`Account`] by the caller.
+    pub fn rollback(&mut self, mark: LedgerMark) {
+        self.external = mark.external;
+        self.settlement = mark.settlement;
+        if let Some(journal) = &mut self.journal {
+            journal.truncate(mark.journal_len);
+        }
+    }
+
+    fn postings_mut<'a>(
+        &'a mut self,
+        account: &'a mut Account,
+        ledger_account: LedgerAccount,
+    ) -> &'a mut Postings {
+        match ledger_account {
+            LedgerAccount::Available(client) => {
+                debug_assert_eq!(client, account.id);
+                &mut account.postings.available
+            }
+            LedgerAccount::Held(client) => {
+                debug_assert_eq!(client, account.id);
+                &mut account.postings.held
+            }
+            LedgerAccount::External => &mut self.external,
+            LedgerAccount::Settlement => &mut self.settlement,
+        }
+    }
+
+    /// Balance of a ledger account, computed from its postings. The postings of a client's
+    /// ledger accounts are kept on its `account`, `None` if it has none.
+    pub fn balance(
+        &self,
+        ledger_account: LedgerAccount,
+        account: Option<&Account>,
+    ) -> Option<Decimal> {
+        match ledger_account {
+            LedgerAccount::External => Some(self.external.balance()),
+            LedgerAccount::Settlement => Some(self.settlement.balance()),
+            LedgerAccount::Available(_) => {
+                account.map(|account| account.postings.available.balance())
+            }
+            LedgerAccount::Held(_) => account.map(|account| account.postings.held.balance()),
+        }
+    }
+
+    /// Computes the trial balance over the ledger and the given client accounts.
+    ///
+    /// Debits and credits are summed independently over every ledger account, while the
+    /// net balance uses the client account balances, so that balances changed outside of
+    /// the ledger show up as well.
+    pub fn trial_balance<'a>(&self, accounts: impl Iterator<Item = &'a Account>) -> TrialBalance {
+        let mut debits = self.external.debits + self.settlement.debits;
+        let mut credits = self.external.credits + self.settlement.credits;
+        let mut clients = Decimal::ZERO;
+        for account in accounts {
+            for postings in [account.postings.available, account.postings.held] {
+                debits += postings.debits;
+                credits += postings.credits;
+            }
+            clients += account.total();
+        }
+        TrialBalance {
+            debits,
+            credits,
+            net: clients + self.external.balance() + self.settlement.balance(),
+        }
+    }
+
+    /// Every posted entry, in posting order. Empty if the journal is not kept.
+    pub fn journal(&self) -> &[LedgerEntry] {
+        self.journal.as_deref().unwrap_or_default()
+    }
+}
+
+/// Balance of the client `account` a ledger account refers to, `None` for the external
+/// and settlement accounts.
+fn client_balance_mut(
+    account: &mut Account,
+    ledger_account: LedgerAccount,
+) -> Option<&mut Decimal> {
+    match ledger_account {
+        LedgerAccount::Available(_) => Some(&mut account.available),
+        LedgerAccount::Held(_) => Some(&mut account.held),
+        LedgerAccount::External | LedgerAccount::Settlement => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn account(id: ClientId) -> Account {
+        Account::new(id)
+    }
+
+    #[test]
+    fn test_post_moves_balances() {
+        let mut ledger = Ledger::new(true);
+        let mut client = account(1);
+        let entries = [
+            (
+                LedgerAccount::External,
+                LedgerAccount::Available(1),
+                dec!(10),
+            ),
+            (LedgerAccount::Available(1), LedgerAccount::Held(1), dec!(4)),
+            (LedgerAccount::Held(1), LedgerAccount::Settlement, dec!(4)),
+        ];
+        for (tx, (debit, credit, amount)) in (1..).zip(entries) {
+            ledger.post(
+                &mut client,
+                LedgerEntry {
+                    tx,
+                    debit,
+                    credit,
+                    amount,
+                },
+            );
+        }
+
+        assert_eq!(client.available, dec!(6));
+        assert_eq!(client.held, dec!(0));
+        assert_eq!(
+            ledger.balance(LedgerAccount::External, None),
+            Some(dec!(-10))
+        );
+        assert_eq!(
+            ledger.balance(LedgerAccount::Settlement, None),
+            Some(dec!(4))
+        );
+        assert_eq!(ledger.journal().len(), 3);
+
+        assert_eq!(
+            ledger.balance(LedgerAccount::Available(1), Some(&client)),
+            Some(dec!(6))
+        );
+        assert_eq!(ledger.balance(LedgerAccount::Held(2), None), None);
+
+        let trial_balance = ledger.trial_balance([&client].into_iter());
+        assert_eq!(trial_balance.debits, dec!(18));
+        assert!(trial_balance.is_balanced());
+    }
+
+    #[test]
+    fn test_unbalanced_postings() {
+        let mut ledger = Ledger::new(false);
+        let mut client = account(1);
+        ledger.post(
+            &mut client,
+            LedgerEntry {
+                tx: 1,
+                debit: LedgerAccount::External,
+                credit: LedgerAccount::Available(1),
+                amount: dec!(10),
+            },
+        );
+        assert!(ledger.trial_balance([&client].into_iter()).is_balanced());
+
+        // A one-sided posting to the client's available funds
+        client.postings.available.credits += dec!(1);
+        let trial_balance = ledger.trial_balance([&client].into_iter());
+        assert_eq!(trial_balance.debits, dec!(10));
+        assert_eq!(trial_balance.credits, dec!(11));
+        assert!(!trial_balance.is_balanced());
+
+        // The client's balances only move with the posted entries
+        ledger.post(
+            &mut client,
+            LedgerEntry {
+                tx: 2,
+                debit: LedgerAccount::Available(1),
+                credit: LedgerAccount::Held(1),
+                amount: dec!(4),
+            },
+        );
+        assert_eq!((client.available, client.held), (dec!(6), dec!(4)));
+        assert!(!ledger.trial_balance([&client].into_iter()).is_balanced());
+    }
+
+    #[test]
+    fn test_rollback_restores_postings() {
+        let mut ledger = Ledger::new(true);
+        let mut client = account(1);
+        let deposit = LedgerEntry {
+            tx: 1,
+            debit: LedgerAccount::External,
+            credit: LedgerAccount::Available(1),
+            amount: dec!(10),
+        };
+        ledger.post(&mut client, deposit);
+        let (mark, saved) = (ledger.mark(), client.clone());
+
+        ledger.post(&mut client, LedgerEntry { tx: 2, ..deposit });
+        ledger.rollback(mark);
+        client = saved;
+        assert_eq!(
+            ledger.balance(LedgerAccount::External, None),
+            Some(dec!(-10))
+        );
+        assert_eq!(
+            ledger.balance(LedgerAccount::Available(1), Some(&client)),
+            Some(dec!(10))
+        );
+        assert!(ledger.trial_balance([&client].into_iter()).is_balanced());
+        assert_eq!(ledger.journal().len(), 1);
+    }
+
+    #[test]
+    fn test_unbalanced_accounts() {
+        let mut ledger = Ledger::new(false);
+        let mut client = account(1);
+        ledger.post(
+            &mut client,
+            LedgerEntry {
+                tx: 1,
+                debit: LedgerAccount::External,
+                credit: LedgerAccount::Available(1),
+                amount: dec!(10),
+            },
+        );
+        assert!(ledger.journal().is_empty());
+
+        // A balance changed outside of the ledger shows up in the trial balance
+        client.available += dec!(1);
+        let trial_balance = ledger.trial_balance([&client].into_iter());
+        assert_eq!(trial_balance.net, dec!(1));
+        assert!(!trial_balance.is_balanced());
+    }
+}
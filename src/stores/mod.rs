@@ -1,12 +1,17 @@
 //! Storage layer for the payment processing system. Provides storage for:
 //! - Account balances and states ([`AccountsStore`])
 //! - Transaction history for dispute handling ([`TransactionsStore`])
+//! - Double-entry ledger behind the account balances ([`Ledger`])
 //!
 //! Current implementation is optimized for synchronous, direct memory
 //! access.
 
 mod accounts;
+mod ledger;
 mod transactions;
 
 pub use accounts::{Account, AccountsStore};
-pub use transactions::TransactionsStore;
+pub use ledger::{ClientPostings, Ledger, LedgerAccount, LedgerEntry, LedgerMark, TrialBalance};
+pub use transactions::{
+    DisputeOpened, ProcessedIds, StoredDeposit, TransactionsStore, TxNamespace,
+};
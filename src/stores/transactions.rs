@@ -6,74 +6,404 @@
 //! - Validating transaction ownership
 
 use rust_decimal::Decimal;
-use std::collections::{HashMap, HashSet};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
 
+use crate::dto::{ClientId, TxId};
 use crate::Error;
 
-#[derive(Debug)]
+/// Scope in which transaction IDs must be unique.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TxNamespace {
+    /// Transaction IDs are unique across all clients.
+    #[default]
+    Global,
+    /// Every client has its own transaction IDs, so different clients can use the
+    /// same ID, and disputes refer to the client's own deposits.
+    PerClient,
+}
+
+impl FromStr for TxNamespace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "global" => Ok(TxNamespace::Global),
+            "per-client" => Ok(TxNamespace::PerClient),
+            other => Err(format!("unknown tx namespace: {}", other)),
+        }
+    }
+}
+
+/// How the IDs of processed deposits and withdrawals are stored for duplicate detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProcessedIds {
+    /// A hash set, costing about 16 bytes per ID whatever the IDs are.
+    #[default]
+    HashSet,
+    /// A bitmap of the IDs of every namespace, costing one bit per ID up to the highest
+    /// one seen. Much smaller for dense IDs counting up from 0, much larger for sparse ones.
+    /// IDs of 2^28 and above are kept in a hash set.
+    Bitmap,
+}
+
+impl FromStr for ProcessedIds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hash-set" => Ok(ProcessedIds::HashSet),
+            "bitmap" => Ok(ProcessedIds::Bitmap),
+            other => Err(format!("unknown processed ids store: {}", other)),
+        }
+    }
+}
+
+/// Key of a stored transaction: the client is only part of it in the
+/// [`TxNamespace::PerClient`] namespace.
+type TxKey = (Option<ClientId>, TxId);
+
+/// Set of transaction keys, stored as chosen by [`ProcessedIds`].
+#[derive(Clone)]
+enum TxKeySet {
+    Hash(HashSet<TxKey>),
+    Bitmap(TxBitmap),
+}
+
+impl TxKeySet {
+    fn new(processed_ids: ProcessedIds) -> Self {
+        match processed_ids {
+            ProcessedIds::HashSet => TxKeySet::Hash(HashSet::new()),
+            ProcessedIds::Bitmap => TxKeySet::Bitmap(TxBitmap::default()),
+        }
+    }
+
+    fn contains(&self, key: &TxKey) -> bool {
+        match self {
+            TxKeySet::Hash(set) => set.contains(key),
+            TxKeySet::Bitmap(bitmap) => bitmap.contains(key),
+        }
+    }
+
+    fn insert(&mut self, key: TxKey) {
+        match self {
+            TxKeySet::Hash(set) => {
+                set.insert(key);
+            }
+            TxKeySet::Bitmap(bitmap) => bitmap.insert(key),
+        }
+    }
+
+    fn remove(&mut self, key: &TxKey) {
+        match self {
+            TxKeySet::Hash(set) => {
+                set.remove(key);
+            }
+            TxKeySet::Bitmap(bitmap) => bitmap.remove(key),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            TxKeySet::Hash(set) => set.len(),
+            TxKeySet::Bitmap(bitmap) => bitmap.len,
+        }
+    }
+}
+
+impl Default for TxKeySet {
+    fn default() -> Self {
+        TxKeySet::new(ProcessedIds::default())
+    }
+}
+
+/// Bitmap of transaction IDs below [`TxBitmap::MAX_ID`], one per namespace key.
+#[derive(Clone, Default)]
+struct TxBitmap {
+    words: HashMap<Option<ClientId>, Vec<u64>>,
+    /// Keys with an ID too large for the bitmap
+    overflow: HashSet<TxKey>,
+    len: usize,
+}
+
+impl TxBitmap {
+    /// IDs from this one up are kept in the overflow set, bounding a bitmap to 32 MiB.
+    const MAX_ID: TxId = 1 << 28;
+
+    /// Returns the index of the word and the mask of the bit of an ID, if it fits the bitmap.
+    fn position(tx: TxId) -> Option<(usize, u64)> {
+        (tx < Self::MAX_ID).then(|| ((tx / 64) as usize, 1 << (tx % 64)))
+    }
+
+    fn contains(&self, key: &TxKey) -> bool {
+        match Self::position(key.1) {
+            Some((word, bit)) => self
+                .words
+                .get(&key.0)
+                .and_then(|words| words.get(word))
+                .is_some_and(|bits| bits & bit != 0),
+            None => self.overflow.contains(key),
+        }
+    }
+
+    fn insert(&mut self, key: TxKey) {
+        let inserted = match Self::position(key.1) {
+            Some((word, bit)) => {
+                let words = self.words.entry(key.0).or_default();
+                if words.len() <= word {
+                    words.resize(word + 1, 0);
+                }
+                let inserted = words[word] & bit == 0;
+                words[word] |= bit;
+                inserted
+            }
+            None => self.overflow.insert(key),
+        };
+        self.len += usize::from(inserted);
+    }
+
+    fn remove(&mut self, key: &TxKey) {
+        let removed = match Self::position(key.1) {
+            Some((word, bit)) => match self.words.get_mut(&key.0).and_then(|w| w.get_mut(word)) {
+                Some(bits) => {
+                    let removed = *bits & bit != 0;
+                    *bits &= !bit;
+                    removed
+                }
+                None => false,
+            },
+            None => self.overflow.remove(key),
+        };
+        self.len -= usize::from(removed);
+    }
+}
+
+/// When a dispute was opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisputeOpened {
+    /// Number of transactions processed by then, different for every open dispute
+    pub processed: u64,
+    /// Latest transaction timestamp by then, if any transaction had one
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
 pub struct StoredDeposit {
-    pub client: u16,
+    pub client: ClientId,
     pub amount: Decimal,
     pub disputed: bool,
+    /// Whether a dispute of the deposit ended in a chargeback
+    pub charged_back: bool,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct TransactionsStore {
+    namespace: TxNamespace,
     /// Deposits can be disputed, so this is a map of all successful deposits
-    deposits: HashMap<u32, StoredDeposit>,
-    /// Set of all successfully processed deposit/withdrawal transaction IDs to prevent duplicates
-    processed_transactions: HashSet<u32>,
+    deposits: HashMap<TxKey, StoredDeposit>,
+    /// Set of all successfully processed deposit/withdrawal transactions to prevent duplicates
+    processed_transactions: TxKeySet,
+    /// Rejected deposit/withdrawal transactions whose IDs cannot be used again,
+    /// with the reason of the rejection
+    rejected_transactions: HashMap<TxKey, Error>,
+    /// Part of disputed or charged back deposits that could not be held, if any
+    shortfalls: HashMap<TxKey, Decimal>,
+    /// When each open dispute was opened
+    disputes_opened: HashMap<TxKey, DisputeOpened>,
+    /// Open disputes by the time they were opened, oldest first
+    open_disputes: BTreeMap<u64, TxKey>,
 }
 
 impl TransactionsStore {
-    pub fn new() -> Self {
+    /// Creates a store where transaction IDs are unique within the given namespace,
+    /// keeping the processed IDs as chosen.
+    pub fn with_config(namespace: TxNamespace, processed_ids: ProcessedIds) -> Self {
         Self {
+            namespace,
             deposits: HashMap::new(),
-            processed_transactions: HashSet::new(),
+            processed_transactions: TxKeySet::new(processed_ids),
+            rejected_transactions: HashMap::new(),
+            shortfalls: HashMap::new(),
+            disputes_opened: HashMap::new(),
+            open_disputes: BTreeMap::new(),
         }
     }
 
+    fn key(&self, client: ClientId, tx: TxId) -> TxKey {
+        let client = (self.namespace == TxNamespace::PerClient).then_some(client);
+        (client, tx)
+    }
+
     /// Checks if a transaction has been processed already.
     /// Processed transactions cannot be repeated.
-    pub fn is_processed(&self, tx: u32) -> bool {
-        self.processed_transactions.contains(&tx)
+    pub fn is_processed(&self, client: ClientId, tx: TxId) -> bool {
+        self.processed_transactions.contains(&self.key(client, tx))
     }
 
     /// Marks a transaction as processed.
     /// Processed transactions cannot be repeated.
-    pub fn mark_processed(&mut self, tx: u32) {
-        self.processed_transactions.insert(tx);
+    pub fn mark_processed(&mut self, client: ClientId, tx: TxId) {
+        self.processed_transactions.insert(self.key(client, tx));
+    }
+
+    /// Forgets a processed transaction, so that its ID can be used again.
+    pub fn unmark_processed(&mut self, client: ClientId, tx: TxId) {
+        self.processed_transactions.remove(&self.key(client, tx));
+    }
+
+    /// Returns the number of processed transactions kept for duplicate detection.
+    pub fn processed_count(&self) -> usize {
+        self.processed_transactions.len()
+    }
+
+    /// Returns the reason a transaction was rejected, if its ID was kept as used.
+    pub fn rejection(&self, client: ClientId, tx: TxId) -> Option<Error> {
+        self.rejected_transactions
+            .get(&self.key(client, tx))
+            .copied()
+    }
+
+    /// Marks the ID of a rejected transaction as used, keeping the reason of the rejection.
+    pub fn mark_rejected(&mut self, client: ClientId, tx: TxId, reason: Error) {
+        self.rejected_transactions
+            .insert(self.key(client, tx), reason);
+    }
+
+    /// Forgets a rejected transaction, so that its ID can be used again.
+    pub fn unmark_rejected(&mut self, client: ClientId, tx: TxId) {
+        self.rejected_transactions.remove(&self.key(client, tx));
     }
 
     /// Stores a new deposit transaction to track its dispute status.
     /// Returns an error if the deposit with the same transaction ID already exists.
     pub fn store_new_deposit(
         &mut self,
-        tx: u32,
-        client: u16,
+        tx: TxId,
+        client: ClientId,
         amount: Decimal,
     ) -> Result<(), Error> {
-        if self.deposits.contains_key(&tx) {
+        let key = self.key(client, tx);
+        if self.deposits.contains_key(&key) {
             return Err(Error::DuplicateTransaction);
         }
         self.deposits.insert(
-            tx,
+            key,
             StoredDeposit {
                 client,
                 amount,
                 disputed: false,
+                charged_back: false,
             },
         );
         Ok(())
     }
 
+    /// Puts back a deposit as it was before a change, removing it if it did not exist.
+    pub fn restore_deposit(&mut self, client: ClientId, tx: TxId, deposit: Option<StoredDeposit>) {
+        let key = self.key(client, tx);
+        match deposit {
+            Some(deposit) => self.deposits.insert(key, deposit),
+            None => self.deposits.remove(&key),
+        };
+    }
+
+    /// Returns the part of a deposit's disputed amount that could not be held.
+    pub fn shortfall(&self, client: ClientId, tx: TxId) -> Decimal {
+        self.shortfalls
+            .get(&self.key(client, tx))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets the part of a deposit's disputed amount that could not be held.
+    /// Only non-zero shortfalls are stored.
+    pub fn set_shortfall(&mut self, client: ClientId, tx: TxId, shortfall: Decimal) {
+        let key = self.key(client, tx);
+        match shortfall.is_zero() {
+            true => self.shortfalls.remove(&key),
+            false => self.shortfalls.insert(key, shortfall),
+        };
+    }
+
+    /// Returns an iterator over the deposits with a non-zero shortfall, and their shortfall.
+    /// Provides no guarantees about the order of the deposits.
+    pub fn shortfalls(&self) -> impl Iterator<Item = (&StoredDeposit, Decimal)> {
+        self.shortfalls
+            .iter()
+            .filter_map(|(key, shortfall)| Some((self.deposits.get(key)?, *shortfall)))
+    }
+
+    /// Returns when the dispute of a deposit was opened, if it is open.
+    pub fn dispute_opened(&self, client: ClientId, tx: TxId) -> Option<DisputeOpened> {
+        self.disputes_opened.get(&self.key(client, tx)).copied()
+    }
+
+    /// Sets when the dispute of a deposit was opened, or `None` once it is closed.
+    /// Every open dispute must have been opened after a different number of transactions.
+    pub fn set_dispute_opened(
+        &mut self,
+        client: ClientId,
+        tx: TxId,
+        opened: Option<DisputeOpened>,
+    ) {
+        let key = self.key(client, tx);
+        if let Some(previous) = self.disputes_opened.remove(&key) {
+            self.open_disputes.remove(&previous.processed);
+        }
+        if let Some(opened) = opened {
+            self.disputes_opened.insert(key, opened);
+            self.open_disputes.insert(opened.processed, key);
+        }
+    }
+
+    /// Returns the number of open disputes.
+    pub fn open_dispute_count(&self) -> usize {
+        self.open_disputes.len()
+    }
+
+    /// Returns an iterator over the open disputes, oldest first, with the ID of the disputed
+    /// deposit and when the dispute was opened.
+    pub fn open_disputes(&self) -> impl Iterator<Item = (TxId, &StoredDeposit, DisputeOpened)> {
+        self.open_disputes.values().filter_map(|key| {
+            let opened = *self.disputes_opened.get(key)?;
+            Some((key.1, self.deposits.get(key)?, opened))
+        })
+    }
+
+    /// Returns an iterator over all stored deposits.
+    /// Provides no guarantees about the order of the deposits.
+    pub fn deposits(&self) -> impl Iterator<Item = &StoredDeposit> {
+        self.deposits.values()
+    }
+
+    /// Gets a stored deposit entry if it exists, and validates that it belongs to the client.
+    /// Returns an error if the deposit does not exist or belongs to a different client.
+    pub fn get_deposit(&self, client: ClientId, tx: TxId) -> Result<&StoredDeposit, Error> {
+        let deposit = self
+            .deposits
+            .get(&self.key(client, tx))
+            .ok_or(Error::TransactionNotFound)?;
+        if deposit.client != client {
+            return Err(Error::TransactionClientMismatch);
+        }
+        Ok(deposit)
+    }
+
     /// Gets a stored deposit entry if it exists, and validates that it belongs to the client.
     /// Returns a mutable reference to the deposit, or an error if the deposit does not exist or
     /// belongs to a different client.
-    pub fn get_deposit_mut(&mut self, client: u16, tx: u32) -> Result<&mut StoredDeposit, Error> {
+    pub fn get_deposit_mut(
+        &mut self,
+        client: ClientId,
+        tx: TxId,
+    ) -> Result<&mut StoredDeposit, Error> {
+        let key = self.key(client, tx);
         let deposit = self
             .deposits
-            .get_mut(&tx)
+            .get_mut(&key)
             .ok_or(Error::TransactionNotFound)?;
         if deposit.client != client {
             return Err(Error::TransactionClientMismatch);
@@ -89,29 +419,29 @@ mod tests {
 
     #[test]
     fn test_new_store_is_empty() {
-        let mut store = TransactionsStore::new();
-        assert!(!store.is_processed(1));
+        let mut store = TransactionsStore::default();
+        assert!(!store.is_processed(1, 1));
         assert!(store.get_deposit_mut(1, 1).is_err());
     }
 
     #[test]
     fn test_mark_and_check_processed() {
-        let mut store = TransactionsStore::new();
+        let mut store = TransactionsStore::default();
 
         // Initially not processed
-        assert!(!store.is_processed(1));
+        assert!(!store.is_processed(1, 1));
 
         // Mark as processed
-        store.mark_processed(1);
-        assert!(store.is_processed(1));
+        store.mark_processed(1, 1);
+        assert!(store.is_processed(1, 1));
 
         // Other transactions still not processed
-        assert!(!store.is_processed(2));
+        assert!(!store.is_processed(1, 2));
     }
 
     #[test]
     fn test_store_and_get_deposit() {
-        let mut store = TransactionsStore::new();
+        let mut store = TransactionsStore::default();
         let tx = 1;
         let client = 1;
         let amount = dec!(100.50);
@@ -128,7 +458,7 @@ mod tests {
 
     #[test]
     fn test_get_nonexistent_deposit() {
-        let mut store = TransactionsStore::new();
+        let mut store = TransactionsStore::default();
         assert!(matches!(
             store.get_deposit_mut(1, 1),
             Err(Error::TransactionNotFound)
@@ -137,7 +467,7 @@ mod tests {
 
     #[test]
     fn test_get_deposit_wrong_client() {
-        let mut store = TransactionsStore::new();
+        let mut store = TransactionsStore::default();
         let tx = 1;
         let client = 1;
         let amount = dec!(100);
@@ -154,7 +484,7 @@ mod tests {
 
     #[test]
     fn test_duplicate_tx_different_clients() {
-        let mut store = TransactionsStore::new();
+        let mut store = TransactionsStore::default();
         let tx = 1;
 
         // Create deposit for first client
@@ -172,7 +502,7 @@ mod tests {
 
     #[test]
     fn test_deposit_dispute_status() {
-        let mut store = TransactionsStore::new();
+        let mut store = TransactionsStore::default();
         let tx = 1;
         let client = 1;
 
@@ -191,7 +521,7 @@ mod tests {
 
     #[test]
     fn test_multiple_deposits_same_client() {
-        let mut store = TransactionsStore::new();
+        let mut store = TransactionsStore::default();
         let client = 1;
 
         // Store multiple deposits
@@ -211,7 +541,7 @@ mod tests {
 
     #[test]
     fn test_overwrite_deposit_should_fail() {
-        let mut store = TransactionsStore::new();
+        let mut store = TransactionsStore::default();
         let tx = 1;
         let client = 1;
 
@@ -229,19 +559,140 @@ mod tests {
 
     #[test]
     fn test_processed_and_stored_independence() {
-        let mut store = TransactionsStore::new();
+        let mut store = TransactionsStore::default();
         let tx = 1;
         let client = 1;
 
         // Mark as processed without storing
-        store.mark_processed(tx);
-        assert!(store.is_processed(tx));
+        store.mark_processed(client, tx);
+        assert!(store.is_processed(client, tx));
         assert!(store.get_deposit_mut(client, tx).is_err());
 
         // Store without marking as processed
         let tx2 = 2;
         store.store_new_deposit(tx2, client, dec!(100)).unwrap();
-        assert!(!store.is_processed(tx2));
+        assert!(!store.is_processed(client, tx2));
         assert!(store.get_deposit_mut(client, tx2).is_ok());
     }
+
+    #[test]
+    fn test_per_client_namespace() {
+        let mut store =
+            TransactionsStore::with_config(TxNamespace::PerClient, ProcessedIds::HashSet);
+        let tx = 1;
+
+        // The same tx can be used by different clients
+        store.store_new_deposit(tx, 1, dec!(100)).unwrap();
+        store.store_new_deposit(tx, 2, dec!(200)).unwrap();
+        store.mark_processed(1, tx);
+        assert!(store.is_processed(1, tx));
+        assert!(!store.is_processed(2, tx));
+
+        // Lookups only see the client's own deposit
+        assert_eq!(store.get_deposit(1, tx).unwrap().amount, dec!(100));
+        assert_eq!(store.get_deposit(2, tx).unwrap().amount, dec!(200));
+        assert!(matches!(
+            store.get_deposit(3, tx),
+            Err(Error::TransactionNotFound)
+        ));
+
+        // But not twice by the same client
+        let result = store.store_new_deposit(tx, 1, dec!(300));
+        assert!(matches!(result, Err(Error::DuplicateTransaction)));
+    }
+
+    #[test]
+    fn test_mark_rejected() {
+        let mut store = TransactionsStore::default();
+        assert_eq!(store.rejection(1, 1), None);
+
+        store.mark_rejected(1, 1, Error::InsufficientFunds);
+        assert_eq!(store.rejection(1, 1), Some(Error::InsufficientFunds));
+        // Rejected transactions are not processed
+        assert!(!store.is_processed(1, 1));
+
+        store.unmark_rejected(1, 1);
+        assert_eq!(store.rejection(1, 1), None);
+    }
+
+    #[test]
+    fn test_shortfalls() {
+        let mut store = TransactionsStore::default();
+        store.store_new_deposit(1, 1, dec!(100)).unwrap();
+        assert_eq!(store.shortfall(1, 1), dec!(0));
+
+        store.set_shortfall(1, 1, dec!(40));
+        assert_eq!(store.shortfall(1, 1), dec!(40));
+        let shortfalls: Vec<_> = store
+            .shortfalls()
+            .map(|(deposit, shortfall)| (deposit.client, shortfall))
+            .collect();
+        assert_eq!(shortfalls, [(1, dec!(40))]);
+
+        // Zero shortfalls are not stored
+        store.set_shortfall(1, 1, dec!(0));
+        assert_eq!(store.shortfalls().count(), 0);
+    }
+
+    #[test]
+    fn test_open_disputes() {
+        let mut store = TransactionsStore::default();
+        for tx in 1..=3 {
+            store.store_new_deposit(tx, 1, dec!(10)).unwrap();
+        }
+        let opened = |processed| DisputeOpened {
+            processed,
+            timestamp: None,
+        };
+        store.set_dispute_opened(1, 2, Some(opened(5)));
+        store.set_dispute_opened(1, 1, Some(opened(7)));
+        store.set_dispute_opened(1, 3, Some(opened(9)));
+        assert_eq!(store.dispute_opened(1, 1), Some(opened(7)));
+
+        store.set_dispute_opened(1, 3, None);
+        let open: Vec<_> = store
+            .open_disputes()
+            .map(|(tx, _, opened)| (tx, opened.processed))
+            .collect();
+        assert_eq!(open, [(2, 5), (1, 7)]);
+        assert_eq!(store.dispute_opened(1, 3), None);
+    }
+
+    #[test]
+    fn test_bitmap_processed_ids() {
+        let mut store =
+            TransactionsStore::with_config(TxNamespace::PerClient, ProcessedIds::Bitmap);
+        let large = 1 << 28;
+        for tx in [0, 63, 64, 1000, large, TxId::MAX] {
+            assert!(!store.is_processed(1, tx));
+            store.mark_processed(1, tx);
+            store.mark_processed(1, tx);
+            assert!(store.is_processed(1, tx));
+            assert!(!store.is_processed(2, tx));
+        }
+        assert!(!store.is_processed(1, 65));
+        assert_eq!(store.processed_count(), 6);
+
+        store.unmark_processed(1, 64);
+        store.unmark_processed(1, large);
+        store.unmark_processed(2, 1000);
+        assert!(!store.is_processed(1, 64));
+        assert!(!store.is_processed(1, large));
+        assert!(store.is_processed(1, 1000));
+        assert_eq!(store.processed_count(), 4);
+    }
+
+    #[test]
+    fn test_processed_ids_from_str() {
+        assert_eq!("hash-set".parse(), Ok(ProcessedIds::HashSet));
+        assert_eq!("BITMAP".parse(), Ok(ProcessedIds::Bitmap));
+        assert!("btree".parse::<ProcessedIds>().is_err());
+    }
+
+    #[test]
+    fn test_tx_namespace_from_str() {
+        assert_eq!("global".parse(), Ok(TxNamespace::Global));
+        assert_eq!("Per-Client".parse(), Ok(TxNamespace::PerClient));
+        assert!("per-account".parse::<TxNamespace>().is_err());
+    }
 }